@@ -1,2 +1,175 @@
+#![feature(let_else)]
+
+pub mod media;
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    #[cfg(feature = "media-processing")]
+    mod processing {
+        use std::io::Cursor;
+
+        use image::{GenericImageView, ImageOutputFormat, RgbImage};
+        use ogg::{PacketWriteEndInfo, PacketWriter};
+
+        use crate::media::processing::MediaMetadata;
+
+        const OPUS_PRE_SKIP: u16 = 312;
+
+        /// One second of silence followed by one second at full volume
+        fn wav_fixture() -> Vec<u8> {
+            let rate = 8_000u32;
+            let samples: Vec<i16> = (0..rate * 2)
+                .map(|index| match index < rate {
+                    true => 0,
+                    false if index % 2 == 0 => i16::MAX,
+                    false => i16::MIN
+                })
+                .collect();
+
+            let length = samples.len() as u32 * 2;
+            let mut data = Vec::new();
+            data.extend_from_slice(b"RIFF");
+            data.extend_from_slice(&(36 + length).to_le_bytes());
+            data.extend_from_slice(b"WAVEfmt ");
+            data.extend_from_slice(&16u32.to_le_bytes());
+            data.extend_from_slice(&1u16.to_le_bytes());
+            data.extend_from_slice(&1u16.to_le_bytes());
+            data.extend_from_slice(&rate.to_le_bytes());
+            data.extend_from_slice(&(rate * 2).to_le_bytes());
+            data.extend_from_slice(&2u16.to_le_bytes());
+            data.extend_from_slice(&16u16.to_le_bytes());
+            data.extend_from_slice(b"data");
+            data.extend_from_slice(&length.to_le_bytes());
+            samples.iter().for_each(|sample| data.extend_from_slice(&sample.to_le_bytes()));
+            data
+        }
+
+        /// A mono voice note of 101 20ms opus frames, just over two seconds after the pre-skip.
+        /// The first half is silent, so its frames are empty, the frames of the second half carry a payload
+        fn ogg_fixture() -> Vec<u8> {
+            let mut head = b"OpusHead".to_vec();
+            head.push(1);
+            head.push(1);
+            head.extend_from_slice(&OPUS_PRE_SKIP.to_le_bytes());
+            head.extend_from_slice(&48_000u32.to_le_bytes());
+            head.extend_from_slice(&0i16.to_le_bytes());
+            head.push(0);
+
+            let mut tags = b"OpusTags".to_vec();
+            tags.extend_from_slice(&4u32.to_le_bytes());
+            tags.extend_from_slice(b"test");
+            tags.extend_from_slice(&0u32.to_le_bytes());
+
+            let mut writer = PacketWriter::new(Vec::new());
+            writer.write_packet(head, 1, PacketWriteEndInfo::EndPage, 0).unwrap();
+            writer.write_packet(tags, 1, PacketWriteEndInfo::EndPage, 0).unwrap();
+
+            let frames = 101;
+            for frame in 1..=frames {
+                let end = match frame == frames {
+                    true => PacketWriteEndInfo::EndStream,
+                    false => PacketWriteEndInfo::NormalPacket
+                };
+
+                // Fullband celt, 20ms, a single frame
+                let mut packet = vec![0xF8];
+                if frame > frames / 2 {
+                    packet.extend_from_slice(&[0x55; 40]);
+                }
+
+                writer.write_packet(packet, 1, end, OPUS_PRE_SKIP as u64 + frame * 960).unwrap();
+            }
+
+            writer.into_inner()
+        }
+
+        /// A three second 640x360 h264 track
+        fn mp4_fixture() -> Vec<u8> {
+            let config = mp4::Mp4Config {
+                major_brand: "isom".parse().unwrap(),
+                minor_version: 512,
+                compatible_brands: vec!["isom".parse().unwrap(), "mp41".parse().unwrap()],
+                timescale: 1000
+            };
+
+            let mut writer = mp4::Mp4Writer::write_start(Cursor::new(Vec::new()), &config).unwrap();
+            writer.add_track(&mp4::TrackConfig {
+                track_type: mp4::TrackType::Video,
+                timescale: 1000,
+                language: "und".to_owned(),
+                media_conf: mp4::MediaConfig::AvcConfig(mp4::AvcConfig {
+                    width: 640,
+                    height: 360,
+                    seq_param_set: vec![0x67, 0x42, 0xC0, 0x1E],
+                    pic_param_set: vec![0x68, 0xCE, 0x3C, 0x80]
+                })
+            }).unwrap();
+
+            writer.write_sample(1, &mp4::Mp4Sample {
+                start_time: 0,
+                duration: 3000,
+                rendering_offset: 0,
+                is_sync: true,
+                bytes: bytes::Bytes::from(vec![0; 16])
+            }).unwrap();
+
+            writer.write_end().unwrap();
+            writer.into_writer().into_inner()
+        }
+
+        #[test]
+        fn jpeg_gets_a_thumbnail_and_dimensions() {
+            let mut jpeg = Cursor::new(Vec::new());
+            image::DynamicImage::ImageRgb8(RgbImage::new(64, 48))
+                .write_to(&mut jpeg, ImageOutputFormat::Jpeg(90))
+                .unwrap();
+
+            let metadata = MediaMetadata::analyze(jpeg.get_ref(), "image/jpeg");
+            assert_eq!(metadata.width, Some(64));
+            assert_eq!(metadata.height, Some(48));
+
+            let thumbnail = image::load_from_memory(&metadata.thumbnail.unwrap()).unwrap();
+            assert_eq!(thumbnail.dimensions(), (32, 24));
+        }
+
+        #[test]
+        fn ogg_duration_skips_the_pre_skip() {
+            let metadata = MediaMetadata::analyze(&ogg_fixture(), "audio/ogg; codecs=opus");
+            assert_eq!(metadata.seconds, Some(2));
+        }
+
+        #[test]
+        fn ogg_waveform_follows_the_packet_sizes() {
+            let waveform = MediaMetadata::analyze(&ogg_fixture(), "audio/ogg; codecs=opus").waveform.unwrap();
+            assert_eq!(waveform.len(), 64);
+            assert_eq!(waveform.first(), Some(&0));
+            assert_eq!(waveform.last(), Some(&100));
+        }
+
+        #[test]
+        fn waveform_follows_the_decoded_amplitude() {
+            let metadata = MediaMetadata::analyze(&wav_fixture(), "audio/wav");
+            assert_eq!(metadata.seconds, Some(2));
+
+            let waveform = metadata.waveform.unwrap();
+            assert_eq!(waveform.len(), 64);
+            assert_eq!(waveform.first(), Some(&0));
+            assert_eq!(waveform.last(), Some(&100));
+        }
+
+        #[test]
+        fn mp4_gets_dimensions_and_duration() {
+            let metadata = MediaMetadata::analyze(&mp4_fixture(), "video/mp4");
+            assert_eq!(metadata.width, Some(640));
+            assert_eq!(metadata.height, Some(360));
+            assert_eq!(metadata.seconds, Some(3));
+        }
+
+        #[test]
+        fn unknown_files_yield_nothing() {
+            let metadata = MediaMetadata::analyze(b"not a pdf", "application/pdf");
+            assert_eq!(metadata.page_count, None);
+            assert_eq!(metadata.thumbnail, None);
+        }
+    }
+}
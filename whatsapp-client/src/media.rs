@@ -0,0 +1,300 @@
+use std::future::Future;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use hyper::body::Bytes;
+use hyper::Body;
+use tokio::io::{AsyncRead, AsyncWriteExt};
+use whatsapp_rs_http::client::download::BodyReader;
+use whatsapp_rs_http::client::upload::{stream_body, MediaUpload};
+use whatsapp_rs_util::model::media_connection::MediaConnection;
+use whatsapp_rs_util::model::message_info::MessageInfo;
+use whatsapp_rs_util::protobuf::media_message::DownloadableMessage;
+use whatsapp_rs_util::protobuf::whatsapp::media_retry_notification::MediaRetryNotificationResultType;
+use whatsapp_rs_util::protobuf::whatsapp::{AudioMessage, DocumentMessage, ImageMessage, VideoMessage};
+use whatsapp_rs_util::security::media::stream::{self, MediaInfo, MediaReader};
+use whatsapp_rs_util::security::media::{self, MediaType};
+use whatsapp_rs_util::util::error::Error;
+use whatsapp_rs_util::util::id;
+use whatsapp_rs_websocket::client::handle::Handle;
+
+#[cfg(feature = "media-processing")]
+pub mod processing;
+
+#[cfg(feature = "media-processing")]
+use processing::MediaMetadata;
+
+/// Everything a media message needs to reference an uploaded file
+#[derive(Clone, Debug)]
+pub struct UploadedFile {
+    pub url: String,
+    pub direct_path: String,
+    pub media_key: Vec<u8>,
+    pub file_sha256: Vec<u8>,
+    pub file_enc_sha256: Vec<u8>,
+    pub file_length: u64,
+    pub media_key_timestamp: i64,
+    pub streaming_sidecar: Option<Vec<u8>>,
+}
+
+/// Encrypts, uploads, downloads and decrypts media, sharing the media connection of the handle
+pub struct MediaClient {
+    handle: Handle,
+}
+
+impl MediaClient {
+    pub fn new(handle: Handle) -> Self {
+        Self { handle }
+    }
+
+    pub async fn connection(&self) -> Result<MediaConnection> {
+        self.handle.media_connection().await
+    }
+
+    /// Uploads the file to the first host that accepts it
+    pub async fn upload(&self, data: &[u8], media_type: MediaType) -> Result<UploadedFile> {
+        let encrypted = media::encrypt(data, media_type);
+        let data = Bytes::from(encrypted.data);
+
+        self.upload_encrypted(media_type, encrypted.info, || std::future::ready(Ok(Body::from(data.clone())))).await
+    }
+
+    /// Uploads files of any size, the url needs the hash of the encrypted file so it's encrypted into a temporary file first
+    pub async fn upload_stream<R: AsyncRead + Unpin>(&self, reader: &mut R, media_type: MediaType) -> Result<UploadedFile> {
+        let path = std::env::temp_dir().join(format!("whatsapp-rs-{}.enc", id::message_id()));
+        let result = self.upload_file(reader, &path, media_type).await;
+
+        // The file might not even exist if the encryption failed
+        let _ = tokio::fs::remove_file(&path).await;
+        result
+    }
+
+    pub async fn upload_image(&self, data: &[u8], mimetype: &str, caption: Option<String>) -> Result<ImageMessage> {
+        let message = self.upload(data, MediaType::Image).await?.image_message(mimetype, caption);
+
+        #[cfg(feature = "media-processing")]
+        let message = MediaMetadata::analyze(data, mimetype).fill_image(message);
+
+        Ok(message)
+    }
+
+    pub async fn upload_video(&self, data: &[u8], mimetype: &str, caption: Option<String>) -> Result<VideoMessage> {
+        let message = self.upload(data, MediaType::Video).await?.video_message(mimetype, caption);
+
+        #[cfg(feature = "media-processing")]
+        let message = MediaMetadata::analyze(data, mimetype).fill_video(message);
+
+        Ok(message)
+    }
+
+    pub async fn upload_audio(&self, data: &[u8], mimetype: &str, voice_note: bool) -> Result<AudioMessage> {
+        let message = self.upload(data, MediaType::Audio).await?.audio_message(mimetype, voice_note);
+
+        #[cfg(feature = "media-processing")]
+        let message = MediaMetadata::analyze(data, mimetype).fill_audio(message);
+
+        Ok(message)
+    }
+
+    pub async fn upload_document(&self, data: &[u8], mimetype: &str, file_name: &str) -> Result<DocumentMessage> {
+        let message = self.upload(data, MediaType::Document).await?.document_message(mimetype, file_name);
+
+        #[cfg(feature = "media-processing")]
+        let message = MediaMetadata::analyze(data, mimetype).fill_document(message);
+
+        Ok(message)
+    }
+
+    /// Downloads the media file of the message, which is decrypted and verified as it's read.
+    /// The last read fails if the file doesn't match the message, see [MediaReader]
+    pub async fn download_media<M: DownloadableMessage>(&self, message: &M) -> Result<MediaReader<BodyReader>> {
+        self.handle.download_media(message).await
+    }
+
+    /// Downloads the media file of the message into the file at the path, which only ever holds the verified file.
+    /// The file is decrypted next to it first and only moved into place once it matched the message
+    pub async fn download_media_to_file<M: DownloadableMessage>(&self, message: &M, path: &Path) -> Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".part");
+
+        let result = self.download_unverified(message, Path::new(&partial)).await;
+        if result.is_err() {
+            // Nothing might have been written yet
+            let _ = tokio::fs::remove_file(&partial).await;
+            return result
+        }
+
+        tokio::fs::rename(&partial, path).await?;
+        Ok(())
+    }
+
+    /// Like [Self::download_media], but asks the phone to upload expired files again
+    ///
+    /// The answer arrives as media retry event and is applied with [Self::apply_media_retry].
+    pub async fn download_message_media<M: DownloadableMessage>(&self, info: &MessageInfo, message: &M) -> Result<MediaReader<BodyReader>> {
+        let result = self.download_media(message).await;
+        if let Err(error) = &result {
+            if matches!(error.downcast_ref::<Error>(), Some(Error::MediaExpired)) {
+                if let Some(media_key) = message.media_key() {
+                    self.handle.request_media_retry(info, media_key)?;
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Points the message to the file the phone uploaded again, the result is taken from the media retry event
+    pub fn apply_media_retry<M: DownloadableMessage>(
+        &self,
+        message: &mut M,
+        id: &str,
+        result: &std::result::Result<(Vec<u8>, Vec<u8>), u32>
+    ) -> Result<()> {
+        let (ciphertext, iv) = match result {
+            Ok(encrypted) => encrypted,
+            Err(code) => bail!("The phone could not upload the media again: {}", code)
+        };
+
+        let media_key = message.media_key().ok_or(Error::MediaIntegrity("missing media key"))?;
+        let notification = media::decrypt_retry_notification(media_key, id, ciphertext, iv)?;
+        if notification.result() != MediaRetryNotificationResultType::SUCCESS {
+            bail!("The phone could not upload the media again: {:?}", notification.result())
+        }
+
+        message.set_direct_path(notification.directPath().to_owned());
+        Ok(())
+    }
+
+    async fn upload_file<R: AsyncRead + Unpin>(&self, reader: &mut R, path: &Path, media_type: MediaType) -> Result<UploadedFile> {
+        let mut file = tokio::fs::File::create(path).await?;
+        let info = stream::encrypt(reader, &mut file, media_type).await?;
+        drop(file);
+
+        self.upload_encrypted(media_type, info, || async move {
+            Ok(stream_body(tokio::fs::File::open(path).await?))
+        }).await
+    }
+
+    /// Every host gets its own body, as a failed upload already consumed the previous one
+    async fn upload_encrypted<F, B>(&self, media_type: MediaType, info: MediaInfo, body: F) -> Result<UploadedFile>
+    where
+        F: Fn() -> B,
+        B: Future<Output = Result<Body>>,
+    {
+        let connection = self.connection().await?;
+
+        let mut last_error = None;
+        for host in &connection.hosts {
+            let upload = MediaUpload {
+                host,
+                path: media_type.path(),
+                auth: &connection.auth,
+                file_enc_sha256: info.file_enc_sha256,
+                body: body().await?,
+            };
+
+            match self.handle.http().upload(upload).await {
+                Ok(uploaded) => return Ok(UploadedFile {
+                    url: uploaded.url,
+                    direct_path: uploaded.direct_path,
+                    media_key: info.media_key.to_vec(),
+                    file_sha256: info.file_sha256.to_vec(),
+                    file_enc_sha256: info.file_enc_sha256.to_vec(),
+                    file_length: info.file_length,
+                    media_key_timestamp: now(),
+                    streaming_sidecar: info.streaming_sidecar,
+                }),
+
+                Err(error) => last_error = error.into()
+            }
+        }
+
+        // The auth token might have been revoked early, so the next upload starts with a new one
+        self.handle.forget_media_connection();
+        match last_error {
+            Some(error) => Err(error),
+            None => bail!("The media connection has no hosts")
+        }
+    }
+
+    /// The file is written as it's decrypted, so it has to be discarded unless this succeeds
+    async fn download_unverified<M: DownloadableMessage>(&self, message: &M, path: &Path) -> Result<()> {
+        let mut reader = self.download_media(message).await?;
+        let mut file = tokio::fs::File::create(path).await?;
+        tokio::io::copy(&mut reader, &mut file).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+impl UploadedFile {
+    pub fn image_message(self, mimetype: &str, caption: Option<String>) -> ImageMessage {
+        ImageMessage {
+            url: self.url.into(),
+            mimetype: mimetype.to_owned().into(),
+            caption,
+            fileSha256: self.file_sha256.into(),
+            fileLength: self.file_length.into(),
+            mediaKey: self.media_key.into(),
+            fileEncSha256: self.file_enc_sha256.into(),
+            directPath: self.direct_path.into(),
+            mediaKeyTimestamp: self.media_key_timestamp.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn video_message(self, mimetype: &str, caption: Option<String>) -> VideoMessage {
+        VideoMessage {
+            url: self.url.into(),
+            mimetype: mimetype.to_owned().into(),
+            caption,
+            fileSha256: self.file_sha256.into(),
+            fileLength: self.file_length.into(),
+            mediaKey: self.media_key.into(),
+            fileEncSha256: self.file_enc_sha256.into(),
+            directPath: self.direct_path.into(),
+            mediaKeyTimestamp: self.media_key_timestamp.into(),
+            streamingSidecar: self.streaming_sidecar,
+            ..Default::default()
+        }
+    }
+
+    /// Voice notes are audio messages marked as push to talk
+    pub fn audio_message(self, mimetype: &str, voice_note: bool) -> AudioMessage {
+        AudioMessage {
+            url: self.url.into(),
+            mimetype: mimetype.to_owned().into(),
+            fileSha256: self.file_sha256.into(),
+            fileLength: self.file_length.into(),
+            ptt: voice_note.into(),
+            mediaKey: self.media_key.into(),
+            fileEncSha256: self.file_enc_sha256.into(),
+            directPath: self.direct_path.into(),
+            mediaKeyTimestamp: self.media_key_timestamp.into(),
+            streamingSidecar: self.streaming_sidecar,
+            ..Default::default()
+        }
+    }
+
+    pub fn document_message(self, mimetype: &str, file_name: &str) -> DocumentMessage {
+        DocumentMessage {
+            url: self.url.into(),
+            mimetype: mimetype.to_owned().into(),
+            title: file_name.to_owned().into(),
+            fileSha256: self.file_sha256.into(),
+            fileLength: self.file_length.into(),
+            mediaKey: self.media_key.into(),
+            fileName: file_name.to_owned().into(),
+            fileEncSha256: self.file_enc_sha256.into(),
+            directPath: self.direct_path.into(),
+            mediaKeyTimestamp: self.media_key_timestamp.into(),
+            ..Default::default()
+        }
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
@@ -0,0 +1,200 @@
+use std::io::Cursor;
+
+use image::imageops::FilterType;
+use image::{GenericImageView, ImageOutputFormat};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+use whatsapp_rs_util::protobuf::whatsapp::{AudioMessage, DocumentMessage, ImageMessage, VideoMessage};
+
+/// The width of the thumbnails the official clients send inline
+const THUMBNAIL_WIDTH: u32 = 32;
+const THUMBNAIL_QUALITY: u8 = 50;
+
+/// Voice notes are drawn from this many bars, each between 0 and 100
+const WAVEFORM_SAMPLES: usize = 64;
+
+/// Everything recipients need to preview a file before they download it
+#[derive(Clone, Debug, Default)]
+pub struct MediaMetadata {
+    pub thumbnail: Option<Vec<u8>>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub seconds: Option<u32>,
+    pub waveform: Option<Vec<u8>>,
+    pub page_count: Option<u32>,
+}
+
+impl MediaMetadata {
+    /// Collects whatever can be read from the file, unsupported formats simply yield nothing
+    pub fn analyze(data: &[u8], mimetype: &str) -> Self {
+        let mimetype = mimetype.split(';').next().unwrap_or_default().trim();
+
+        match mimetype {
+            "image/jpeg" | "image/png" | "image/gif" | "image/webp" => image_metadata(data),
+            audio if audio.starts_with("audio/") => audio_metadata(data, audio),
+            "video/mp4" => video_metadata(data),
+            "application/pdf" => Self {
+                page_count: pdf_page_count(data),
+                ..Default::default()
+            },
+            _ => Self::default()
+        }
+    }
+
+    /// Fields we couldn't determine keep whatever the message already had
+    pub fn fill_image(&self, mut message: ImageMessage) -> ImageMessage {
+        message.jpegThumbnail = self.thumbnail.clone().or_else(|| message.jpegThumbnail.take());
+        message.width = self.width.or(message.width);
+        message.height = self.height.or(message.height);
+        message
+    }
+
+    pub fn fill_video(&self, mut message: VideoMessage) -> VideoMessage {
+        message.jpegThumbnail = self.thumbnail.clone().or_else(|| message.jpegThumbnail.take());
+        message.width = self.width.or(message.width);
+        message.height = self.height.or(message.height);
+        message.seconds = self.seconds.or(message.seconds);
+        message
+    }
+
+    pub fn fill_audio(&self, mut message: AudioMessage) -> AudioMessage {
+        message.seconds = self.seconds.or(message.seconds);
+        message.waveform = self.waveform.clone().or_else(|| message.waveform.take());
+        message
+    }
+
+    pub fn fill_document(&self, mut message: DocumentMessage) -> DocumentMessage {
+        message.pageCount = self.page_count.or(message.pageCount);
+        message
+    }
+}
+
+fn image_metadata(data: &[u8]) -> MediaMetadata {
+    let Ok(image) = image::load_from_memory(data) else {
+        return MediaMetadata::default()
+    };
+
+    let (width, height) = image.dimensions();
+    let thumbnail_height = (height as u64 * THUMBNAIL_WIDTH as u64 / width.max(1) as u64).max(1) as u32;
+    let thumbnail = image.resize(THUMBNAIL_WIDTH, thumbnail_height, FilterType::Triangle);
+
+    let mut output = Cursor::new(Vec::new());
+    let thumbnail = thumbnail.write_to(&mut output, ImageOutputFormat::Jpeg(THUMBNAIL_QUALITY))
+        .ok()
+        .map(|_| output.into_inner());
+
+    MediaMetadata {
+        thumbnail,
+        width: width.into(),
+        height: height.into(),
+        ..Default::default()
+    }
+}
+
+/// Decodes the whole track, so the waveform follows what recipients will actually hear
+fn audio_metadata(data: &[u8], mimetype: &str) -> MediaMetadata {
+    let mut hint = Hint::new();
+    hint.mime_type(mimetype);
+
+    let source = MediaSourceStream::new(Box::new(Cursor::new(data.to_vec())), Default::default());
+    let Ok(probed) = symphonia::default::get_probe().format(&hint, source, &FormatOptions::default(), &MetadataOptions::default()) else {
+        return MediaMetadata::default()
+    };
+
+    let mut format = probed.format;
+    let Some(track) = format.default_track() else {
+        return MediaMetadata::default()
+    };
+
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+
+    // Opus has no pure rust decoder, but its packets grow with what there is to hear, so their size stands in for the loudness
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&params, &DecoderOptions::default())
+        .ok();
+
+    let mut frames = 0;
+    let mut loudness = Vec::new();
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue
+        }
+
+        frames += packet.dur;
+        let Some(decoder) = decoder.as_mut() else {
+            // The first byte only describes the frames of the packet
+            loudness.push(packet.buf().len().saturating_sub(1) as f32);
+            continue
+        };
+
+        let Ok(decoded) = decoder.decode(&packet) else {
+            continue
+        };
+
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        samples.copy_interleaved_ref(decoded);
+
+        let samples = samples.samples();
+        if !samples.is_empty() {
+            loudness.push(samples.iter().map(|sample| sample.abs()).sum::<f32>() / samples.len() as f32);
+        }
+    }
+
+    let frames = params.n_frames.unwrap_or(frames).saturating_sub(params.delay.unwrap_or_default() as u64);
+    let seconds = params.time_base
+        .or_else(|| params.sample_rate.map(|rate| TimeBase::new(1, rate)))
+        .map(|time_base| time_base.calc_time(frames).seconds.max(1));
+
+    MediaMetadata {
+        seconds: seconds.and_then(|seconds| seconds.try_into().ok()),
+        waveform: (!loudness.is_empty()).then(|| waveform(&loudness)),
+        ..Default::default()
+    }
+}
+
+/// Averages the loudness of each packet into the bars, scaled so the loudest one is full height
+fn waveform(loudness: &[f32]) -> Vec<u8> {
+    let buckets: Vec<f32> = (0..WAVEFORM_SAMPLES)
+        .map(|bucket| {
+            let start = bucket * loudness.len() / WAVEFORM_SAMPLES;
+            let end = ((bucket + 1) * loudness.len() / WAVEFORM_SAMPLES).max(start + 1);
+            loudness[start..end].iter().sum::<f32>() / (end - start) as f32
+        })
+        .collect();
+
+    let loudest = buckets.iter().copied().fold(0.0, f32::max);
+    if loudest <= 0.0 {
+        return vec![0; WAVEFORM_SAMPLES]
+    }
+
+    buckets.iter().map(|bucket| (bucket / loudest * 100.0).round() as u8).collect()
+}
+
+fn video_metadata(data: &[u8]) -> MediaMetadata {
+    let Ok(video) = mp4::Mp4Reader::read_header(Cursor::new(data), data.len() as u64) else {
+        return MediaMetadata::default()
+    };
+
+    let track = video.tracks().values()
+        .find(|track| matches!(track.track_type(), Ok(mp4::TrackType::Video)));
+
+    MediaMetadata {
+        width: track.map(|track| track.width() as u32),
+        height: track.map(|track| track.height() as u32),
+        seconds: video.duration().as_secs().try_into().ok(),
+        ..Default::default()
+    }
+}
+
+/// Walks the page tree, so compressed object streams are counted as well
+fn pdf_page_count(data: &[u8]) -> Option<u32> {
+    let document = lopdf::Document::load_mem(data).ok()?;
+    let count = document.get_pages().len();
+    (count > 0).then(|| count as u32)
+}
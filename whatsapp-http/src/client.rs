@@ -1,4 +1,6 @@
+pub mod download;
 pub mod request;
+pub mod upload;
 pub mod version;
 
 use crate::client::request::RequestModel;
@@ -7,6 +9,8 @@ use hyper::client::HttpConnector;
 use hyper::{Body, Client as Http, Uri};
 use hyper_tls::HttpsConnector;
 
+/// Cheap to clone, every clone shares the same connection pool
+#[derive(Clone)]
 pub struct Client {
     http: Http<HttpsConnector<HttpConnector>, Body>,
 }
@@ -0,0 +1,83 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::{anyhow, bail, Result};
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::ORIGIN;
+use hyper::{Body, Request, Uri};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use whatsapp_rs_util::util::error::Error;
+
+use crate::client::Client;
+
+/// Reads the body of a response as it arrives
+pub struct BodyReader {
+    body: Body,
+    chunk: Bytes,
+}
+
+impl Client {
+    /// Downloads the encrypted media file, failed requests surface their http status
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        let body = self.download_reader(url).await?.body;
+        Ok(hyper::body::to_bytes(body).await?.to_vec())
+    }
+
+    /// Opens the first url that answers, a file every url is missing for has expired on the server
+    pub async fn download_any(&self, urls: &[String]) -> Result<BodyReader> {
+        let mut expired = false;
+        let mut last_error = None;
+        for url in urls {
+            match self.download_reader(url).await {
+                Ok(reader) => return Ok(reader),
+                Err(error) => {
+                    expired |= matches!(error.downcast_ref::<Error>(), Some(Error::HttpStatus(404 | 410)));
+                    last_error = error.into();
+                }
+            }
+        }
+
+        if expired {
+            bail!(Error::MediaExpired)
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("There is no url to download the file from")))
+    }
+
+    /// Like [Client::download], but the file is read as it arrives
+    pub async fn download_reader(&self, url: &str) -> Result<BodyReader> {
+        let request = Request::get(url.parse::<Uri>()?)
+            .header(ORIGIN, "https://web.whatsapp.com")
+            .body(Body::empty())?;
+
+        let response = self.http.request(request).await?;
+        if !response.status().is_success() {
+            bail!(Error::HttpStatus(response.status().as_u16()))
+        }
+
+        Ok(BodyReader {
+            body: response.into_body(),
+            chunk: Bytes::new(),
+        })
+    }
+}
+
+impl AsyncRead for BodyReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        while self.chunk.is_empty() {
+            match Pin::new(&mut self.body).poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.chunk = chunk,
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, error))),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending
+            }
+        }
+
+        let length = self.chunk.len().min(buf.remaining());
+        let chunk = self.chunk.split_to(length);
+        buf.put_slice(&chunk);
+        Poll::Ready(Ok(()))
+    }
+}
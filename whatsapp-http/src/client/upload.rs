@@ -0,0 +1,99 @@
+use anyhow::{bail, Result};
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_TYPE, ORIGIN};
+use hyper::{Body, Request};
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use whatsapp_rs_util::security::base64;
+use whatsapp_rs_util::util::error::Error;
+
+use crate::client::Client;
+
+/// An encrypted media file and where it should be uploaded to
+pub struct MediaUpload<'a> {
+    pub host: &'a str,
+    pub path: &'a str,
+    pub auth: &'a str,
+    pub file_enc_sha256: [u8; 32],
+    pub body: Body,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct UploadedMedia {
+    pub url: String,
+    pub direct_path: String,
+}
+
+impl MediaUpload<'_> {
+    /// The hash of the encrypted file doubles as upload token
+    pub fn uri(&self) -> String {
+        let token = base64::encode_config(self.file_enc_sha256, base64::URL_SAFE_NO_PAD);
+        format!(
+            "https://{}/mms/{}/{}?auth={}&token={}",
+            self.host,
+            self.path,
+            token,
+            encode_query(self.auth),
+            token
+        )
+    }
+}
+
+impl Client {
+    pub async fn upload(&self, upload: MediaUpload<'_>) -> Result<UploadedMedia> {
+        let request = Request::post(upload.uri())
+            .header(ORIGIN, "https://web.whatsapp.com")
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(upload.body)?;
+
+        let response = self.http.request(request).await?;
+        if !response.status().is_success() {
+            bail!(Error::HttpStatus(response.status().as_u16()))
+        }
+
+        let bytes = hyper::body::to_bytes(response.into_body()).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+/// Streams the reader as request body, so large files never have to be held in memory
+pub fn stream_body<R>(mut reader: R) -> Body
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    let (mut sender, body) = Body::channel();
+
+    tokio::spawn(async move {
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            match reader.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(read) => {
+                    // The request was dropped, nobody needs the rest of the file
+                    if sender.send_data(Bytes::copy_from_slice(&buffer[..read])).await.is_err() {
+                        break
+                    }
+                },
+                Err(_) => {
+                    sender.abort();
+                    break
+                }
+            }
+        }
+    });
+
+    body
+}
+
+/// The auth token is base64, so only its special characters have to be escaped
+fn encode_query(input: &str) -> String {
+    input.chars()
+        .map(|character| match character {
+            '+' => "%2B".to_owned(),
+            '/' => "%2F".to_owned(),
+            '=' => "%3D".to_owned(),
+            character => character.to_string()
+        })
+        .collect()
+}
@@ -108,12 +108,27 @@ impl NodeEncoder {
 			}
 		}
 
+		// Jids are written in their own format, so the server can tell them apart from plain strings
+		if let Some(jid) = Self::parse_jid(input) {
+			return self.write_jid(jid);
+		}
+
 		self.write_i64(length as i64);
 		self.buffer.write_bytes(input.as_bytes());
 
 		Ok(())
 	}
 
+	fn parse_jid(input: &str) -> Option<ContactJid> {
+		let (_, address) = input.split_once('@')?;
+		let server = Server::of(address)?;
+		if server.address() != address {
+			return None;
+		}
+
+		ContactJid::from_complex(input.to_owned(), server).ok()
+	}
+
 	fn write_i64(&mut self, length: i64) {
 		match length {
 			0..=255 => {
@@ -136,7 +151,6 @@ impl NodeEncoder {
 		self.buffer.write_u8(length as u8);
 	}
 
-	#[allow(unused)]
 	fn write_jid(&mut self, jid: ContactJid) -> Result<()> {
 		if jid.is_companion() {
 			self.buffer.write_u8(tag::COMPANION_JID as u8);
@@ -173,7 +187,11 @@ impl NodeEncoder {
 				self.write_list(serde_json::from_value(Value::Array(input))?)?
 			},
 			Value::Object(value) => {
-				self.write_attributes(value)?
+				// Decoded jids are kept as objects, so they can be sent back as they are
+				match serde_json::from_value::<ContactJid>(Value::Object(value.clone())) {
+					Ok(jid) => self.write_jid(jid)?,
+					Err(_) => self.write_attributes(value)?
+				}
 			},
 		})
 	}
@@ -94,7 +94,7 @@ impl Handshake {
     }
 
     // TODO: Make dis thing lil bit less hardcoded lol
-    pub fn create_user_payload(Session { credentials, store } : &Session) -> Result<ClientPayload> {
+    pub fn create_user_payload(Session { credentials, store, .. } : &Session) -> Result<ClientPayload> {
         let mut user_agent = UserAgent::new();
         let mut app_version = AppVersion::new();
         app_version.primary = 2.into();
@@ -130,7 +130,7 @@ impl Handshake {
             .into();
 
         // signatureId
-        reg_data.eSkeyId = Self::int_to_bytes(credentials.registration_id as i32, 3).into();
+        reg_data.eSkeyId = Self::int_to_bytes(credentials.signed_keypair.key_id, 3).into();
 
         // signaturePublicKey
         reg_data.eSkeyVal = credentials
@@ -29,6 +29,14 @@ pub trait DataExt : Debug {
 
     fn content_array_nums(&self) -> Option<Vec<u8>>;
 
+    /// Binary content is decoded as string whenever it happens to be valid utf-8
+    fn content_bytes(&self) -> Option<Vec<u8>> {
+        match self.content_as_value() {
+            Value::String(content) => Some(content.as_bytes().to_vec()),
+            _ => self.content_array_nums()
+        }
+    }
+
     fn attribute(&self, key: &str) -> Option<&Value>;
 
     fn error_code(&self) -> Option<u32> {
@@ -63,6 +71,26 @@ impl Node {
             content: Value::Null,
         }
     }
+
+    pub fn with_children(
+        description: String,
+        attributes: HashMap<String, Value>,
+        children: Vec<Node>
+    ) -> Self {
+        Self {
+            description,
+            attributes,
+            content: Value::Array(children.into_iter().filter_map(Node::serialize).collect()),
+        }
+    }
+
+    pub fn with_bytes<T: AsRef<[u8]>>(description: String, bytes: T) -> Self {
+        Self {
+            description,
+            attributes: HashMap::new(),
+            content: Value::from(bytes.as_ref().to_vec()),
+        }
+    }
     
     pub fn children(&self) -> Vec<&Value> {
         self.into_iter().collect()
@@ -72,6 +100,32 @@ impl Node {
         self.attributes.clone()
     }
 
+    pub fn set_attribute<V: Into<Value>>(&mut self, key: &str, value: V) {
+        self.attributes.insert(key.to_owned(), value.into());
+    }
+
+    pub fn attribute_str(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key)?.as_str()
+    }
+
+    /// Jids are either decoded objects or plain strings when we created the node ourselves
+    pub fn attribute_jid(&self, key: &str) -> Option<ContactJid> {
+        match self.attributes.get(key)? {
+            Value::String(jid) => jid.parse().ok(),
+            jid => serde_json::from_value(jid.clone()).ok()
+        }
+    }
+
+    pub fn nodes(&self) -> Vec<Node> {
+        self.content.as_array()
+            .map(|children| children.iter().filter_map(|child| Node::deserialize(child.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn find_node(&self, description: &str) -> Option<Node> {
+        self.nodes().into_iter().find(|node| node.description() == description)
+    }
+
     pub fn find_description(&self, description: &str) -> Option<&Value> {
         for item in self.into_iter() {
             match item {
@@ -92,12 +146,12 @@ impl Node {
         None
     }
 
-    pub fn parse_jid(jid: &Value) -> ContactJid {
-        let user = jid["user"].as_str().unwrap().to_string();
-        let device = jid["device"].as_u64().unwrap() as u32;
-        let agent = jid["agent"].as_u64().unwrap() as u32;
+    pub fn parse_jid(jid: &Value) -> Option<ContactJid> {
+        let user = jid["user"].as_str()?.to_string();
+        let device = jid["device"].as_u64()? as u32;
+        let agent = jid["agent"].as_u64()? as u32;
 
-        ContactJid::from_companion(user, device, agent)
+        Some(ContactJid::from_companion(user, device, agent))
     }
 
     pub fn deserialize(node: Value) -> Option<Self> {
@@ -138,9 +192,7 @@ impl DataExt for Node {
 
     fn content_array_nums(&self) -> Option<Vec<u8>> {
         match &self.content {
-            Value::Array(values) => {
-                Some(values.into_iter().map(|value| value.as_u64().unwrap() as u8).collect())
-            },
+            Value::Array(values) => byte_array(values),
             _ => None
         }
     }
@@ -156,12 +208,13 @@ impl DataExt for Value {
     }
 
     fn size(&self) -> usize {
-        2 * self["attributes"].as_object().unwrap().len()
+        // Values without attributes are encoded like nodes without any
+        2 * self["attributes"].as_object().map_or(0, |attributes| attributes.len())
             + !self["content"].is_null() as usize + 1
     }
 
     fn id(&self) -> Option<&str> {
-        self["attributes"].as_object().unwrap()["id"].as_str()
+        self["attributes"].get("id")?.as_str()
     }
 
     fn content<'a, T>(&'a self) -> Option<T> where &'a str: TryInto<T> {
@@ -177,9 +230,7 @@ impl DataExt for Value {
     }
 
     fn content_array_nums(&self) -> Option<Vec<u8>> {
-        self["content"].as_array().map(|val| {
-            val.into_iter().map(|v| v.as_u64().unwrap() as u8).collect()
-        })
+        byte_array(self["content"].as_array()?)
     }
 
     fn attribute(&self, key: &str) -> Option<&Value> {
@@ -187,6 +238,13 @@ impl DataExt for Value {
     }
 }
 
+/// Arrays with anything but bytes aren't binary content
+fn byte_array(values: &[Value]) -> Option<Vec<u8>> {
+    values.iter()
+        .map(|value| value.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+        .collect()
+}
+
 impl TryFrom<Value> for Node {
     type Error = anyhow::Error;
 
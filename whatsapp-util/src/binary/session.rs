@@ -2,6 +2,8 @@ use crate::binary::codec;
 use crate::binary::codec::{CodecInput, NodeCodec, TransposeOutput};
 use crate::binary::node::Node;
 use crate::model::Credentials;
+use crate::model::app_state::{AppStateStore, SharedAppStateStore};
+use crate::security::signal::{SharedSignalStore, SignalStore};
 
 pub use crate::Result;
 pub use crate::model::session_store::SessionStore;
@@ -10,18 +12,37 @@ pub use crate::model::session_store::SessionStore;
 pub struct Session {
     pub store: SessionStore,
     pub credentials: Credentials,
+    pub signal: SharedSignalStore,
+    pub app_state: SharedAppStateStore,
 }
 
 impl Default for Session {
     fn default() -> Self {
+        let credentials = Credentials::default();
+        let signal = SignalStore::new(&credentials)
+            .expect("Freshly generated credentials are always valid");
+
         Self {
             store: SessionStore::default(),
-            credentials: Credentials::default(),
+            signal: SharedSignalStore::new(signal),
+            app_state: SharedAppStateStore::default(),
+            credentials,
         }
     }
 }
 
 impl Session {
+    /// Replaces everything with fresh credentials, the shared stores are reset in place
+    pub fn reset(&mut self) -> Result<()> {
+        let credentials = Credentials::default();
+        *self.signal.lock() = SignalStore::new(&credentials)?;
+        *self.app_state.lock() = AppStateStore::default();
+
+        self.store = SessionStore::default();
+        self.credentials = credentials;
+        Ok(())
+    }
+
     pub fn encode(&mut self, intro: bool, node: Node) -> Result<Vec<u8>> {
         let TransposeOutput::Encoded(encoded) = NodeCodec::transpose(
             &mut self.store,
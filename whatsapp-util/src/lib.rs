@@ -10,10 +10,23 @@ pub use anyhow::Result;
 
 #[cfg(test)]
 mod tests {
+	use std::collections::HashMap;
 	use serde_json::Value;
 	use crate::binary::codec::{NodeDecoder, NodeEncoder};
 	use crate::binary::node::Node;
 
+	fn node(description: &str, attributes: &[(&str, &str)], children: Vec<Node>) -> Node {
+		let attributes: HashMap<String, Value> = attributes.iter()
+			.map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+			.collect();
+
+		if children.is_empty() {
+			Node::from_attributes(description.to_owned(), attributes)
+		} else {
+			Node::with_children(description.to_owned(), attributes, children)
+		}
+	}
+
 	#[test]
 	pub fn encode_decode_node() {
 		let node = Node::new(
@@ -62,4 +75,489 @@ mod tests {
 		);
 	}
 
-}
\ No newline at end of file
+	#[test]
+	pub fn jids_are_encoded_as_jids() {
+		use crate::model::ContactJid;
+
+		let attributes = [
+			("type", "text"),
+			("id", "3EB0A1B2C3D4E5F6"),
+			("email", "someone@example.com"),
+			("to", "1234@s.whatsapp.net"),
+			("participant", "1234:5@s.whatsapp.net"),
+			("group", "1234-5678@g.us")
+		];
+
+		let encoded = NodeEncoder::encode(node("message", &attributes, vec![])).unwrap();
+		let decoded = NodeDecoder::decode(encoded.as_slice()).unwrap();
+
+		// Plain strings stay strings, even if they contain an @ of another server
+		assert_eq!(decoded.attribute_str("type"), Some("text"));
+		assert_eq!(decoded.attribute_str("id"), Some("3EB0A1B2C3D4E5F6"));
+		assert_eq!(decoded.attribute_str("email"), Some("someone@example.com"));
+
+		// Jids come back as jids, devices included
+		for key in ["to", "participant", "group"] {
+			let (_, jid) = attributes.iter().find(|(name, _)| *name == key).unwrap();
+			assert!(decoded.attribute_str(key).is_none());
+			assert_eq!(decoded.attribute_jid(key), Some(jid.parse::<ContactJid>().unwrap()));
+		}
+
+		// Decoded jids are sent back as they are
+		let encoded_again = NodeEncoder::encode(decoded.clone()).unwrap();
+		assert_eq!(NodeDecoder::decode(encoded_again.as_slice()).unwrap(), decoded);
+	}
+
+	#[test]
+	pub fn encrypt_decrypt_media() {
+		use crate::security::media::{self, MediaType};
+
+		// Larger than a sidecar chunk and not aligned to the block size
+		let file: Vec<u8> = (0..70_000u32).map(|index| index as u8).collect();
+		let encrypted = media::encrypt(&file, MediaType::Video);
+
+		let decrypted = media::decrypt(
+			&encrypted.data,
+			&encrypted.info.media_key,
+			MediaType::Video,
+			Some(&encrypted.info.file_sha256),
+			Some(&encrypted.info.file_enc_sha256)
+		).unwrap();
+
+		assert_eq!(file, decrypted);
+		assert_eq!(encrypted.info.streaming_sidecar.map(|sidecar| sidecar.len()), Some(20));
+	}
+
+	#[test]
+	pub fn lt_hash_removes_values() {
+		use crate::security::app_state::LtHash;
+
+		let mut hash = LtHash::default();
+		hash.subtract_then_add(&[], &[b"first".to_vec(), b"second".to_vec()]);
+		hash.subtract_then_add(&[b"first".to_vec()], &[]);
+
+		let mut expected = LtHash::default();
+		expected.subtract_then_add(&[], &[b"second".to_vec()]);
+		assert_eq!(hash, expected);
+	}
+
+	/// Computed with an independent implementation of the lt-hash and the macs whatsmeow and WhatsApp Web use
+	#[test]
+	pub fn app_state_known_answers() {
+		use crate::protobuf::whatsapp::syncd_mutation::SyncdMutationSyncdOperation;
+		use crate::security::app_state::{self, AppStateKeys, LtHash};
+
+		let hex = |data: &[u8]| data.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+		let key_data: Vec<u8> = (0..32).collect();
+		let keys = AppStateKeys::derive(&key_data);
+
+		let mut hash = LtHash::default();
+		hash.subtract_then_add(&[], &[b"first".to_vec(), b"second".to_vec()]);
+		assert_eq!(
+			hex(&hash.0),
+			"84d49dfc036c673d1df82d1f68dde32d97fdb1989eb540095b76a4cb48ed7417bbbec14f3216a6824587f96c066467ab055d67f34faf5e6da4f68335d89ce7e3\
+			a153d66918fbca305b635fca97efcab831e31bbf546394d38edc9a77c63e72db84fcf31b563b974d918edf49f1a4e4e6a4822f4042fcdd9503687d5c076cb7a6"
+		);
+
+		let value_mac = app_state::value_mac(&keys, SyncdMutationSyncdOperation::SET, b"content", b"key-id");
+		assert_eq!(hex(&value_mac), "fadee6c77da825ab49b934d93928cfc1ed355f6299a200e38fb7b90f12366e6d");
+
+		let snapshot_mac = app_state::snapshot_mac(&keys, &hash, 3, "regular");
+		assert_eq!(hex(&snapshot_mac), "f8ac8d82f8e56e1842a45021b9c40c474935b7c5618496c509580e2ced1833a2");
+
+		let patch_mac = app_state::patch_mac(&keys, &snapshot_mac, &[value_mac], 3, "regular");
+		assert_eq!(hex(&patch_mac), "e509a5748868c5753446784fbb40bfd6cd1b5703323f62664b4d26a1b2a3926e");
+	}
+
+	#[test]
+	pub fn chat_actions_name_their_messages() {
+		use protobuf::MessageField;
+		use crate::model::ContactJid;
+		use crate::model::app_state::Mutation;
+		use crate::model::chat_action::{ChatAction, LabelTarget, MessageRef};
+		use crate::protobuf::whatsapp::syncd_mutation::SyncdMutationSyncdOperation;
+		use crate::protobuf::whatsapp::{LabelAssociationAction, StarAction, SyncActionValue};
+
+		let mutation = |operation, index: &[&str], action| Mutation {
+			operation,
+			index: index.iter().map(|part| part.to_string()).collect(),
+			action,
+			version: 2,
+			index_mac: Vec::new(),
+			value_mac: Vec::new(),
+		};
+
+		let group: ContactJid = "1234-5678@g.us".parse().unwrap();
+		let sender: ContactJid = "5678@s.whatsapp.net".parse().unwrap();
+		let star = SyncActionValue { starAction: MessageField::some(StarAction { starred: Some(true), ..Default::default() }), ..Default::default() };
+
+		// Outside of groups the participant is "0"
+		let direct = mutation(SyncdMutationSyncdOperation::SET, &["star", "5678@s.whatsapp.net", "ABCD", "1", "0"], star.clone());
+		let Some(ChatAction::Star { message, starred: true }) = ChatAction::from_mutation(&direct) else {
+			panic!("expected a star")
+		};
+
+		assert_eq!(message, MessageRef { chat: sender.clone(), id: "ABCD".to_owned(), from_me: true, participant: None });
+		assert_eq!(message.index(), vec!["5678@s.whatsapp.net", "ABCD", "1", "0"]);
+
+		let labeled = SyncActionValue {
+			labelAssociationAction: MessageField::some(LabelAssociationAction { labeled: Some(true), ..Default::default() }),
+			..Default::default()
+		};
+
+		let in_group = mutation(SyncdMutationSyncdOperation::SET, &["label_message", "3", "1234-5678@g.us", "EFGH", "0", "5678@s.whatsapp.net"], labeled.clone());
+		let Some(ChatAction::LabelAssociation { label, target: LabelTarget::Message(message), labeled: true }) = ChatAction::from_mutation(&in_group) else {
+			panic!("expected a message label")
+		};
+
+		assert_eq!(label, "3");
+		assert_eq!(message, MessageRef { chat: group, id: "EFGH".to_owned(), from_me: false, participant: sender.into() });
+
+		// Removals and indexes that are too short don't name anything
+		let removed = mutation(SyncdMutationSyncdOperation::REMOVE, &["star", "5678@s.whatsapp.net", "ABCD", "1", "0"], star);
+		assert!(ChatAction::from_mutation(&removed).is_none());
+
+		let truncated = mutation(SyncdMutationSyncdOperation::SET, &["label_message", "3", "1234-5678@g.us"], labeled);
+		assert!(ChatAction::from_mutation(&truncated).is_none());
+	}
+
+	#[test]
+	pub fn poll_votes_are_tallied() {
+		use crate::model::ContactJid;
+		use crate::model::poll::Poll;
+		use crate::protobuf::poll_message::PollCreationMessage;
+		use crate::security::poll;
+
+		let creator: ContactJid = "1234@s.whatsapp.net".parse().unwrap();
+		let voter: ContactJid = "5678@s.whatsapp.net".parse().unwrap();
+		let creation = PollCreationMessage {
+			name: "Meeting".to_owned(),
+			options: vec!["Monday".to_owned(), "Tuesday".to_owned()],
+			selectable_count: 1,
+		};
+
+		let mut created = Poll::new("ABCD".to_owned(), creator.clone(), &creator, creation, poll::generate_secret().to_vec());
+		let update = created.encrypt_vote(&voter, &["Tuesday"]).unwrap();
+		assert_eq!(created.apply_vote(&voter, &update).unwrap(), vec!["Tuesday".to_owned()]);
+
+		let tally = created.tally();
+		assert!(tally[0].1.is_empty());
+		assert_eq!(tally[1].1, vec![voter]);
+
+		let restored: Poll = serde_json::from_str(&serde_json::to_string(&created).unwrap()).unwrap();
+		assert_eq!(restored.tally(), tally);
+	}
+
+	#[test]
+	pub fn poll_vote_known_answer() {
+		use crate::model::ContactJid;
+		use crate::protobuf::poll_message::PollVoteMessage;
+		use crate::security::poll;
+
+		let hex = |data: &[u8]| data.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+		let secret: Vec<u8> = (0..32).collect();
+		let iv: Vec<u8> = (0..12).collect();
+		let creator: ContactJid = "1234@s.whatsapp.net".parse().unwrap();
+		let voter: ContactJid = "5678:3@s.whatsapp.net".parse().unwrap();
+		let vote = PollVoteMessage { selected_options: vec![poll::option_hash("Tuesday").to_vec()] }.write_to_bytes().unwrap();
+
+		// The key is derived from and the aad names the voter without their device
+		let encrypted = poll::encrypt_vote(&secret, "3EB0A1B2C3D4E5F6", &creator, &voter, &vote, &iv).unwrap();
+		assert_eq!(
+			hex(&encrypted),
+			"e0e748f414b1da0a3dcddb87b663d04637091c662a618c177e501c317c296dcf9022456f21bc8d67561e9bb6497cbb201a76"
+		);
+
+		assert_eq!(poll::decrypt_vote(&secret, "3EB0A1B2C3D4E5F6", &creator, &voter, &encrypted, &iv).unwrap(), vote);
+		assert!(poll::decrypt_vote(&secret, "3EB0A1B2C3D4E5F7", &creator, &voter, &encrypted, &iv).is_err());
+		assert!(poll::decrypt_vote(&secret, "3EB0A1B2C3D4E5F6", &creator, &creator, &encrypted, &iv).is_err());
+	}
+
+	#[test]
+	pub fn disappearing_timers_are_read_from_the_context() {
+		use protobuf::MessageField;
+		use crate::model::ephemeral::{self, EphemeralSetting, TIMER_7_DAYS};
+		use crate::protobuf::message_wrapper::MessageWrapperExt;
+		use crate::protobuf::whatsapp::{ContextInfo, ImageMessage, Message};
+
+		let image = Message {
+			imageMessage: MessageField::some(ImageMessage {
+				contextInfo: MessageField::some(ContextInfo { expiration: Some(TIMER_7_DAYS), ..Default::default() }),
+				..Default::default()
+			}),
+			..Default::default()
+		};
+
+		let setting = ephemeral::context_info(&image.view_once().unwrap()).and_then(EphemeralSetting::of_context_info).unwrap();
+		assert_eq!(setting, EphemeralSetting { duration: TIMER_7_DAYS, timestamp: None });
+		assert!(ephemeral::context_info(&Message::default()).is_none());
+
+		let known = EphemeralSetting { duration: 0, timestamp: Some(1_000) };
+		assert!(!setting.replaces(&known));
+		assert!(EphemeralSetting { timestamp: Some(2_000), ..setting }.replaces(&known));
+	}
+
+	#[test]
+	pub fn nested_wrappers_are_unwrapped() {
+		use protobuf::MessageField;
+		use crate::protobuf::message_wrapper::MessageWrapperExt;
+		use crate::protobuf::whatsapp::{DeviceSentMessage, FutureProofMessage, ImageMessage, Message};
+
+		let image = Message { imageMessage: MessageField::some(ImageMessage::default()), ..Default::default() };
+		let view_once = image.view_once().unwrap();
+		let message = Message {
+			deviceSentMessage: MessageField::some(DeviceSentMessage {
+				destinationJid: Some("1234@s.whatsapp.net".to_owned()),
+				message: MessageField::some(Message {
+					ephemeralMessage: MessageField::some(FutureProofMessage { message: MessageField::some(view_once), ..Default::default() }),
+					..Default::default()
+				}),
+				..Default::default()
+			}),
+			..Default::default()
+		};
+
+		let unwrapped = message.unwrap_content();
+		assert!(unwrapped.is_view_once && unwrapped.is_ephemeral && unwrapped.sent_by_self_device);
+		assert!(!unwrapped.is_edit);
+		assert_eq!(unwrapped.destination.as_deref(), Some("1234@s.whatsapp.net"));
+		assert_eq!(unwrapped.message.imageMessage.viewOnce, Some(true));
+	}
+
+	#[test]
+	pub fn malformed_values_are_none() {
+		use crate::binary::node::DataExt;
+
+		let value = serde_json::json!({ "description": "iq", "content": [1, 300] });
+		assert_eq!(value.id(), None);
+		assert_eq!(value.size(), 2);
+		assert_eq!(value.content_array_nums(), None);
+	}
+
+	#[test]
+	pub fn media_is_verified_as_it_is_read() {
+		use tokio::io::AsyncReadExt;
+		use crate::security::media::{self, MediaType};
+		use crate::security::media::stream::MediaReader;
+
+		let plaintext: Vec<u8> = (0..200_000u32).map(|index| index as u8).collect();
+		let encrypted = media::encrypt(&plaintext, MediaType::Document);
+		let read = |data: &[u8]| futures::executor::block_on(async {
+			let info = &encrypted.info;
+			let mut reader = MediaReader::new(data, &info.media_key, MediaType::Document, Some(&info.file_sha256), Some(&info.file_enc_sha256));
+			let mut output = Vec::new();
+			let result = reader.read_to_end(&mut output).await.map(|_| output);
+			(result.ok(), reader.is_verified())
+		});
+
+		assert_eq!(read(&encrypted.data), (Some(plaintext), true));
+
+		let mut tampered = encrypted.data.clone();
+		tampered[1000] ^= 1;
+		assert_eq!(read(&tampered), (None, false));
+	}
+
+	#[test]
+	pub fn group_metadata_is_parsed() {
+		use crate::model::ContactJid;
+		use crate::model::group::{GroupDescription, GroupMetadata, GroupParticipant, ParticipantRole};
+
+		let creator: ContactJid = "1111@s.whatsapp.net".parse().unwrap();
+		let member: ContactJid = "2222@s.whatsapp.net".parse().unwrap();
+		let result = node("iq", &[("type", "result")], vec![
+			node("group", &[
+				("id", "1234-5678"),
+				("subject", "Friends"),
+				("s_o", "1111@s.whatsapp.net"),
+				("s_t", "1600000000"),
+				("creator", "1111@s.whatsapp.net"),
+				("creation", "1500000000")
+			], vec![
+				node("participant", &[("jid", "1111@s.whatsapp.net"), ("type", "superadmin")], vec![]),
+				node("participant", &[("jid", "2222@s.whatsapp.net")], vec![]),
+				node("description", &[("id", "D1"), ("participant", "1111@s.whatsapp.net"), ("t", "1600000001")], vec![
+					Node::with_bytes("body".to_owned(), b"Hello")
+				]),
+				node("announcement", &[], vec![]),
+				node("ephemeral", &[("expiration", "86400")], vec![])
+			])
+		]);
+
+		let metadata = GroupMetadata::try_from(result).unwrap();
+		assert_eq!(metadata.jid, "1234-5678@g.us".parse::<ContactJid>().unwrap());
+		assert_eq!(metadata.subject, "Friends");
+		assert_eq!(metadata.subject_owner.as_ref(), Some(&creator));
+		assert_eq!(metadata.subject_time, Some(1600000000));
+		assert_eq!(metadata.creation, Some(1500000000));
+		assert_eq!(metadata.participants, vec![
+			GroupParticipant { jid: creator.clone(), role: ParticipantRole::SuperAdmin },
+			GroupParticipant { jid: member, role: ParticipantRole::Member }
+		]);
+		assert_eq!(metadata.description, Some(GroupDescription {
+			id: "D1".to_owned(),
+			text: "Hello".to_owned(),
+			author: creator.into(),
+			timestamp: Some(1600000001)
+		}));
+		assert!(metadata.announce);
+		assert!(!metadata.locked);
+		assert_eq!(metadata.ephemeral_duration, Some(86400));
+
+		assert!(GroupMetadata::try_from(node("iq", &[("type", "result")], vec![])).is_err());
+	}
+
+	#[test]
+	pub fn group_updates_apply_to_metadata() {
+		use crate::model::ContactJid;
+		use crate::model::group::{GroupChange, GroupMetadata, GroupUpdate, ParticipantChange, ParticipantRole};
+
+		let mut metadata = GroupMetadata::try_from(node("group", &[("id", "1234-5678"), ("subject", "Friends")], vec![
+			node("participant", &[("jid", "1111@s.whatsapp.net"), ("type", "superadmin")], vec![]),
+			node("participant", &[("jid", "2222@s.whatsapp.net")], vec![]),
+			node("ephemeral", &[("expiration", "86400")], vec![])
+		])).unwrap();
+
+		let added: ContactJid = "3333@s.whatsapp.net".parse().unwrap();
+		let promoted: ContactJid = "2222@s.whatsapp.net".parse().unwrap();
+		let notification = node("notification", &[
+			("from", "1234-5678@g.us"),
+			("type", "w:gp2"),
+			("participant", "1111@s.whatsapp.net"),
+			("t", "1600000002")
+		], vec![
+			node("add", &[], vec![node("participant", &[("jid", "3333@s.whatsapp.net")], vec![])]),
+			node("promote", &[], vec![node("participant", &[("jid", "2222@s.whatsapp.net")], vec![])]),
+			node("subject", &[("subject", "Best friends")], vec![]),
+			node("not_ephemeral", &[], vec![]),
+			node("unknown_change", &[], vec![])
+		]);
+
+		let update = GroupUpdate::of(&notification).unwrap();
+		assert_eq!(update.group, metadata.jid);
+		assert_eq!(update.actor, "1111@s.whatsapp.net".parse::<ContactJid>().ok());
+		assert_eq!(update.timestamp, Some(1600000002));
+		assert_eq!(update.changes, vec![
+			GroupChange::Participants { change: ParticipantChange::Add, participants: vec![added.clone()] },
+			GroupChange::Participants { change: ParticipantChange::Promote, participants: vec![promoted.clone()] },
+			GroupChange::Subject("Best friends".to_owned()),
+			GroupChange::Ephemeral(None)
+		]);
+
+		update.changes.iter().for_each(|change| metadata.apply(change));
+		assert_eq!(metadata.subject, "Best friends");
+		assert_eq!(metadata.ephemeral_duration, None);
+		assert_eq!(metadata.participant(&added).map(|participant| participant.role), Some(ParticipantRole::Member));
+		assert_eq!(metadata.participant(&promoted).map(|participant| participant.role), Some(ParticipantRole::Admin));
+
+		// Adding someone twice doesn't duplicate them
+		metadata.apply(&update.changes[0]);
+		assert_eq!(metadata.participants.len(), 3);
+
+		metadata.apply(&GroupChange::Participants { change: ParticipantChange::Remove, participants: vec![added.clone()] });
+		assert!(metadata.participant(&added).is_none());
+
+		// Notifications without a change we understand aren't updates
+		assert!(GroupUpdate::of(&node("notification", &[("from", "1234-5678@g.us")], vec![node("unknown_change", &[], vec![])])).is_none());
+	}
+
+	#[test]
+	pub fn presences_and_chat_states_are_parsed() {
+		use crate::model::ContactJid;
+		use crate::model::presence::{ChatState, ChatStateUpdate, PresenceUpdate};
+
+		let contact: ContactJid = "1111@s.whatsapp.net".parse().unwrap();
+		let presence = PresenceUpdate::of(&node("presence", &[("from", "1111@s.whatsapp.net"), ("type", "unavailable"), ("last", "1600000000")], vec![]));
+		assert_eq!(presence, Some(PresenceUpdate { jid: contact.clone(), available: false, last_seen: Some(1600000000) }));
+
+		// Hidden last seen
+		let presence = PresenceUpdate::of(&node("presence", &[("from", "1111@s.whatsapp.net"), ("last", "deny")], vec![])).unwrap();
+		assert!(presence.available);
+		assert_eq!(presence.last_seen, None);
+
+		let recording = node("chatstate", &[("from", "1234-5678@g.us"), ("participant", "1111@s.whatsapp.net")], vec![
+			node("composing", &[("media", "audio")], vec![])
+		]);
+
+		assert_eq!(ChatStateUpdate::of(&recording), Some(ChatStateUpdate {
+			chat: "1234-5678@g.us".parse().unwrap(),
+			participant: contact.into(),
+			state: ChatState::Recording
+		}));
+
+		// Stanzas without a sender or with an unknown state are dropped
+		assert_eq!(PresenceUpdate::of(&node("presence", &[("type", "available")], vec![])), None);
+		assert_eq!(ChatStateUpdate::of(&node("chatstate", &[], vec![node("paused", &[], vec![])])), None);
+		assert_eq!(ChatStateUpdate::of(&node("chatstate", &[("from", "1111@s.whatsapp.net")], vec![node("unknown", &[], vec![])])), None);
+	}
+
+	#[test]
+	pub fn verified_names_need_the_server_signature() {
+		use crate::model::profile::{NameVerification, VerifiedName};
+		use crate::protobuf::whatsapp::{MessageParser, VerifiedNameCertificate, VerifiedNameDetails};
+
+		let details = VerifiedNameDetails { verifiedName: Some("Shop".to_owned()), ..Default::default() };
+		let certificate = VerifiedNameCertificate {
+			details: details.write_to_bytes().unwrap().into(),
+			signature: vec![1; 64].into(),
+			serverSignature: vec![2; 64].into(),
+			..Default::default()
+		};
+
+		let verified_name = |identity: Option<&[u8; 32]>| {
+			let node = Node::with_bytes("verified_name".to_owned(), certificate.write_to_bytes().unwrap());
+			VerifiedName::of(&node, identity).unwrap()
+		};
+
+		// A certificate WhatsApp didn't sign is invalid, whether we know the business or not
+		let unknown = verified_name(None);
+		assert_eq!(unknown.name(), "Shop");
+		assert_eq!(unknown.verification, NameVerification::Invalid);
+		assert_eq!(unknown.identity_info().signed, Some(false));
+		assert_eq!(verified_name(Some(&[9; 32])).verification, NameVerification::Invalid);
+
+		assert!(VerifiedName::of(&node("verified_name", &[], vec![]), None).is_err());
+	}
+
+	#[test]
+	pub fn privacy_nodes_are_parsed() {
+		use crate::model::ContactJid;
+		use crate::model::privacy::{self, BlockAction, BlocklistChange, PrivacySetting, PrivacyToken, PrivacyValue};
+
+		let settings = privacy::privacy_settings(&node("privacy", &[], vec![
+			node("category", &[("name", "last"), ("value", "contact_blacklist")], vec![]),
+			node("category", &[("name", "online"), ("value", "match_last_seen")], vec![]),
+			node("category", &[("name", "unknown"), ("value", "all")], vec![]),
+			node("category", &[("name", "groupadd"), ("value", "unknown")], vec![])
+		]));
+
+		assert_eq!(settings, HashMap::from([
+			(PrivacySetting::LastSeen, PrivacyValue::ContactBlacklist),
+			(PrivacySetting::Online, PrivacyValue::MatchLastSeen)
+		]));
+
+		let contact: ContactJid = "1111@s.whatsapp.net".parse().unwrap();
+		assert_eq!(
+			BlocklistChange::of(&node("item", &[("jid", "1111@s.whatsapp.net"), ("action", "unblock")], vec![])),
+			Some(BlocklistChange { jid: contact.clone(), action: BlockAction::Unblock })
+		);
+		assert_eq!(
+			BlocklistChange::of(&node("item", &[("jid", "1111@s.whatsapp.net")], vec![])).map(|change| change.action),
+			Some(BlockAction::Block)
+		);
+		assert_eq!(BlocklistChange::of(&node("item", &[("action", "block")], vec![])), None);
+
+		let device: ContactJid = "1111:2@s.whatsapp.net".parse().unwrap();
+		let mut token = Node::with_bytes("token".to_owned(), [1, 2, 3]);
+		token.set_attribute("type", "trusted_contact");
+		token.set_attribute("t", "1600000000");
+
+		// Tokens belong to the user, not to the device that shared them
+		assert_eq!(
+			PrivacyToken::of(&device, &token),
+			Some(PrivacyToken { from: contact, token: vec![1, 2, 3], timestamp: Some(1600000000) })
+		);
+
+		token.set_attribute("type", "other");
+		assert_eq!(PrivacyToken::of(&device, &token), None);
+	}
+}
@@ -3,6 +3,17 @@ pub use contact_jid::*;
 
 pub mod credentials;
 pub mod session_store;
+pub mod receipt;
+pub mod message_info;
+pub mod media_connection;
+pub mod app_state;
+pub mod chat_action;
+pub mod ephemeral;
+pub mod group;
+pub mod poll;
+pub mod presence;
+pub mod privacy;
+pub mod profile;
 
 pub use credentials::*;
 
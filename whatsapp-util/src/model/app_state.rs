@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::bail;
+use crypto::util::fixed_time_eq;
+use protobuf::{EnumOrUnknown, MessageField};
+
+use crate::protobuf::whatsapp::syncd_mutation::SyncdMutationSyncdOperation;
+use crate::protobuf::whatsapp::{
+    AppStateSyncKeyData, AppStateSyncKeyShare, KeyId, MessageParser, SyncActionData, SyncActionValue, SyncdIndex,
+    SyncdMutation, SyncdPatch, SyncdRecord, SyncdSnapshot, SyncdValue,
+};
+use crate::security::app_state::{self, AppStateKeys, LtHash, MAC_LENGTH};
+use crate::security::base64;
+use crate::util::error::Error;
+use crate::Result;
+
+/// The app state is split into collections, which are synced independently
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Collection {
+    Regular,
+    RegularLow,
+    RegularHigh,
+    CriticalBlock,
+    CriticalUnblockLow,
+}
+
+impl Collection {
+    pub const ALL: [Collection; 5] = [
+        Self::Regular,
+        Self::RegularLow,
+        Self::RegularHigh,
+        Self::CriticalBlock,
+        Self::CriticalUnblockLow,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Regular => "regular",
+            Self::RegularLow => "regular_low",
+            Self::RegularHigh => "regular_high",
+            Self::CriticalBlock => "critical_block",
+            Self::CriticalUnblockLow => "critical_unblock_low",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|collection| collection.name() == name)
+    }
+}
+
+/// A decrypted mutation, the index names what it applies to (ex. `["mute", "<jid>"]`)
+#[derive(Clone, Debug)]
+pub struct Mutation {
+    pub operation: SyncdMutationSyncdOperation,
+    pub index: Vec<String>,
+    pub action: SyncActionValue,
+    pub version: i32,
+    pub index_mac: Vec<u8>,
+    pub value_mac: Vec<u8>,
+}
+
+/// A mutation we want to push to a collection, see [AppStateStore::encode_patch]
+#[derive(Clone, Debug)]
+pub struct PendingMutation {
+    pub operation: SyncdMutationSyncdOperation,
+    pub index: Vec<String>,
+    pub action: SyncActionValue,
+
+    /// The version of the action's format, which differs between the actions
+    pub version: i32,
+}
+
+/// How far we synced a collection
+#[derive(Clone, Debug, Default)]
+pub struct HashState {
+    pub version: u64,
+    pub hash: LtHash,
+
+    /// The value mac of every index that's currently set, as it has to be subtracted once the index changes
+    pub values: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl HashState {
+    pub fn apply(&mut self, mutations: &[Mutation]) {
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+
+        for mutation in mutations {
+            removed.extend(self.values.remove(&mutation.index_mac));
+
+            if mutation.operation == SyncdMutationSyncdOperation::SET {
+                self.values.insert(mutation.index_mac.clone(), mutation.value_mac.clone());
+                added.push(mutation.value_mac.clone());
+            }
+        }
+
+        self.hash.subtract_then_add(&removed, &added);
+    }
+}
+
+/// The app state sync keys the phone shared with us and the state of every collection
+#[derive(Default)]
+pub struct AppStateStore {
+    keys: HashMap<Vec<u8>, AppStateSyncKeyData>,
+    collections: HashMap<Collection, HashState>,
+}
+
+#[derive(Clone, Default)]
+pub struct SharedAppStateStore(Arc<Mutex<AppStateStore>>);
+
+impl AppStateStore {
+    pub fn add_keys(&mut self, share: &AppStateSyncKeyShare) {
+        for key in &share.keys {
+            if let (Some(id), Some(data)) = (key.keyId.keyId.as_ref(), key.keyData.as_ref()) {
+                self.keys.insert(id.clone(), data.clone());
+            }
+        }
+    }
+
+    pub fn has_keys(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn keys(&self, id: &[u8]) -> Result<AppStateKeys> {
+        let data = self.keys.get(id)
+            .ok_or_else(|| Error::MissingAppStateKey(base64::encode(id)))?;
+
+        Ok(AppStateKeys::derive(data.keyData()))
+    }
+
+    /// The key the phone shared last, which is the one new patches are encrypted with
+    pub fn latest_keys(&self) -> Result<(Vec<u8>, AppStateKeys)> {
+        let (id, data) = self.keys.iter()
+            .max_by_key(|(_, data)| data.timestamp())
+            .ok_or(Error::NoAppStateKeys)?;
+
+        Ok((id.clone(), AppStateKeys::derive(data.keyData())))
+    }
+
+    pub fn state(&self, collection: Collection) -> HashState {
+        self.collections.get(&collection).cloned().unwrap_or_default()
+    }
+
+    /// A snapshot replaces whatever we knew about the collection
+    pub fn decode_snapshot(&mut self, collection: Collection, snapshot: &SyncdSnapshot) -> Result<Vec<Mutation>> {
+        let mutations = snapshot.records.iter()
+            .map(|record| self.decode_record(SyncdMutationSyncdOperation::SET, record))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut state = HashState {
+            version: snapshot.version.version(),
+            ..Default::default()
+        };
+
+        state.apply(&mutations);
+
+        let keys = self.keys(snapshot.keyId.id())?;
+        if !fixed_time_eq(&app_state::snapshot_mac(&keys, &state.hash, state.version, collection.name()), snapshot.mac()) {
+            bail!(Error::AppStateIntegrity("snapshot mac mismatch"))
+        }
+
+        self.collections.insert(collection, state);
+        Ok(mutations)
+    }
+
+    /// Applies a patch on top of the current state, the mutations have to be passed as they might be stored externally
+    pub fn decode_patch(&mut self, collection: Collection, patch: &SyncdPatch, mutations: &[SyncdMutation]) -> Result<Vec<Mutation>> {
+        let mutations = mutations.iter()
+            .map(|mutation| self.decode_record(mutation.operation(), &mutation.record))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut state = self.state(collection);
+        state.version = patch.version.version();
+        state.apply(&mutations);
+
+        let keys = self.keys(patch.keyId.id())?;
+        if !fixed_time_eq(&app_state::snapshot_mac(&keys, &state.hash, state.version, collection.name()), patch.snapshotMac()) {
+            bail!(Error::AppStateIntegrity("snapshot mac mismatch"))
+        }
+
+        let value_macs: Vec<&[u8]> = mutations.iter().map(|mutation| mutation.value_mac.as_slice()).collect();
+        let patch_mac = app_state::patch_mac(&keys, patch.snapshotMac(), &value_macs, state.version, collection.name());
+        if !fixed_time_eq(&patch_mac, patch.patchMac()) {
+            bail!(Error::AppStateIntegrity("patch mac mismatch"))
+        }
+
+        self.collections.insert(collection, state);
+        Ok(mutations)
+    }
+
+    /// Encrypts the mutations into the patch following our current version of the collection
+    ///
+    /// The state isn't changed, the patch is applied once it's synced back from the server.
+    pub fn encode_patch(&self, collection: Collection, mutations: &[PendingMutation]) -> Result<SyncdPatch> {
+        let (key_id, keys) = self.latest_keys()?;
+
+        let mut encoded = Vec::with_capacity(mutations.len());
+        let mut applied = Vec::with_capacity(mutations.len());
+        for mutation in mutations {
+            let index = serde_json::to_vec(&mutation.index)?;
+            let data = SyncActionData {
+                index: index.clone().into(),
+                value: MessageField::some(mutation.action.clone()),
+                padding: Some(Vec::new()),
+                version: mutation.version.into(),
+                ..Default::default()
+            };
+
+            let mut value = app_state::encrypt_value(&keys, &data.write_to_bytes()?)?;
+            let value_mac = app_state::value_mac(&keys, mutation.operation, &value, &key_id);
+            let index_mac = app_state::index_mac(&keys, &index);
+            value.extend_from_slice(&value_mac);
+
+            encoded.push(SyncdMutation {
+                operation: EnumOrUnknown::new(mutation.operation).into(),
+                record: MessageField::some(SyncdRecord {
+                    index: MessageField::some(SyncdIndex { blob: index_mac.to_vec().into(), ..Default::default() }),
+                    value: MessageField::some(SyncdValue { blob: value.into(), ..Default::default() }),
+                    keyId: MessageField::some(KeyId { id: key_id.clone().into(), ..Default::default() }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+
+            applied.push(Mutation {
+                operation: mutation.operation,
+                index: mutation.index.clone(),
+                action: mutation.action.clone(),
+                version: mutation.version,
+                index_mac: index_mac.to_vec(),
+                value_mac: value_mac.to_vec(),
+            });
+        }
+
+        let mut state = self.state(collection);
+        state.version += 1;
+        state.apply(&applied);
+
+        let snapshot_mac = app_state::snapshot_mac(&keys, &state.hash, state.version, collection.name());
+        let value_macs: Vec<&[u8]> = applied.iter().map(|mutation| mutation.value_mac.as_slice()).collect();
+        let patch_mac = app_state::patch_mac(&keys, &snapshot_mac, &value_macs, state.version, collection.name());
+
+        Ok(SyncdPatch {
+            mutations: encoded,
+            snapshotMac: snapshot_mac.to_vec().into(),
+            patchMac: patch_mac.to_vec().into(),
+            keyId: MessageField::some(KeyId { id: key_id.into(), ..Default::default() }),
+            ..Default::default()
+        })
+    }
+
+    fn decode_record(&self, operation: SyncdMutationSyncdOperation, record: &SyncdRecord) -> Result<Mutation> {
+        let key_id = record.keyId.id();
+        let keys = self.keys(key_id)?;
+
+        let blob = record.value.blob();
+        let Some(split) = blob.len().checked_sub(MAC_LENGTH) else {
+            bail!(Error::AppStateIntegrity("missing value mac"))
+        };
+
+        let (content, value_mac) = blob.split_at(split);
+        if !fixed_time_eq(&app_state::value_mac(&keys, operation, content, key_id), value_mac) {
+            bail!(Error::AppStateIntegrity("value mac mismatch"))
+        }
+
+        let data = SyncActionData::parse_from_bytes(&app_state::decrypt_value(&keys, content)?)?;
+        let index_mac = record.index.blob();
+        if !fixed_time_eq(&app_state::index_mac(&keys, data.index()), index_mac) {
+            bail!(Error::AppStateIntegrity("index mac mismatch"))
+        }
+
+        Ok(Mutation {
+            operation,
+            index: serde_json::from_slice(data.index())?,
+            action: data.value.clone().unwrap_or_default(),
+            version: data.version(),
+            index_mac: index_mac.to_vec(),
+            value_mac: value_mac.to_vec(),
+        })
+    }
+}
+
+impl SharedAppStateStore {
+    pub fn lock(&self) -> MutexGuard<'_, AppStateStore> {
+        // Every operation only commits once it succeeded, so a panic elsewhere doesn't matter
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,222 @@
+use crate::model::app_state::Mutation;
+use crate::model::ContactJid;
+use crate::protobuf::whatsapp::syncd_mutation::SyncdMutationSyncdOperation;
+
+/// A message named by the index of a mutation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageRef {
+    pub chat: ContactJid,
+    pub id: String,
+    pub from_me: bool,
+
+    /// The sender in groups, if it wasn't us
+    pub participant: Option<ContactJid>,
+}
+
+impl MessageRef {
+    /// The message part of the index, as [ChatAction::from_mutation] reads it
+    pub fn index(&self) -> Vec<String> {
+        let participant = self.participant.as_ref().map_or_else(|| "0".to_owned(), ContactJid::to_string);
+        let from_me = if self.from_me { "1" } else { "0" };
+        vec![self.chat.to_string(), self.id.clone(), from_me.to_owned(), participant]
+    }
+}
+
+/// Labels are either assigned to a whole chat or to a single message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LabelTarget {
+    Chat(ContactJid),
+    Message(MessageRef),
+}
+
+/// A change of the app state another device of ours made
+#[derive(Clone, Debug)]
+pub enum ChatAction {
+    Mute {
+        chat: ContactJid,
+        muted: bool,
+
+        /// Milliseconds since the epoch, -1 mutes the chat forever
+        until: Option<i64>,
+    },
+
+    Pin {
+        chat: ContactJid,
+        pinned: bool,
+    },
+
+    Archive {
+        chat: ContactJid,
+        archived: bool,
+    },
+
+    MarkChatAsRead {
+        chat: ContactJid,
+        read: bool,
+    },
+
+    ClearChat {
+        chat: ContactJid,
+    },
+
+    DeleteChat {
+        chat: ContactJid,
+    },
+
+    Contact {
+        jid: ContactJid,
+        full_name: Option<String>,
+        first_name: Option<String>,
+    },
+
+    Star {
+        message: MessageRef,
+        starred: bool,
+    },
+
+    DeleteMessageForMe {
+        message: MessageRef,
+        delete_media: bool,
+        message_timestamp: Option<i64>,
+    },
+
+    LabelEdit {
+        label: String,
+        name: Option<String>,
+        color: Option<i32>,
+        deleted: bool,
+    },
+
+    LabelAssociation {
+        label: String,
+        target: LabelTarget,
+        labeled: bool,
+    },
+
+    PushName {
+        name: String,
+    },
+
+    QuickReply {
+        id: String,
+        shortcut: Option<String>,
+        message: Option<String>,
+        keywords: Vec<String>,
+        deleted: bool,
+    },
+
+    UnarchiveChatsSetting {
+        unarchive: bool,
+    },
+}
+
+impl ChatAction {
+    /// Decodes the mutations we know, removals only tell that the index no longer exists and are skipped
+    pub fn from_mutation(mutation: &Mutation) -> Option<Self> {
+        if mutation.operation != SyncdMutationSyncdOperation::SET {
+            return None
+        }
+
+        let index: Vec<&str> = mutation.index.iter().map(String::as_str).collect();
+        let action = &mutation.action;
+
+        let chat_action = match index.as_slice() {
+            ["mute", chat, ..] => Self::Mute {
+                chat: chat.parse().ok()?,
+                muted: action.muteAction.muted(),
+                until: action.muteAction.muteEndTimestamp,
+            },
+
+            ["pin_v1", chat, ..] => Self::Pin {
+                chat: chat.parse().ok()?,
+                pinned: action.pinAction.pinned(),
+            },
+
+            ["archive", chat, ..] => Self::Archive {
+                chat: chat.parse().ok()?,
+                archived: action.archiveChatAction.archived(),
+            },
+
+            ["markChatAsRead", chat, ..] => Self::MarkChatAsRead {
+                chat: chat.parse().ok()?,
+                read: action.markChatAsReadAction.read(),
+            },
+
+            ["clearChat", chat, ..] => Self::ClearChat { chat: chat.parse().ok()? },
+
+            ["deleteChat", chat, ..] => Self::DeleteChat { chat: chat.parse().ok()? },
+
+            ["contact", jid, ..] => Self::Contact {
+                jid: jid.parse().ok()?,
+                full_name: action.contactAction.fullName.clone(),
+                first_name: action.contactAction.firstName.clone(),
+            },
+
+            ["star", message @ ..] => Self::Star {
+                message: message_ref(message)?,
+                starred: action.starAction.starred(),
+            },
+
+            ["deleteMessageForMe", message @ ..] => Self::DeleteMessageForMe {
+                message: message_ref(message)?,
+                delete_media: action.deleteMessageForMeAction.deleteMedia(),
+                message_timestamp: action.deleteMessageForMeAction.messageTimestamp,
+            },
+
+            ["label_edit", label, ..] => Self::LabelEdit {
+                label: label.to_string(),
+                name: action.labelEditAction.name.clone(),
+                color: action.labelEditAction.color,
+                deleted: action.labelEditAction.deleted(),
+            },
+
+            ["label_jid", label, chat, ..] => Self::LabelAssociation {
+                label: label.to_string(),
+                target: LabelTarget::Chat(chat.parse().ok()?),
+                labeled: action.labelAssociationAction.labeled(),
+            },
+
+            ["label_message", label, message @ ..] => Self::LabelAssociation {
+                label: label.to_string(),
+                target: LabelTarget::Message(message_ref(message)?),
+                labeled: action.labelAssociationAction.labeled(),
+            },
+
+            ["setting_pushName", ..] => Self::PushName { name: action.pushNameSetting.name().to_owned() },
+
+            ["quick_reply", id, ..] => Self::QuickReply {
+                id: id.to_string(),
+                shortcut: action.quickReplyAction.shortcut.clone(),
+                message: action.quickReplyAction.message.clone(),
+                keywords: action.quickReplyAction.keywords.clone(),
+                deleted: action.quickReplyAction.deleted(),
+            },
+
+            ["setting_unarchiveChats", ..] => Self::UnarchiveChatsSetting {
+                unarchive: action.unarchiveChatsSetting.unarchiveChats(),
+            },
+
+            _ => return None
+        };
+
+        Some(chat_action)
+    }
+}
+
+/// Messages are indexed by chat, id, whether we sent them and the participant, which is "0" outside of groups
+fn message_ref(index: &[&str]) -> Option<MessageRef> {
+    let [chat, id, from_me, rest @ ..] = index else {
+        return None
+    };
+
+    let participant = rest.first()
+        .filter(|participant| **participant != "0")
+        .and_then(|participant| participant.parse().ok());
+
+    Some(MessageRef {
+        chat: chat.parse().ok()?,
+        id: id.to_string(),
+        from_me: *from_me == "1",
+        participant,
+    })
+}
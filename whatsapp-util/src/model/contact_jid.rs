@@ -1,13 +1,15 @@
 #![allow(unused)]
 #![allow(non_snake_case)]
 
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 use anyhow::{bail, Result};
 
 macro_rules! declare_server {
     (
         $($name:ident => $val:expr)*
     ) => {
-        #[derive(serde::Serialize, Copy, Clone, Debug)]
+        #[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
         pub enum Server {
             $(
                 $name,
@@ -42,7 +44,7 @@ macro_rules! declare_server {
     };
 }
 
-#[derive(serde::Serialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ContactJid {
     pub user: String,
     pub server: Server,
@@ -73,23 +75,21 @@ impl ContactJid {
 
         if complex_user.contains(':') {
             if let Some((user, device)) = complex_user.split_once(':') {
-                if user.contains('_') {
-                    if let Some((user, agent)) = user.split_once('_') {
-                        return Ok(Self {
-                            user: user.to_owned(),
-                            server,
-                            device: device.parse()?,
-                            agent: agent.parse()?,
-                        });
-                    }
-
+                if let Some((user, agent)) = user.split_once('_') {
                     return Ok(Self {
                         user: user.to_owned(),
                         server,
                         device: device.parse()?,
-                        agent: 0,
+                        agent: agent.parse()?,
                     });
                 }
+
+                return Ok(Self {
+                    user: user.to_owned(),
+                    server,
+                    device: device.parse()?,
+                    agent: 0,
+                });
             }
         }
 
@@ -114,10 +114,28 @@ impl ContactJid {
         bail!("Could not parse jid")
     }
 
+    pub fn new(user: impl Into<String>, server: Server) -> Self {
+        Self {
+            user: user.into(),
+            server,
+            device: 0,
+            agent: 0,
+        }
+    }
+
     pub fn is_companion(&self) -> bool {
         self.device != 0
     }
 
+    pub fn is_group(&self) -> bool {
+        self.server == Server::Group
+    }
+
+    /// The same jid without agent and device, as it is used to address chats
+    pub fn to_user(&self) -> Self {
+        Self::new(self.user.clone(), self.server)
+    }
+
     fn without_server(mut jid: String) -> String {
         if jid.is_empty() {
             return String::new();
@@ -131,6 +149,45 @@ impl ContactJid {
     }
 }
 
+impl Display for ContactJid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.user)?;
+
+        if self.agent != 0 {
+            write!(f, "_{}", self.agent)?;
+        }
+
+        if self.device != 0 {
+            write!(f, ":{}", self.device)?;
+        }
+
+        if self.user.is_empty() {
+            return write!(f, "{}", self.server.address());
+        }
+
+        write!(f, "@{}", self.server.address())
+    }
+}
+
+impl FromStr for ContactJid {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let address = input.split_once('@').map_or(input, |(_, address)| address);
+        let server = match Server::of(address) {
+            Some(server) if !server.address().is_empty() => server,
+            _ => bail!("Unknown server in jid {input}")
+        };
+
+        // Jids like s.whatsapp.net only consist of their server
+        if !input.contains('@') {
+            return Ok(Self::new(String::new(), server));
+        }
+
+        Self::from_complex(input.to_owned(), server)
+    }
+}
+
 declare_server! {
     User => "c.us"
     Group => "g.us"
@@ -37,6 +37,10 @@ impl Credentials {
     pub fn ephemeral_private(&self) -> [u8; 32] {
         self.ephemeral_keypair.secret.to_bytes()
     }
+
+    pub fn registration_id(&self) -> u32 {
+        self.registration_id
+    }
     
 }
 
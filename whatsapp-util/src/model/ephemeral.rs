@@ -0,0 +1,143 @@
+use crate::protobuf::whatsapp::protocol_message::ProtocolMessageType;
+use crate::protobuf::whatsapp::{ContextInfo, Conversation, Message, ProtocolMessage};
+
+/// The timers the official clients offer, in seconds
+pub const TIMER_24_HOURS: u32 = 24 * 60 * 60;
+pub const TIMER_7_DAYS: u32 = 7 * TIMER_24_HOURS;
+pub const TIMER_90_DAYS: u32 = 90 * TIMER_24_HOURS;
+
+/// The disappearing messages timer of a chat
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EphemeralSetting {
+    /// Seconds until messages disappear, 0 if they don't
+    pub duration: u32,
+
+    /// When the timer was set in seconds since the epoch, messages name it so the recipient knows which setting they follow
+    pub timestamp: Option<i64>,
+}
+
+impl EphemeralSetting {
+    pub fn is_active(&self) -> bool {
+        self.duration > 0
+    }
+
+    /// Decodes the setting of a protocol message that changes the timer of a chat
+    pub fn of(protocol: &ProtocolMessage) -> Option<Self> {
+        if protocol.type_() != ProtocolMessageType::EPHEMERAL_SETTING {
+            return None
+        }
+
+        Some(Self {
+            duration: protocol.ephemeralExpiration(),
+            timestamp: protocol.ephemeralSettingTimestamp,
+        })
+    }
+
+    /// The timer the sender of a message followed, which is how we learn it for chats whose setting we missed
+    pub fn of_context_info(context_info: &ContextInfo) -> Option<Self> {
+        Some(Self {
+            duration: context_info.expiration?,
+            timestamp: context_info.ephemeralSettingTimestamp,
+        })
+    }
+
+    /// The timer of a chat in the history the phone shared with us
+    pub fn of_conversation(conversation: &Conversation) -> Option<Self> {
+        Some(Self {
+            duration: conversation.ephemeralExpiration?,
+            timestamp: conversation.ephemeralSettingTimestamp,
+        })
+    }
+
+    /// Whether the setting was made after the known one, a setting that doesn't say when it was made never replaces one that does
+    pub fn replaces(&self, known: &Self) -> bool {
+        self.timestamp >= known.timestamp
+    }
+
+    /// The protocol message that changes the timer of a chat with a single contact
+    pub fn protocol_message(&self) -> ProtocolMessage {
+        ProtocolMessage {
+            type_: Some(ProtocolMessageType::EPHEMERAL_SETTING.into()),
+            ephemeralExpiration: Some(self.duration),
+            ephemeralSettingTimestamp: self.timestamp,
+            ..Default::default()
+        }
+    }
+
+    /// Names the timer in the content of the message, so the recipient doesn't show a warning.
+    /// Plain text is sent as extended text, because it has no context info.
+    pub fn apply(&self, message: &mut Message) {
+        if let Some(text) = message.conversation.take() {
+            message.extendedTextMessage.mut_or_insert_default().text = Some(text);
+        }
+
+        if let Some(context_info) = context_info_mut(message) {
+            context_info.expiration = Some(self.duration);
+            context_info.ephemeralSettingTimestamp = self.timestamp;
+        }
+    }
+}
+
+macro_rules! context_info {
+    ($message:expr, $as:ident, $content:ident => $context_info:expr) => {
+        context_info!(
+            @fields $message, $as, $content => $context_info,
+            extendedTextMessage,
+            imageMessage,
+            videoMessage,
+            audioMessage,
+            documentMessage,
+            stickerMessage,
+            contactMessage,
+            contactsArrayMessage,
+            locationMessage,
+            liveLocationMessage,
+            groupInviteMessage,
+            buttonsMessage,
+            buttonsResponseMessage,
+            listMessage,
+            listResponseMessage,
+            templateButtonReplyMessage,
+            productMessage,
+            orderMessage,
+            interactiveMessage
+        )
+    };
+
+    (@fields $message:expr, $as:ident, $content:ident => $context_info:expr, $($field:ident),*) => {
+        $(
+            if let Some($content) = $message.$field.$as() {
+                return $context_info
+            }
+        )*
+    };
+}
+
+/// Wrappers carry the context as part of the content they wrap
+macro_rules! wrapped {
+    ($message:expr, $as:ident, $context_info:ident) => {
+        wrapped!(@fields $message, $as, $context_info, viewOnceMessage, ephemeralMessage, deviceSentMessage)
+    };
+
+    (@fields $message:expr, $as:ident, $context_info:ident, $($field:ident),*) => {
+        $(
+            if let Some(wrapped) = $message.$field.$as().and_then(|wrapper| wrapper.message.$as()) {
+                return $context_info(wrapped)
+            }
+        )*
+    };
+}
+
+/// The context info of the content of the message, protocol messages and reactions don't have one
+pub fn context_info(message: &Message) -> Option<&ContextInfo> {
+    wrapped!(message, as_ref, context_info);
+    context_info!(message, as_ref, content => content.contextInfo.as_ref());
+    None
+}
+
+/// Same as [context_info], but inserts an empty context into content that has none yet
+pub fn context_info_mut(message: &mut Message) -> Option<&mut ContextInfo> {
+    wrapped!(message, as_mut, context_info_mut);
+    context_info!(message, as_mut, content => Some(content.contextInfo.mut_or_insert_default()));
+    None
+}
@@ -0,0 +1,420 @@
+use crate::binary::node::{DataExt, Node};
+use crate::model::{ContactJid, Server};
+use crate::util::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParticipantRole {
+	Member,
+	Admin,
+
+	/// The creator of the group, who can't be demoted
+	SuperAdmin,
+}
+
+impl ParticipantRole {
+	fn of(node: &Node) -> Self {
+		match node.attribute_str("type") {
+			Some("superadmin") => Self::SuperAdmin,
+			Some("admin") => Self::Admin,
+			_ => Self::Member
+		}
+	}
+
+	pub fn is_admin(&self) -> bool {
+		*self != Self::Member
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupParticipant {
+	pub jid: ContactJid,
+	pub role: ParticipantRole,
+}
+
+impl GroupParticipant {
+	pub fn of(node: &Node) -> Option<Self> {
+		Some(Self {
+			jid: node.attribute_jid("jid")?,
+			role: ParticipantRole::of(node),
+		})
+	}
+}
+
+/// How the participants of a group can be changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParticipantChange {
+	Add,
+	Remove,
+	Promote,
+	Demote,
+}
+
+impl ParticipantChange {
+	pub fn tag(&self) -> &'static str {
+		match self {
+			Self::Add => "add",
+			Self::Remove => "remove",
+			Self::Promote => "promote",
+			Self::Demote => "demote",
+		}
+	}
+}
+
+/// The outcome of a participant change for a single participant, ex. 403 if they don't allow to be added
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParticipantResult {
+	pub jid: ContactJid,
+	pub error: Option<u32>,
+}
+
+impl ParticipantResult {
+	pub fn of(node: &Node) -> Option<Self> {
+		Some(Self {
+			jid: node.attribute_jid("jid")?,
+			error: node.attribute_str("error").and_then(|error| error.parse().ok()),
+		})
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupDescription {
+	pub id: String,
+	pub text: String,
+	pub author: Option<ContactJid>,
+	pub timestamp: Option<u64>,
+}
+
+/// Everything we know about a group, as it's returned by w:g2 queries
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupMetadata {
+	pub jid: ContactJid,
+	pub subject: String,
+	pub subject_owner: Option<ContactJid>,
+	pub subject_time: Option<u64>,
+	pub creator: Option<ContactJid>,
+	pub creation: Option<u64>,
+	pub description: Option<GroupDescription>,
+	pub participants: Vec<GroupParticipant>,
+
+	/// Only admins can send messages
+	pub announce: bool,
+
+	/// Only admins can edit the group info
+	pub locked: bool,
+
+	/// The duration of disappearing messages in seconds
+	pub ephemeral_duration: Option<u32>,
+
+	/// The group is a community, which only links its sub groups
+	pub is_parent: bool,
+
+	/// The community this group belongs to
+	pub linked_parent: Option<ContactJid>,
+
+	/// The announcement group every member of the community is part of
+	pub is_default_sub_group: bool,
+}
+
+impl GroupMetadata {
+	pub fn participant(&self, jid: &ContactJid) -> Option<&GroupParticipant> {
+		self.participants.iter().find(|participant| participant.jid.user == jid.user)
+	}
+}
+
+impl TryFrom<Node> for GroupMetadata {
+	type Error = Error;
+
+	/// Accepts the group node itself or any node that wraps it, like the iq result
+	fn try_from(value: Node) -> Result<Self, Self::Error> {
+		let group = if value.description() == "group" {
+			value
+		} else {
+			value.find_node("group").ok_or_else(|| malformed("missing group"))?
+		};
+
+		let id = group.attribute_str("id").ok_or_else(|| malformed("missing id"))?;
+		let children = group.nodes();
+
+		let description = children.iter().find(|child| child.description() == "description").and_then(|description| {
+			let text = description.find_node("body")?.content_bytes()?;
+			Some(GroupDescription {
+				id: description.attribute_str("id").unwrap_or_default().to_owned(),
+				text: String::from_utf8_lossy(&text).into_owned(),
+				author: description.attribute_jid("participant"),
+				timestamp: timestamp(description, "t"),
+			})
+		});
+
+		let has_child = |tag: &str| children.iter().any(|child| child.description() == tag);
+
+		Ok(Self {
+			jid: group_jid(id),
+			subject: group.attribute_str("subject").unwrap_or_default().to_owned(),
+			subject_owner: group.attribute_jid("s_o"),
+			subject_time: timestamp(&group, "s_t"),
+			creator: group.attribute_jid("creator"),
+			creation: timestamp(&group, "creation"),
+			description,
+			participants: children.iter()
+				.filter(|child| child.description() == "participant")
+				.filter_map(GroupParticipant::of)
+				.collect(),
+			announce: has_child("announcement"),
+			locked: has_child("locked"),
+			ephemeral_duration: children.iter()
+				.find(|child| child.description() == "ephemeral")
+				.and_then(|ephemeral| ephemeral.attribute_str("expiration")?.parse().ok()),
+			is_parent: has_child("parent"),
+			linked_parent: children.iter()
+				.find(|child| child.description() == "linked_parent")
+				.and_then(|parent| parent.attribute_jid("jid")),
+			is_default_sub_group: has_child("default_sub_group"),
+		})
+	}
+}
+
+/// A single change of a group, as announced by a w:gp2 notification
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GroupChange {
+	/// We were added to a new group or created it on another device
+	Created(Box<GroupMetadata>),
+
+	Participants {
+		change: ParticipantChange,
+		participants: Vec<ContactJid>,
+	},
+
+	Subject(String),
+
+	/// The new description, [None] if it was removed
+	Description(Option<GroupDescription>),
+
+	Announce(bool),
+	Locked(bool),
+
+	/// The duration of disappearing messages in seconds, [None] if they were turned off
+	Ephemeral(Option<u32>),
+
+	/// The invite link was reset, which revokes the previous one
+	InviteReset,
+
+	/// Someone asked to join a group that requires approval
+	JoinRequest(ContactJid),
+
+	/// Whether new members have to be approved by an admin
+	JoinApproval(bool),
+
+	/// A sub group was linked to the community, or the group to its community
+	Linked {
+		kind: LinkKind,
+		group: ContactJid,
+	},
+
+	Unlinked {
+		kind: LinkKind,
+		group: ContactJid,
+	},
+}
+
+/// Which side of the link between a community and a sub group the other group is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkKind {
+	Parent,
+	SubGroup,
+}
+
+impl LinkKind {
+	fn of(node: &Node, attribute: &str) -> Option<Self> {
+		match node.attribute_str(attribute)? {
+			"parent" => Some(Self::Parent),
+			"sub_group" => Some(Self::SubGroup),
+			_ => None
+		}
+	}
+}
+
+/// Everything that changed in a group at once
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupUpdate {
+	pub group: ContactJid,
+
+	/// The participant who made the changes, the server if it's missing
+	pub actor: Option<ContactJid>,
+	pub timestamp: Option<u64>,
+	pub changes: Vec<GroupChange>,
+}
+
+impl GroupUpdate {
+	pub fn of(notification: &Node) -> Option<Self> {
+		let actor = notification.attribute_jid("participant");
+		let timestamp = timestamp(notification, "t");
+
+		let changes: Vec<GroupChange> = notification.nodes().iter()
+			.filter_map(|child| GroupChange::of(child, actor.as_ref(), timestamp))
+			.collect();
+
+		if changes.is_empty() {
+			return None
+		}
+
+		Some(Self {
+			group: notification.attribute_jid("from")?,
+			actor,
+			timestamp,
+			changes,
+		})
+	}
+}
+
+impl GroupChange {
+	fn of(node: &Node, actor: Option<&ContactJid>, timestamp: Option<u64>) -> Option<Self> {
+		let participants = || node.nodes().iter()
+			.filter(|participant| participant.description() == "participant")
+			.filter_map(|participant| participant.attribute_jid("jid"))
+			.collect();
+
+		let change = match node.description() {
+			"create" => Self::Created(Box::new(GroupMetadata::try_from(node.clone()).ok()?)),
+			"add" => Self::Participants { change: ParticipantChange::Add, participants: participants() },
+			"remove" => Self::Participants { change: ParticipantChange::Remove, participants: participants() },
+			"promote" => Self::Participants { change: ParticipantChange::Promote, participants: participants() },
+			"demote" => Self::Participants { change: ParticipantChange::Demote, participants: participants() },
+			"subject" => Self::Subject(node.attribute_str("subject")?.to_owned()),
+			"description" => Self::Description(node.find_node("body").and_then(|body| body.content_bytes()).map(|text| GroupDescription {
+				id: node.attribute_str("id").unwrap_or_default().to_owned(),
+				text: String::from_utf8_lossy(&text).into_owned(),
+				author: actor.cloned(),
+				timestamp,
+			})),
+			"announcement" => Self::Announce(true),
+			"not_announcement" => Self::Announce(false),
+			"locked" => Self::Locked(true),
+			"unlocked" => Self::Locked(false),
+			"ephemeral" => Self::Ephemeral(node.attribute_str("expiration").and_then(|expiration| expiration.parse().ok())),
+			"not_ephemeral" => Self::Ephemeral(None),
+			"invite" => Self::InviteReset,
+			"membership_approval_request" => Self::JoinRequest(node.attribute_jid("jid").or_else(|| actor.cloned())?),
+			"link" => Self::Linked {
+				kind: LinkKind::of(node, "link_type")?,
+				group: linked_group(node)?,
+			},
+			"unlink" => Self::Unlinked {
+				kind: LinkKind::of(node, "unlink_type")?,
+				group: linked_group(node)?,
+			},
+			"membership_approval_mode" => Self::JoinApproval(node.find_node("group_join")?.attribute_str("state") == Some("on")),
+			_ => return None
+		};
+
+		Some(change)
+	}
+}
+
+impl GroupMetadata {
+	/// Keeps cached metadata up to date without querying it again
+	pub fn apply(&mut self, change: &GroupChange) {
+		match change {
+			GroupChange::Created(metadata) => *self = (**metadata).clone(),
+
+			GroupChange::Participants { change, participants } => match change {
+				ParticipantChange::Add => {
+					for jid in participants {
+						if self.participant(jid).is_none() {
+							self.participants.push(GroupParticipant { jid: jid.clone(), role: ParticipantRole::Member });
+						}
+					}
+				},
+
+				ParticipantChange::Remove => self.participants.retain(|participant| {
+					!participants.iter().any(|jid| jid.user == participant.jid.user)
+				}),
+
+				ParticipantChange::Promote | ParticipantChange::Demote => {
+					let role = if *change == ParticipantChange::Promote { ParticipantRole::Admin } else { ParticipantRole::Member };
+					for participant in &mut self.participants {
+						if participants.iter().any(|jid| jid.user == participant.jid.user) {
+							participant.role = role;
+						}
+					}
+				}
+			},
+
+			GroupChange::Subject(subject) => self.subject = subject.clone(),
+			GroupChange::Description(description) => self.description = description.clone(),
+			GroupChange::Announce(announce) => self.announce = *announce,
+			GroupChange::Locked(locked) => self.locked = *locked,
+			GroupChange::Ephemeral(duration) => self.ephemeral_duration = *duration,
+			GroupChange::Linked { kind: LinkKind::Parent, group } => self.linked_parent = group.clone().into(),
+			GroupChange::Unlinked { kind: LinkKind::Parent, .. } => self.linked_parent = None,
+			GroupChange::InviteReset
+				| GroupChange::JoinRequest(_)
+				| GroupChange::JoinApproval(_)
+				| GroupChange::Linked { .. }
+				| GroupChange::Unlinked { .. } => {}
+		}
+	}
+}
+
+/// A group linked to a community
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubGroup {
+	pub jid: ContactJid,
+	pub subject: String,
+	pub is_default_sub_group: bool,
+}
+
+impl SubGroup {
+	pub fn of(node: &Node) -> Option<Self> {
+		Some(Self {
+			jid: group_jid(node.attribute_str("id")?),
+			subject: node.attribute_str("subject").unwrap_or_default().to_owned(),
+			is_default_sub_group: node.find_node("default_sub_group").is_some(),
+		})
+	}
+}
+
+/// Someone who asked to join a group that requires the approval of an admin
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JoinRequest {
+	pub jid: ContactJid,
+	pub timestamp: Option<u64>,
+}
+
+impl JoinRequest {
+	pub fn of(node: &Node) -> Option<Self> {
+		Some(Self {
+			jid: node.attribute_jid("jid")?,
+			timestamp: timestamp(node, "request_time").or_else(|| timestamp(node, "t")),
+		})
+	}
+}
+
+const INVITE_LINK_PREFIX: &str = "https://chat.whatsapp.com/";
+
+pub fn invite_link(code: &str) -> String {
+	format!("{INVITE_LINK_PREFIX}{code}")
+}
+
+/// Accepts both invite links and plain codes
+pub fn invite_code(link: &str) -> &str {
+	let link = link.trim().trim_end_matches('/');
+	link.rsplit_once('/').map_or(link, |(_, code)| code)
+}
+
+/// Groups are often only named by their id, which is the user part of their jid
+pub fn group_jid(id: &str) -> ContactJid {
+	id.parse().unwrap_or_else(|_| ContactJid::new(id, Server::Group))
+}
+
+fn linked_group(node: &Node) -> Option<ContactJid> {
+	let group = node.find_node("group")?;
+	group.attribute_jid("jid").or_else(|| group.attribute_str("id").map(group_jid))
+}
+
+fn timestamp(node: &Node, key: &str) -> Option<u64> {
+	node.attribute_str(key)?.parse().ok()
+}
+
+fn malformed(reason: &'static str) -> Error {
+	Error::MalformedNode { tag: "group".to_owned(), reason }
+}
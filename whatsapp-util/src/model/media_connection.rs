@@ -1,6 +1,9 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use crate::binary::node::{DataExt, Node};
+use crate::util::error::Error;
 
+/// The hosts and the token we need to upload or download media, valid for ttl seconds
+#[derive(Clone, Debug)]
 pub struct MediaConnection {
 	pub auth: String,
 	pub ttl: i64,
@@ -19,26 +22,50 @@ impl MediaConnection {
 			hosts,
 		}
 	}
+
+	pub fn is_expired(&self) -> bool {
+		let expiration = self.timestamp + Duration::from_secs(self.ttl.max(0) as u64).as_millis();
+		now() >= expiration
+	}
 }
 
-impl From<Node> for MediaConnection {
+impl TryFrom<Node> for MediaConnection {
+	type Error = Error;
 
-	fn from(value: Node) -> Self {
-		let media_connection = value.find_description("media_conn").unwrap();
-		let auth = media_connection["auth"].as_str().unwrap().to_string();
-		let ttl = media_connection["ttl"].as_i64().unwrap();
-		let max_buckets = media_connection["max_buckets"].as_u64().unwrap();
-		let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
+	fn try_from(value: Node) -> Result<Self, Self::Error> {
+		let media_connection = value.find_node("media_conn").ok_or_else(|| malformed("missing media_conn"))?;
 
-		let hosts: Vec<String> = media_connection["hosts"].as_array().unwrap().iter()
-			.map(|host| host.attribute("hostname").unwrap().as_str().unwrap().to_owned()).collect();
+		let auth = media_connection.attribute_str("auth").ok_or_else(|| malformed("missing auth"))?.to_owned();
+		let ttl = media_connection.attribute_str("ttl")
+			.and_then(|ttl| ttl.parse().ok())
+			.ok_or_else(|| malformed("missing ttl"))?;
+		let max_buckets = media_connection.attribute_str("max_buckets")
+			.and_then(|max_buckets| max_buckets.parse().ok())
+			.unwrap_or_default();
 
-		Self {
+		let hosts: Vec<String> = media_connection.nodes().iter()
+			.filter(|host| host.description() == "host")
+			.filter_map(|host| host.attribute_str("hostname").map(str::to_owned))
+			.collect();
+
+		if hosts.is_empty() {
+			return Err(malformed("missing hosts"))
+		}
+
+		Ok(Self {
 			auth,
 			ttl,
 			max_buckets,
-			timestamp,
+			timestamp: now(),
 			hosts,
-		}
+		})
 	}
-}
\ No newline at end of file
+}
+
+fn now() -> u128 {
+	SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+fn malformed(reason: &'static str) -> Error {
+	Error::MalformedNode { tag: "media_conn".to_owned(), reason }
+}
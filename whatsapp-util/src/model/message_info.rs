@@ -0,0 +1,47 @@
+use crate::model::{ContactJid, Server};
+use crate::protobuf::whatsapp::MessageKey;
+
+/// Everything we know about a message apart from its content
+#[derive(Clone, Debug)]
+pub struct MessageInfo {
+    pub id: String,
+    pub chat: ContactJid,
+    pub sender: ContactJid,
+    pub from_me: bool,
+    pub timestamp: u64,
+    pub push_name: Option<String>,
+}
+
+impl MessageInfo {
+    pub fn key(&self) -> MessageKey {
+        MessageKey {
+            remoteJid: self.chat.to_string().into(),
+            fromMe: self.from_me.into(),
+            id: self.id.clone().into(),
+            participant: (self.chat.server == Server::Group).then(|| self.sender.to_user().to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Keys in reactions, edits and revokes name the message from the side of their sender, this names it from ours like [MessageInfo::key]
+    pub fn referenced_key(&self, key: &MessageKey, own: Option<&ContactJid>) -> MessageKey {
+        let is_group = self.chat.server == Server::Group;
+        let is_own = |jid: &str| own.zip(jid.parse::<ContactJid>().ok()).map_or(false, |(own, jid)| own.user == jid.user);
+
+        let (from_me, participant) = if key.fromMe() {
+            (self.from_me, is_group.then(|| self.sender.to_user().to_string()))
+        } else if is_group {
+            (key.participant.as_deref().map_or(false, is_own), key.participant.clone())
+        } else {
+            (!self.from_me, None)
+        };
+
+        MessageKey {
+            remoteJid: self.chat.to_string().into(),
+            fromMe: from_me.into(),
+            id: key.id.clone(),
+            participant,
+            ..Default::default()
+        }
+    }
+}
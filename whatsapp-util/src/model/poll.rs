@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::model::ContactJid;
+use crate::protobuf::poll_message::{PollCreationMessage, PollUpdateMessage, PollVoteMessage};
+use crate::security::poll;
+use crate::Result;
+
+/// A poll whose secret we know, so its votes can be decrypted and counted
+/// Serializable, so the polls and their votes can be kept across restarts
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Poll {
+    pub id: String,
+    pub chat: ContactJid,
+    pub creator: ContactJid,
+    pub name: String,
+    pub options: Vec<String>,
+    pub selectable_count: u32,
+    secret: Vec<u8>,
+
+    /// The latest vote of every voter, a vote replaces the previous one
+    #[serde(with = "votes")]
+    votes: HashMap<ContactJid, Vec<String>>,
+}
+
+impl Poll {
+    pub fn new(id: String, chat: ContactJid, creator: &ContactJid, creation: PollCreationMessage, secret: Vec<u8>) -> Self {
+        Self {
+            id,
+            chat,
+            creator: creator.to_user(),
+            name: creation.name,
+            options: creation.options,
+            selectable_count: creation.selectable_count,
+            secret,
+            votes: HashMap::new(),
+        }
+    }
+
+    /// Decrypts the vote and counts it instead of the previous vote of the voter, returns the selected options
+    pub fn apply_vote(&mut self, voter: &ContactJid, update: &PollUpdateMessage) -> Result<Vec<String>> {
+        let vote = poll::decrypt_vote(&self.secret, &self.id, &self.creator, voter, &update.enc_payload, &update.enc_iv)?;
+        let vote = PollVoteMessage::parse_from_bytes(&vote)?;
+
+        // Hashes of options we don't know can't be named
+        let selected: Vec<String> = self.options.iter()
+            .filter(|option| vote.selected_options.iter().any(|hash| hash[..] == poll::option_hash(option)[..]))
+            .cloned()
+            .collect();
+
+        self.votes.insert(voter.to_user(), selected.clone());
+        Ok(selected)
+    }
+
+    /// Encrypts a vote of ours for the options with the given names
+    pub fn encrypt_vote(&self, voter: &ContactJid, options: &[&str]) -> Result<PollUpdateMessage> {
+        let iv: [u8; 12] = rand::thread_rng().gen();
+        let vote = PollVoteMessage {
+            selected_options: options.iter().map(|option| poll::option_hash(option).to_vec()).collect(),
+        };
+
+        Ok(PollUpdateMessage {
+            enc_payload: poll::encrypt_vote(&self.secret, &self.id, &self.creator, voter, &vote.write_to_bytes()?, &iv)?,
+            enc_iv: iv.to_vec(),
+            ..Default::default()
+        })
+    }
+
+    pub fn votes(&self) -> &HashMap<ContactJid, Vec<String>> {
+        &self.votes
+    }
+
+    /// The voters of every option, in the order of the options
+    pub fn tally(&self) -> Vec<(String, Vec<ContactJid>)> {
+        self.options.iter()
+            .map(|option| {
+                let voters = self.votes.iter()
+                    .filter(|(_, selected)| selected.contains(option))
+                    .map(|(voter, _)| voter.clone())
+                    .collect();
+
+                (option.clone(), voters)
+            })
+            .collect()
+    }
+}
+
+/// Jids aren't strings, so the votes are serialized as a list for formats whose map keys must be
+mod votes {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::model::ContactJid;
+
+    pub fn serialize<S: Serializer>(votes: &HashMap<ContactJid, Vec<String>>, serializer: S) -> Result<S::Ok, S::Error> {
+        votes.iter().collect::<Vec<_>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<ContactJid, Vec<String>>, D::Error> {
+        Ok(Vec::<(ContactJid, Vec<String>)>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use crate::binary::node::{DataExt, Node, Value};
+use crate::model::ContactJid;
+
+/// Whether we're shown as online to the contacts that subscribed to us
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Presence {
+    Available,
+    Unavailable,
+}
+
+impl Presence {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::Available => "available",
+            Self::Unavailable => "unavailable",
+        }
+    }
+}
+
+/// A presence stanza of a contact we subscribed to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PresenceUpdate {
+    pub jid: ContactJid,
+    pub available: bool,
+
+    /// Seconds since the epoch, unless the contact hides it
+    pub last_seen: Option<u64>,
+}
+
+impl PresenceUpdate {
+    /// Stanzas without a sender can't be attributed to anyone
+    pub fn of(node: &Node) -> Option<Self> {
+        Some(Self {
+            jid: node.attribute_jid("from")?,
+            available: node.attribute_str("type") != Some("unavailable"),
+
+            // Contacts that hide their last seen send "deny" instead
+            last_seen: node.attribute_str("last").and_then(|last| last.parse().ok()),
+        })
+    }
+}
+
+/// A chatstate stanza, [None] for states we don't know yet
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatStateUpdate {
+    pub chat: ContactJid,
+
+    /// Who is typing in a group
+    pub participant: Option<ContactJid>,
+    pub state: ChatState,
+}
+
+impl ChatStateUpdate {
+    pub fn of(node: &Node) -> Option<Self> {
+        Some(Self {
+            chat: node.attribute_jid("from")?,
+            participant: node.attribute_jid("participant"),
+            state: node.nodes().iter().find_map(ChatState::of)?,
+        })
+    }
+}
+
+/// What someone is doing in a chat right now
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatState {
+    Composing,
+
+    /// Recording a voice note
+    Recording,
+    Paused,
+}
+
+impl ChatState {
+    pub fn of(node: &Node) -> Option<Self> {
+        match node.description() {
+            "composing" if node.attribute_str("media") == Some("audio") => Some(Self::Recording),
+            "composing" => Some(Self::Composing),
+            "paused" => Some(Self::Paused),
+            _ => None
+        }
+    }
+
+    pub fn node(&self) -> Node {
+        match self {
+            Self::Composing => Node::from_attributes("composing".to_owned(), HashMap::new()),
+            Self::Recording => Node::from_attributes("composing".to_owned(), HashMap::from([
+                ("media".to_owned(), Value::String("audio".to_owned()))
+            ])),
+            Self::Paused => Node::from_attributes("paused".to_owned(), HashMap::new()),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use crate::binary::node::{DataExt, Node};
+use crate::model::ContactJid;
+
+/// What a privacy setting controls, named like its category in privacy queries
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PrivacySetting {
+    LastSeen,
+    Online,
+    ProfilePhoto,
+    About,
+    GroupsAdd,
+    ReadReceipts,
+    CallAdd,
+}
+
+impl PrivacySetting {
+    pub const ALL: [Self; 7] = [
+        Self::LastSeen,
+        Self::Online,
+        Self::ProfilePhoto,
+        Self::About,
+        Self::GroupsAdd,
+        Self::ReadReceipts,
+        Self::CallAdd,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::LastSeen => "last",
+            Self::Online => "online",
+            Self::ProfilePhoto => "profile",
+            Self::About => "status",
+            Self::GroupsAdd => "groupadd",
+            Self::ReadReceipts => "readreceipts",
+            Self::CallAdd => "calladd",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|setting| setting.name() == name)
+    }
+}
+
+/// Who a setting applies to, not every value is accepted by every setting
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PrivacyValue {
+    All,
+    Contacts,
+
+    /// Our contacts, except the ones excluded on the phone
+    ContactBlacklist,
+
+    /// Only for [PrivacySetting::Online], follows the last seen setting
+    MatchLastSeen,
+
+    /// Only for [PrivacySetting::CallAdd], callers we already know
+    Known,
+    None,
+}
+
+impl PrivacyValue {
+    pub const ALL: [Self; 6] = [
+        Self::All,
+        Self::Contacts,
+        Self::ContactBlacklist,
+        Self::MatchLastSeen,
+        Self::Known,
+        Self::None,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Contacts => "contacts",
+            Self::ContactBlacklist => "contact_blacklist",
+            Self::MatchLastSeen => "match_last_seen",
+            Self::Known => "known",
+            Self::None => "none",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|value| value.name() == name)
+    }
+}
+
+/// Decodes the categories of a <privacy> node, the ones we don't know are skipped
+pub fn privacy_settings(node: &Node) -> HashMap<PrivacySetting, PrivacyValue> {
+    node.nodes()
+        .iter()
+        .filter(|category| category.description() == "category")
+        .filter_map(|category| Some((
+            PrivacySetting::from_name(category.attribute_str("name")?)?,
+            PrivacyValue::from_name(category.attribute_str("value")?)?
+        )))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockAction {
+    Block,
+    Unblock,
+}
+
+impl BlockAction {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::Unblock => "unblock",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlocklistChange {
+    pub jid: ContactJid,
+    pub action: BlockAction,
+}
+
+impl BlocklistChange {
+    /// Items without an action are part of a whole list, so they're blocked
+    pub fn of(node: &Node) -> Option<Self> {
+        let action = match node.attribute_str("action") {
+            Some("unblock") => BlockAction::Unblock,
+            _ => BlockAction::Block
+        };
+
+        Some(Self { jid: node.attribute_jid("jid")?, action })
+    }
+}
+
+/// A token a contact shared with us, which proves we're trusted by them when we call or message them
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrivacyToken {
+    /// The contact who shared the token
+    pub from: ContactJid,
+    pub token: Vec<u8>,
+
+    /// Seconds since the epoch
+    pub timestamp: Option<u64>,
+}
+
+impl PrivacyToken {
+    /// Decodes a <token> of the notification the contact sent, only trusted contact tokens are known
+    pub fn of(from: &ContactJid, node: &Node) -> Option<Self> {
+        if node.attribute_str("type") != Some("trusted_contact") {
+            return None
+        }
+
+        Some(Self {
+            from: from.to_user(),
+            token: node.content_bytes()?,
+            timestamp: node.attribute_str("t").and_then(|timestamp| timestamp.parse().ok()),
+        })
+    }
+}
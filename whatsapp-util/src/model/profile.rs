@@ -0,0 +1,230 @@
+use protobuf::{EnumOrUnknown, MessageField};
+
+use crate::binary::node::{DataExt, Node};
+use crate::model::ContactJid;
+use crate::protobuf::whatsapp::biz_identity_info::BizIdentityInfoVerifiedLevelValue;
+use crate::protobuf::whatsapp::{BizIdentityInfo, MessageParser, VerifiedNameCertificate, VerifiedNameDetails};
+use crate::security::keypair;
+use crate::util::error::Error;
+use crate::Result;
+
+/// Pictures are either a small thumbnail or the picture in full size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PictureKind {
+    Preview,
+    Full,
+}
+
+impl PictureKind {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::Preview => "preview",
+            Self::Full => "image",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfilePicture {
+    pub id: String,
+    pub url: String,
+    pub direct_path: Option<String>,
+}
+
+impl ProfilePicture {
+    pub fn of(node: &Node) -> Option<Self> {
+        Some(Self {
+            id: node.attribute_str("id")?.to_owned(),
+            url: node.attribute_str("url")?.to_owned(),
+            direct_path: node.attribute_str("direct_path").map(str::to_owned),
+        })
+    }
+}
+
+/// The about text of a contact
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub text: String,
+
+    /// Seconds since the epoch
+    pub set_at: Option<u64>,
+}
+
+impl Status {
+    /// Contacts that hide their about answer with an error code instead
+    pub fn of(node: &Node) -> Option<Self> {
+        if node.attribute_str("code").is_some() {
+            return None
+        }
+
+        Some(Self {
+            text: text(node).unwrap_or_default(),
+            set_at: node.attribute_str("t").and_then(|timestamp| timestamp.parse().ok()),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusinessCategory {
+    pub id: String,
+    pub name: String,
+}
+
+/// The opening hours of a single day of the week
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusinessDay {
+    /// Abbreviated, ex. "mon"
+    pub day: String,
+
+    /// Either "open_24h", "appointment_only" or "specific_hours"
+    pub mode: String,
+
+    /// Minutes since midnight, only set for specific hours
+    pub open_time: Option<u32>,
+    pub close_time: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusinessHours {
+    pub timezone: Option<String>,
+    pub days: Vec<BusinessDay>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusinessProfile {
+    pub jid: ContactJid,
+    pub description: Option<String>,
+    pub categories: Vec<BusinessCategory>,
+    pub hours: Option<BusinessHours>,
+    pub websites: Vec<String>,
+    pub email: Option<String>,
+    pub address: Option<String>,
+}
+
+impl BusinessProfile {
+    /// Decodes the <profile> of a business, every field is optional but the jid
+    pub fn of(node: &Node) -> Option<Self> {
+        let children = node.nodes();
+        let child_text = |tag: &str| children.iter()
+            .find(|child| child.description() == tag)
+            .and_then(text);
+
+        let categories = node.find_node("categories")
+            .map(|categories| categories.nodes())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|category| Some(BusinessCategory {
+                id: category.attribute_str("id")?.to_owned(),
+                name: text(category).unwrap_or_default(),
+            }))
+            .collect();
+
+        let hours = node.find_node("business_hours").map(|hours| BusinessHours {
+            timezone: hours.attribute_str("timezone").map(str::to_owned),
+            days: hours.nodes()
+                .iter()
+                .filter(|day| day.description() == "business_hours_config")
+                .filter_map(|day| Some(BusinessDay {
+                    day: day.attribute_str("day_of_week")?.to_owned(),
+                    mode: day.attribute_str("mode")?.to_owned(),
+                    open_time: day.attribute_str("open_time").and_then(|time| time.parse().ok()),
+                    close_time: day.attribute_str("close_time").and_then(|time| time.parse().ok()),
+                }))
+                .collect(),
+        });
+
+        Some(Self {
+            jid: node.attribute_jid("jid")?,
+            description: child_text("description"),
+            categories,
+            hours,
+            websites: children.iter().filter(|child| child.description() == "website").filter_map(text).collect(),
+            email: child_text("email"),
+            address: child_text("address"),
+        })
+    }
+}
+
+/// The key WhatsApp signs the certificates of verified names with
+pub const WHATSAPP_CERTIFICATE_KEY: [u8; 32] = [
+    0x14, 0x23, 0x75, 0x57, 0x4d, 0x0a, 0x58, 0x71, 0x66, 0xaa, 0xe7, 0x1e, 0xbe, 0x51, 0x64, 0x37,
+    0xc4, 0xa2, 0x8b, 0x73, 0xe3, 0x69, 0x5c, 0x6c, 0xe1, 0xf7, 0xf9, 0x54, 0x5d, 0xa8, 0xee, 0x6b,
+];
+
+/// How far we could check the signatures of a verified name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameVerification {
+    /// Signed by WhatsApp and by the identity key we know for the business
+    Verified,
+
+    /// Signed by WhatsApp, but we have no session with the business to check its own signature
+    Unverifiable,
+
+    /// WhatsApp or the business didn't sign the certificate, the name must not be trusted
+    Invalid,
+}
+
+/// The name WhatsApp verified for a business account
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedName {
+    pub certificate: VerifiedNameCertificate,
+    pub details: VerifiedNameDetails,
+    pub level: BizIdentityInfoVerifiedLevelValue,
+    pub verification: NameVerification,
+}
+
+impl VerifiedName {
+    /// Decodes the certificate of a <verified_name>, the signature of the business is only checked if we know its identity
+    pub fn of(node: &Node, identity: Option<&[u8; 32]>) -> Result<Self> {
+        let certificate = node.content_bytes()
+            .ok_or(Error::MalformedNode { tag: "verified_name".to_owned(), reason: "missing certificate" })?;
+        let certificate = VerifiedNameCertificate::parse_from_bytes(&certificate)?;
+        let details = VerifiedNameDetails::parse_from_bytes(certificate.details())?;
+
+        let level = match node.attribute_str("verified_level") {
+            Some("high") => BizIdentityInfoVerifiedLevelValue::HIGH,
+            Some("low") => BizIdentityInfoVerifiedLevelValue::LOW,
+            _ => BizIdentityInfoVerifiedLevelValue::UNKNOWN
+        };
+
+        // WhatsApp countersigns the certificate including the signature of the business
+        let mut signed = certificate.details().to_vec();
+        signed.extend_from_slice(certificate.signature());
+
+        let verification = match identity {
+            _ if !verify(&WHATSAPP_CERTIFICATE_KEY, &signed, certificate.serverSignature())? => NameVerification::Invalid,
+            None => NameVerification::Unverifiable,
+            Some(identity) if verify(identity, certificate.details(), certificate.signature())? => NameVerification::Verified,
+            Some(_) => NameVerification::Invalid
+        };
+
+        Ok(Self { certificate, details, level, verification })
+    }
+
+    pub fn name(&self) -> &str {
+        self.details.verifiedName()
+    }
+
+    /// The identity as it's attached to messages of the business
+    pub fn identity_info(&self) -> BizIdentityInfo {
+        BizIdentityInfo {
+            vlevel: Some(EnumOrUnknown::new(self.level)),
+            vnameCert: MessageField::some(self.certificate.clone()),
+            signed: Some(self.verification == NameVerification::Verified),
+            ..Default::default()
+        }
+    }
+}
+
+/// Malformed signatures would make the verification panic, so they're rejected right away
+fn verify(key: &[u8; 32], message: &[u8], signature: &[u8]) -> Result<bool> {
+    if signature.len() != 64 {
+        return Ok(false)
+    }
+
+    keypair::verify_signature(key, message, signature)
+}
+
+fn text(node: &Node) -> Option<String> {
+    node.content_bytes().map(|text| String::from_utf8_lossy(&text).into_owned())
+}
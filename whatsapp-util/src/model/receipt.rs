@@ -0,0 +1,61 @@
+use crate::binary::node::Node;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReceiptKind {
+    Delivery,
+    Read,
+    ReadSelf,
+    Played,
+    PlayedSelf,
+    Retry,
+    Sender,
+    Inactive,
+    PeerMessage,
+    HistorySync,
+    Other(String),
+}
+
+impl ReceiptKind {
+    /// Delivery receipts are the only ones without a type attribute
+    pub fn of(kind: Option<&str>) -> Self {
+        match kind {
+            None | Some("") => Self::Delivery,
+            Some("read") => Self::Read,
+            Some("read-self") => Self::ReadSelf,
+            Some("played") => Self::Played,
+            Some("played-self") => Self::PlayedSelf,
+            Some("retry") => Self::Retry,
+            Some("sender") => Self::Sender,
+            Some("inactive") => Self::Inactive,
+            Some("peer_msg") => Self::PeerMessage,
+            Some("hist_sync") => Self::HistorySync,
+            Some(other) => Self::Other(other.to_owned()),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        Some(match self {
+            Self::Delivery => return None,
+            Self::Read => "read",
+            Self::ReadSelf => "read-self",
+            Self::Played => "played",
+            Self::PlayedSelf => "played-self",
+            Self::Retry => "retry",
+            Self::Sender => "sender",
+            Self::Inactive => "inactive",
+            Self::PeerMessage => "peer_msg",
+            Self::HistorySync => "hist_sync",
+            Self::Other(other) => other,
+        })
+    }
+}
+
+/// The ids of the messages a receipt confirms, receipts for multiple messages list every further id as item
+pub fn receipt_ids(node: &Node) -> Vec<String> {
+    let mut ids: Vec<String> = node.attribute_str("id").map(str::to_owned).into_iter().collect();
+    if let Some(list) = node.find_node("list") {
+        ids.extend(list.nodes().iter().filter_map(|item| item.attribute_str("id")).map(str::to_owned));
+    }
+
+    ids
+}
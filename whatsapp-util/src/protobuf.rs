@@ -3,6 +3,10 @@ use serde::{Deserialize, Deserializer};
 pub mod version;
 pub mod whatsapp;
 pub mod adv_message;
+pub mod media_message;
+pub mod message_wrapper;
+pub mod poll_message;
+pub mod protocol_message;
 
 pub const MESSAGE_HEADER: [u8; 2] = [6u8, 0u8];
 pub const SIGNATURE_HEADER: [u8; 2] = [6u8, 1u8];
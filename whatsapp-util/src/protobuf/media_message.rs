@@ -0,0 +1,116 @@
+use crate::protobuf::whatsapp::{
+	AudioMessage, DocumentMessage, ExternalBlobReference, HistorySyncNotification, ImageMessage, StickerMessage,
+	VideoMessage,
+};
+use crate::security::media::MediaType;
+
+/// Every message that references an encrypted media file
+pub trait DownloadableMessage {
+	const MEDIA_TYPE: MediaType;
+
+	fn url(&self) -> Option<&str>;
+	fn direct_path(&self) -> Option<&str>;
+	fn media_key(&self) -> Option<&[u8]>;
+	fn file_sha256(&self) -> Option<&[u8]>;
+	fn file_enc_sha256(&self) -> Option<&[u8]>;
+
+	/// Points the message to the file the phone uploaded again
+	fn set_direct_path(&mut self, direct_path: String);
+}
+
+macro_rules! downloadable {
+	($message:ty, $media_type:expr) => {
+		impl DownloadableMessage for $message {
+			const MEDIA_TYPE: MediaType = $media_type;
+
+			fn url(&self) -> Option<&str> {
+				self.url.as_deref()
+			}
+
+			fn direct_path(&self) -> Option<&str> {
+				self.directPath.as_deref()
+			}
+
+			fn media_key(&self) -> Option<&[u8]> {
+				self.mediaKey.as_deref()
+			}
+
+			fn file_sha256(&self) -> Option<&[u8]> {
+				self.fileSha256.as_deref()
+			}
+
+			fn file_enc_sha256(&self) -> Option<&[u8]> {
+				self.fileEncSha256.as_deref()
+			}
+
+			fn set_direct_path(&mut self, direct_path: String) {
+				self.directPath = direct_path.into();
+				self.url = None;
+			}
+		}
+	};
+}
+
+downloadable!(ImageMessage, MediaType::Image);
+downloadable!(VideoMessage, MediaType::Video);
+downloadable!(AudioMessage, MediaType::Audio);
+downloadable!(DocumentMessage, MediaType::Document);
+downloadable!(StickerMessage, MediaType::Sticker);
+
+/// History blobs are only referenced by their direct path
+impl DownloadableMessage for HistorySyncNotification {
+	const MEDIA_TYPE: MediaType = MediaType::History;
+
+	fn url(&self) -> Option<&str> {
+		None
+	}
+
+	fn direct_path(&self) -> Option<&str> {
+		self.directPath.as_deref()
+	}
+
+	fn media_key(&self) -> Option<&[u8]> {
+		self.mediaKey.as_deref()
+	}
+
+	fn file_sha256(&self) -> Option<&[u8]> {
+		self.fileSha256.as_deref()
+	}
+
+	fn file_enc_sha256(&self) -> Option<&[u8]> {
+		self.fileEncSha256.as_deref()
+	}
+
+	fn set_direct_path(&mut self, direct_path: String) {
+		self.directPath = direct_path.into();
+	}
+}
+
+/// Snapshots and patches of the app state that are too large to be sent inline
+impl DownloadableMessage for ExternalBlobReference {
+	const MEDIA_TYPE: MediaType = MediaType::AppState;
+
+	fn url(&self) -> Option<&str> {
+		None
+	}
+
+	fn direct_path(&self) -> Option<&str> {
+		self.directPath.as_deref()
+	}
+
+	fn media_key(&self) -> Option<&[u8]> {
+		self.mediaKey.as_deref()
+	}
+
+	fn file_sha256(&self) -> Option<&[u8]> {
+		self.fileSha256.as_deref()
+	}
+
+	fn file_enc_sha256(&self) -> Option<&[u8]> {
+		self.fileEncSha256.as_deref()
+	}
+
+	fn set_direct_path(&mut self, direct_path: String) {
+		self.directPath = direct_path.into();
+	}
+}
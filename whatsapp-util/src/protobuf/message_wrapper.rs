@@ -0,0 +1,111 @@
+use anyhow::bail;
+use protobuf::MessageField;
+
+use crate::protobuf::poll_message::length_delimited;
+use crate::protobuf::protocol_message::ProtocolMessageExt;
+use crate::protobuf::whatsapp::{FutureProofMessage, Message, MessageParser};
+use crate::Result;
+
+/// Wrappers newer than our schema, all of them are future proof messages kept as unknown fields of the message
+const DOCUMENT_WITH_CAPTION_FIELD: u32 = 53;
+const VIEW_ONCE_V2_FIELD: u32 = 55;
+const EDITED_FIELD: u32 = 58;
+const VIEW_ONCE_V2_EXTENSION_FIELD: u32 = 59;
+const FUTURE_PROOF_FIELDS: [u32; 4] = [DOCUMENT_WITH_CAPTION_FIELD, VIEW_ONCE_V2_FIELD, EDITED_FIELD, VIEW_ONCE_V2_EXTENSION_FIELD];
+
+/// The innermost content of a message and the wrappers it arrived in
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UnwrappedMessage {
+	pub message: Message,
+	pub is_view_once: bool,
+
+	/// Sent to a chat with disappearing messages by an older client
+	pub is_ephemeral: bool,
+
+	/// The content is a protocol message with the new content of an earlier message
+	pub is_edit: bool,
+	pub is_document_with_caption: bool,
+
+	/// Another device of ours sent the message, to the chat named by the destination
+	pub sent_by_self_device: bool,
+	pub destination: Option<String>,
+}
+
+impl UnwrappedMessage {
+	/// Removes the outermost wrapper and remembers it, returns the message it wrapped
+	fn take_wrapper(&mut self) -> Option<Message> {
+		let message = &mut self.message;
+		if let Some(sent) = message.deviceSentMessage.take() {
+			self.sent_by_self_device = true;
+			self.destination = sent.destinationJid;
+			return Some(sent.message.unwrap_or_default())
+		}
+
+		if let Some(view_once) = message.viewOnceMessage.take() {
+			self.is_view_once = true;
+			return Some(view_once.message.unwrap_or_default())
+		}
+
+		if let Some(ephemeral) = message.ephemeralMessage.take() {
+			self.is_ephemeral = true;
+			return Some(ephemeral.message.unwrap_or_default())
+		}
+
+		// Malformed wrappers are kept as they are
+		let (field, wrapped) = FUTURE_PROOF_FIELDS.into_iter().find_map(|field| {
+			let bytes = length_delimited(message.special_fields.unknown_fields().get(field))?;
+			Some((field, FutureProofMessage::parse_from_bytes(bytes).ok()?))
+		})?;
+
+		message.special_fields.mut_unknown_fields().remove(field);
+		match field {
+			DOCUMENT_WITH_CAPTION_FIELD => self.is_document_with_caption = true,
+			EDITED_FIELD => self.is_edit = true,
+			_ => self.is_view_once = true
+		}
+
+		Some(wrapped.message.unwrap_or_default())
+	}
+}
+
+/// Access to the content of a message, whichever wrappers it's nested in
+pub trait MessageWrapperExt: Sized {
+	/// Strips every wrapper, in whichever order they're nested
+	fn unwrap_content(self) -> UnwrappedMessage;
+
+	/// Wraps an image or video so the recipient can only open it once
+	fn view_once(self) -> Result<Self>;
+}
+
+impl MessageWrapperExt for Message {
+	fn unwrap_content(self) -> UnwrappedMessage {
+		let mut unwrapped = UnwrappedMessage { message: self, ..Default::default() };
+		while let Some(mut inner) = unwrapped.take_wrapper() {
+			// Wrappers carry the context of the message next to the content, like the secret of a poll
+			if inner.messageContextInfo.is_none() {
+				inner.messageContextInfo = unwrapped.message.messageContextInfo.take().into();
+			}
+
+			unwrapped.message = inner;
+		}
+
+		unwrapped.is_edit |= unwrapped.message.protocolMessage.as_ref().map_or(false, ProtocolMessageExt::is_edit);
+		unwrapped
+	}
+
+	fn view_once(mut self) -> Result<Self> {
+		match (self.imageMessage.as_mut(), self.videoMessage.as_mut()) {
+			(Some(image), _) => image.viewOnce = Some(true),
+			(_, Some(video)) => video.viewOnce = Some(true),
+			_ => bail!("Only images and videos can be viewed once")
+		}
+
+		// The context stays outside, so the recipient can read it without opening the media
+		let context = self.messageContextInfo.take();
+		Ok(Self {
+			viewOnceMessage: MessageField::some(FutureProofMessage { message: MessageField::some(self), ..Default::default() }),
+			messageContextInfo: context.into(),
+			..Default::default()
+		})
+	}
+}
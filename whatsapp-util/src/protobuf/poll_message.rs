@@ -0,0 +1,236 @@
+use anyhow::bail;
+use protobuf::rt::WireType;
+use protobuf::{CodedInputStream, CodedOutputStream, UnknownValueRef};
+
+use crate::protobuf::whatsapp::{Message, MessageKey, MessageParser};
+use crate::Result;
+
+/// Polls are newer than our schema, so they're encoded by hand and kept as unknown fields of the message
+const POLL_CREATION_FIELDS: [u32; 3] = [49, 60, 64];
+const POLL_UPDATE_FIELD: u32 = 50;
+
+/// The secret is part of the message context info
+const MESSAGE_SECRET_FIELD: u32 = 3;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PollCreationMessage {
+	pub name: String,
+	pub options: Vec<String>,
+
+	/// How many options a voter may select, 0 allows every option
+	pub selectable_count: u32,
+}
+
+impl PollCreationMessage {
+	pub fn write_to_bytes(&self) -> Result<Vec<u8>> {
+		encode(|stream| {
+			stream.write_string(2, &self.name)?;
+			for option in &self.options {
+				stream.write_bytes(3, &encode(|option_stream| Ok(option_stream.write_string(1, option)?))?)?;
+			}
+
+			Ok(stream.write_uint32(4, self.selectable_count)?)
+		})
+	}
+
+	pub fn parse_from_bytes(bytes: &[u8]) -> Result<Self> {
+		let mut poll = Self::default();
+		decode(bytes, |field, stream| {
+			match field {
+				2 => poll.name = stream.read_string()?,
+				3 => {
+					let option = stream.read_bytes()?;
+					let mut name = String::new();
+					decode(&option, |field, stream| {
+						if field == 1 {
+							name = stream.read_string()?;
+						}
+
+						Ok(field == 1)
+					})?;
+
+					poll.options.push(name);
+				},
+				4 => poll.selectable_count = stream.read_uint32()?,
+				_ => return Ok(false)
+			}
+
+			Ok(true)
+		})?;
+
+		Ok(poll)
+	}
+}
+
+/// An encrypted vote for a poll, only the participants that know the secret of the poll can read it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PollUpdateMessage {
+	pub poll_key: MessageKey,
+	pub enc_payload: Vec<u8>,
+	pub enc_iv: Vec<u8>,
+	pub sender_timestamp_ms: Option<i64>,
+}
+
+impl PollUpdateMessage {
+	pub fn write_to_bytes(&self) -> Result<Vec<u8>> {
+		encode(|stream| {
+			stream.write_bytes(1, &self.poll_key.write_to_bytes()?)?;
+			stream.write_bytes(2, &encode(|vote| {
+				vote.write_bytes(1, &self.enc_payload)?;
+				Ok(vote.write_bytes(2, &self.enc_iv)?)
+			})?)?;
+
+			if let Some(timestamp) = self.sender_timestamp_ms {
+				stream.write_int64(4, timestamp)?;
+			}
+
+			Ok(())
+		})
+	}
+
+	pub fn parse_from_bytes(bytes: &[u8]) -> Result<Self> {
+		let mut update = Self::default();
+		decode(bytes, |field, stream| {
+			match field {
+				1 => update.poll_key = stream.read_message()?,
+				2 => {
+					let vote = stream.read_bytes()?;
+					decode(&vote, |field, stream| {
+						match field {
+							1 => update.enc_payload = stream.read_bytes()?,
+							2 => update.enc_iv = stream.read_bytes()?,
+							_ => return Ok(false)
+						}
+
+						Ok(true)
+					})?;
+				},
+				4 => update.sender_timestamp_ms = Some(stream.read_int64()?),
+				_ => return Ok(false)
+			}
+
+			Ok(true)
+		})?;
+
+		Ok(update)
+	}
+}
+
+/// The decrypted content of a vote, options are named by the sha256 of their name
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PollVoteMessage {
+	pub selected_options: Vec<Vec<u8>>,
+}
+
+impl PollVoteMessage {
+	pub fn write_to_bytes(&self) -> Result<Vec<u8>> {
+		encode(|stream| {
+			for option in &self.selected_options {
+				stream.write_bytes(1, option)?;
+			}
+
+			Ok(())
+		})
+	}
+
+	pub fn parse_from_bytes(bytes: &[u8]) -> Result<Self> {
+		let mut vote = Self::default();
+		decode(bytes, |field, stream| {
+			if field == 1 {
+				vote.selected_options.push(stream.read_bytes()?);
+			}
+
+			Ok(field == 1)
+		})?;
+
+		Ok(vote)
+	}
+}
+
+/// Access to the poll fields of a message
+pub trait PollMessageExt {
+	fn poll_creation(&self) -> Option<PollCreationMessage>;
+	fn set_poll_creation(&mut self, poll: &PollCreationMessage) -> Result<()>;
+
+	fn poll_update(&self) -> Option<PollUpdateMessage>;
+	fn set_poll_update(&mut self, update: &PollUpdateMessage) -> Result<()>;
+
+	/// The secret votes and other updates of the message are encrypted with
+	fn message_secret(&self) -> Option<Vec<u8>>;
+	fn set_message_secret(&mut self, secret: Vec<u8>);
+}
+
+impl PollMessageExt for Message {
+	fn poll_creation(&self) -> Option<PollCreationMessage> {
+		// Newer clients use another field for every version of polls, the content is the same
+		POLL_CREATION_FIELDS.into_iter()
+			.find_map(|field| length_delimited(self.special_fields.unknown_fields().get(field)))
+			.and_then(|bytes| PollCreationMessage::parse_from_bytes(bytes).ok())
+	}
+
+	fn set_poll_creation(&mut self, poll: &PollCreationMessage) -> Result<()> {
+		self.special_fields.mut_unknown_fields().add_length_delimited(POLL_CREATION_FIELDS[0], poll.write_to_bytes()?);
+		Ok(())
+	}
+
+	fn poll_update(&self) -> Option<PollUpdateMessage> {
+		length_delimited(self.special_fields.unknown_fields().get(POLL_UPDATE_FIELD))
+			.and_then(|bytes| PollUpdateMessage::parse_from_bytes(bytes).ok())
+	}
+
+	fn set_poll_update(&mut self, update: &PollUpdateMessage) -> Result<()> {
+		self.special_fields.mut_unknown_fields().add_length_delimited(POLL_UPDATE_FIELD, update.write_to_bytes()?);
+		Ok(())
+	}
+
+	fn message_secret(&self) -> Option<Vec<u8>> {
+		length_delimited(self.messageContextInfo.special_fields.unknown_fields().get(MESSAGE_SECRET_FIELD))
+			.map(<[u8]>::to_vec)
+	}
+
+	fn set_message_secret(&mut self, secret: Vec<u8>) {
+		self.messageContextInfo.mut_or_insert_default()
+			.special_fields
+			.mut_unknown_fields()
+			.add_length_delimited(MESSAGE_SECRET_FIELD, secret);
+	}
+}
+
+pub(crate) fn length_delimited(value: Option<UnknownValueRef>) -> Option<&[u8]> {
+	match value? {
+		UnknownValueRef::LengthDelimited(bytes) => Some(bytes),
+		_ => None
+	}
+}
+
+fn encode<F>(write: F) -> Result<Vec<u8>>
+where
+	F: FnOnce(&mut CodedOutputStream) -> Result<()>,
+{
+	let mut bytes = Vec::new();
+	let mut stream = CodedOutputStream::vec(&mut bytes);
+	write(&mut stream)?;
+	stream.flush()?;
+	drop(stream);
+
+	Ok(bytes)
+}
+
+/// Reads every field with the callback, which returns false for the fields it doesn't know so they're skipped
+fn decode<F>(bytes: &[u8], mut read: F) -> Result<()>
+where
+	F: FnMut(u32, &mut CodedInputStream) -> Result<bool>,
+{
+	let mut stream = CodedInputStream::from_bytes(bytes);
+	while let Some(tag) = stream.read_raw_tag_or_eof()? {
+		let Some(wire_type) = WireType::new(tag & 7) else {
+			bail!("Unknown wire type {}", tag & 7)
+		};
+
+		if !read(tag >> 3, &mut stream)? {
+			stream.skip_field(wire_type)?;
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,68 @@
+use protobuf::{EnumOrUnknown, MessageField, UnknownValueRef};
+
+use crate::protobuf::whatsapp::protocol_message::ProtocolMessageType;
+use crate::protobuf::whatsapp::{Message, MessageKey, MessageParser, ProtocolMessage};
+use crate::Result;
+
+/// Edits are newer than our schema, so their fields are read and written as unknown fields
+pub const MESSAGE_EDIT: i32 = 14;
+const EDITED_MESSAGE_FIELD: u32 = 14;
+const TIMESTAMP_MS_FIELD: u32 = 15;
+
+/// Protocol messages that change an earlier message, which is named by their key
+pub trait ProtocolMessageExt: Sized {
+	fn revoke(key: MessageKey) -> Self;
+	fn edit(key: MessageKey, message: &Message, timestamp_ms: i64) -> Result<Self>;
+
+	fn is_revoke(&self) -> bool;
+	fn is_edit(&self) -> bool;
+
+	/// The new content of an edit
+	fn edited_message(&self) -> Option<Message>;
+	fn timestamp_ms(&self) -> Option<i64>;
+}
+
+impl ProtocolMessageExt for ProtocolMessage {
+	fn revoke(key: MessageKey) -> Self {
+		Self {
+			key: MessageField::some(key),
+			type_: Some(EnumOrUnknown::new(ProtocolMessageType::REVOKE)),
+			..Default::default()
+		}
+	}
+
+	fn edit(key: MessageKey, message: &Message, timestamp_ms: i64) -> Result<Self> {
+		let mut edit = Self {
+			key: MessageField::some(key),
+			type_: Some(EnumOrUnknown::from_i32(MESSAGE_EDIT)),
+			..Default::default()
+		};
+
+		let unknown_fields = edit.special_fields.mut_unknown_fields();
+		unknown_fields.add_length_delimited(EDITED_MESSAGE_FIELD, message.write_to_bytes()?);
+		unknown_fields.add_varint(TIMESTAMP_MS_FIELD, timestamp_ms as u64);
+		Ok(edit)
+	}
+
+	fn is_revoke(&self) -> bool {
+		self.type_.as_ref().map(EnumOrUnknown::value) == Some(ProtocolMessageType::REVOKE as i32)
+	}
+
+	fn is_edit(&self) -> bool {
+		self.type_.as_ref().map(EnumOrUnknown::value) == Some(MESSAGE_EDIT)
+	}
+
+	fn edited_message(&self) -> Option<Message> {
+		match self.special_fields.unknown_fields().get(EDITED_MESSAGE_FIELD)? {
+			UnknownValueRef::LengthDelimited(bytes) => Message::parse_from_bytes(bytes).ok(),
+			_ => None
+		}
+	}
+
+	fn timestamp_ms(&self) -> Option<i64> {
+		match self.special_fields.unknown_fields().get(TIMESTAMP_MS_FIELD)? {
+			UnknownValueRef::Varint(timestamp) => Some(timestamp as i64),
+			_ => None
+		}
+	}
+}
@@ -1,7 +1,12 @@
 pub mod aes;
+pub mod app_state;
 pub mod hash;
 pub mod hkdf;
 pub mod keypair;
+pub mod media;
+pub mod poll;
+pub mod sender_key;
+pub mod signal;
 
 pub use base64;
 
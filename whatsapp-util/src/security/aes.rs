@@ -63,4 +63,80 @@ pub fn encrypt_no_hash<I>(key: [u8; 32], nonce: [u8; 12], input: I) -> Result<Ve
     Ok(cipher
         .encrypt(nonce, input.as_ref())
         .map_err(Error::AesCipherFail)?)
-}
\ No newline at end of file
+}
+/// Like [encrypt], but with additional data of any length
+pub fn encrypt_aad<I>(key: &[u8], nonce: &[u8], aad: &[u8], input: I) -> Result<Vec<u8>>
+where
+    I: AsRef<[u8]>,
+{
+    let cipher = Aes256Gcm::new(Key::from_slice(key));
+    let payload = Payload {
+        msg: input.as_ref(),
+        aad,
+    };
+
+    Ok(cipher
+        .encrypt(Nonce::from_slice(nonce), payload)
+        .map_err(Error::AesCipherFail)?)
+}
+
+pub fn decrypt_aad<I>(key: &[u8], nonce: &[u8], aad: &[u8], input: I) -> Result<Vec<u8>>
+where
+    I: AsRef<[u8]>,
+{
+    let cipher = Aes256Gcm::new(Key::from_slice(key));
+    let payload = Payload {
+        msg: input.as_ref(),
+        aad,
+    };
+
+    Ok(cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(Error::AesCipherFail)?)
+}
+
+pub fn encrypt_cbc<I>(key: &[u8], iv: &[u8], input: I) -> Result<Vec<u8>>
+where
+    I: AsRef<[u8]>,
+{
+    use crypto::{aes, blockmodes, buffer::{BufferResult, ReadBuffer, RefReadBuffer, RefWriteBuffer, WriteBuffer}};
+
+    let mut encryptor = aes::cbc_encryptor(aes::KeySize::KeySize256, key, iv, blockmodes::PkcsPadding);
+    let mut output = Vec::with_capacity(input.as_ref().len() + 16);
+    let mut reader = RefReadBuffer::new(input.as_ref());
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        let mut writer = RefWriteBuffer::new(&mut buffer);
+        let result = encryptor.encrypt(&mut reader, &mut writer, true)
+            .map_err(|_| Error::CbcCipherFail)?;
+
+        output.extend(writer.take_read_buffer().take_remaining());
+        if let BufferResult::BufferUnderflow = result {
+            return Ok(output)
+        }
+    }
+}
+
+pub fn decrypt_cbc<I>(key: &[u8], iv: &[u8], input: I) -> Result<Vec<u8>>
+where
+    I: AsRef<[u8]>,
+{
+    use crypto::{aes, blockmodes, buffer::{BufferResult, ReadBuffer, RefReadBuffer, RefWriteBuffer, WriteBuffer}};
+
+    let mut decryptor = aes::cbc_decryptor(aes::KeySize::KeySize256, key, iv, blockmodes::PkcsPadding);
+    let mut output = Vec::with_capacity(input.as_ref().len());
+    let mut reader = RefReadBuffer::new(input.as_ref());
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        let mut writer = RefWriteBuffer::new(&mut buffer);
+        let result = decryptor.decrypt(&mut reader, &mut writer, true)
+            .map_err(|_| Error::CbcCipherFail)?;
+
+        output.extend(writer.take_read_buffer().take_remaining());
+        if let BufferResult::BufferUnderflow = result {
+            return Ok(output)
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::bail;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::{Sha256, Sha512};
+use rand::Rng;
+
+use crate::protobuf::whatsapp::syncd_mutation::SyncdMutationSyncdOperation;
+use crate::security::{aes, hkdf};
+use crate::security::Error;
+use crate::Result;
+
+/// The length of the index, value, snapshot and patch macs
+pub const MAC_LENGTH: usize = 32;
+
+/// The length of the lt-hash that sums up the value macs of a collection
+pub const LT_HASH_LENGTH: usize = 128;
+
+const MUTATION_KEYS_INFO: &str = "WhatsApp Mutation Keys";
+const LT_HASH_INFO: &str = "WhatsApp Patch Integrity";
+
+/// The keys expanded from an app state sync key the phone shared with us
+#[derive(Clone)]
+pub struct AppStateKeys {
+    pub index: [u8; 32],
+    pub value_encryption: [u8; 32],
+    pub value_mac: [u8; 32],
+    pub snapshot_mac: [u8; 32],
+    pub patch_mac: [u8; 32],
+}
+
+impl AppStateKeys {
+    pub fn derive(key_data: &[u8]) -> Self {
+        let expanded = hkdf::expand(key_data, MUTATION_KEYS_INFO, 160);
+
+        let mut keys = Self {
+            index: [0u8; 32],
+            value_encryption: [0u8; 32],
+            value_mac: [0u8; 32],
+            snapshot_mac: [0u8; 32],
+            patch_mac: [0u8; 32],
+        };
+
+        keys.index.copy_from_slice(&expanded[..32]);
+        keys.value_encryption.copy_from_slice(&expanded[32..64]);
+        keys.value_mac.copy_from_slice(&expanded[64..96]);
+        keys.snapshot_mac.copy_from_slice(&expanded[96..128]);
+        keys.patch_mac.copy_from_slice(&expanded[128..]);
+        keys
+    }
+}
+
+/// A homomorphic hash, so single values can be added and removed without hashing the whole collection again
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LtHash(pub [u8; LT_HASH_LENGTH]);
+
+impl Default for LtHash {
+    fn default() -> Self {
+        Self([0u8; LT_HASH_LENGTH])
+    }
+}
+
+impl LtHash {
+    pub fn subtract_then_add<T: AsRef<[u8]>>(&mut self, removed: &[T], added: &[T]) {
+        for value in removed {
+            self.combine(value.as_ref(), u16::wrapping_sub);
+        }
+
+        for value in added {
+            self.combine(value.as_ref(), u16::wrapping_add);
+        }
+    }
+
+    /// Every value is expanded to the length of the hash and combined with it as little endian u16s
+    fn combine(&mut self, value: &[u8], operation: fn(u16, u16) -> u16) {
+        let expanded = hkdf::expand(value, LT_HASH_INFO, LT_HASH_LENGTH);
+
+        for (current, other) in self.0.chunks_mut(2).zip(expanded.chunks(2)) {
+            let result = operation(
+                u16::from_le_bytes([current[0], current[1]]),
+                u16::from_le_bytes([other[0], other[1]])
+            );
+
+            current.copy_from_slice(&result.to_le_bytes());
+        }
+    }
+}
+
+/// Encrypts the serialized SyncActionData, the iv is prepended to the ciphertext
+pub fn encrypt_value(keys: &AppStateKeys, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let iv: [u8; 16] = rand::thread_rng().gen();
+
+    let mut content = iv.to_vec();
+    content.extend(aes::encrypt_cbc(&keys.value_encryption, &iv, plaintext)?);
+    Ok(content)
+}
+
+/// Decrypts the content of a value blob, which doesn't include the trailing mac
+pub fn decrypt_value(keys: &AppStateKeys, content: &[u8]) -> Result<Vec<u8>> {
+    if content.len() < 32 || content.len() % 16 != 0 {
+        bail!(Error::AppStateIntegrity("invalid value length"))
+    }
+
+    aes::decrypt_cbc(&keys.value_encryption, &content[..16], &content[16..])
+}
+
+pub fn index_mac(keys: &AppStateKeys, index: &[u8]) -> [u8; MAC_LENGTH] {
+    mac_sha256(&keys.index, &[index])
+}
+
+/// Binds the encrypted value to the operation and the key it was encrypted with
+pub fn value_mac(keys: &AppStateKeys, operation: SyncdMutationSyncdOperation, content: &[u8], key_id: &[u8]) -> [u8; MAC_LENGTH] {
+    let mut hmac = Hmac::new(Sha512::new(), &keys.value_mac);
+    hmac.input(&[operation as u8 + 1]);
+    hmac.input(key_id);
+    hmac.input(content);
+    hmac.input(&(key_id.len() as u64 + 1).to_be_bytes());
+
+    let result = hmac.result();
+    result.code()[..MAC_LENGTH].try_into().unwrap()
+}
+
+pub fn snapshot_mac(keys: &AppStateKeys, hash: &LtHash, version: u64, collection: &str) -> [u8; MAC_LENGTH] {
+    mac_sha256(&keys.snapshot_mac, &[&hash.0, &version.to_be_bytes(), collection.as_bytes()])
+}
+
+pub fn patch_mac<T: AsRef<[u8]>>(
+    keys: &AppStateKeys,
+    snapshot_mac: &[u8],
+    value_macs: &[T],
+    version: u64,
+    collection: &str
+) -> [u8; MAC_LENGTH] {
+    let mut hmac = Hmac::new(Sha256::new(), &keys.patch_mac);
+    hmac.input(snapshot_mac);
+    for value_mac in value_macs {
+        hmac.input(value_mac.as_ref());
+    }
+
+    hmac.input(&version.to_be_bytes());
+    hmac.input(collection.as_bytes());
+    hmac.result().code().try_into().unwrap()
+}
+
+fn mac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; MAC_LENGTH] {
+    let mut hmac = Hmac::new(Sha256::new(), key);
+    for part in parts {
+        hmac.input(part);
+    }
+
+    hmac.result().code().try_into().unwrap()
+}
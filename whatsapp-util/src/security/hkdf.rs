@@ -12,3 +12,20 @@ where
 
     output
 }
+
+/// Derives a key of the given length with an empty salt, as it's done for message and media keys
+pub fn expand<I, T>(input: I, info: T, length: usize) -> Vec<u8>
+where
+    I: AsRef<[u8]>,
+    T: AsRef<[u8]>,
+{
+    use crypto::{hkdf, sha2::Sha256};
+
+    let mut prk = [0u8; 256 / 8];
+    let mut output = vec![0u8; length];
+
+    hkdf::hkdf_extract(Sha256::new(), &[0u8; 32], input.as_ref(), prk.as_mut_slice());
+    hkdf::hkdf_expand(Sha256::new(), prk.as_slice(), info.as_ref(), output.as_mut_slice());
+
+    output
+}
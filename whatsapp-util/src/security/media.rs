@@ -0,0 +1,127 @@
+use rand::Rng;
+
+use crate::protobuf::whatsapp::{MediaRetryNotification, MessageParser, ServerErrorReceipt};
+use crate::security::{aes, hkdf};
+use crate::Result;
+
+pub mod stream;
+
+use stream::{MediaDecryptor, MediaEncryptor, MediaInfo};
+
+/// The length of the mac appended to every encrypted media file
+pub const MAC_LENGTH: usize = 10;
+
+const RETRY_INFO: &str = "WhatsApp Media Retry Notification";
+
+/// Every kind of media derives its keys with its own info and is uploaded to its own path
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaType {
+    Image,
+    Video,
+    Audio,
+    Document,
+    Sticker,
+    History,
+    AppState,
+}
+
+impl MediaType {
+    pub fn info(&self) -> &'static str {
+        match self {
+            Self::Image | Self::Sticker => "WhatsApp Image Keys",
+            Self::Video => "WhatsApp Video Keys",
+            Self::Audio => "WhatsApp Audio Keys",
+            Self::Document => "WhatsApp Document Keys",
+            Self::History => "WhatsApp History Keys",
+            Self::AppState => "WhatsApp App State Keys",
+        }
+    }
+
+    /// The path below /mms/ the encrypted file is uploaded to
+    pub fn path(&self) -> &'static str {
+        match self {
+            Self::Image | Self::Sticker => "image",
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Document => "document",
+            Self::History => "md-msg-hist",
+            Self::AppState => "md-app-state",
+        }
+    }
+}
+
+/// The keys expanded from the media key that's sent along with the message
+pub struct MediaKeys {
+    pub iv: [u8; 16],
+    pub cipher_key: [u8; 32],
+    pub mac_key: [u8; 32],
+    pub ref_key: [u8; 32],
+}
+
+impl MediaKeys {
+    pub fn derive(media_key: &[u8], media_type: MediaType) -> Self {
+        let expanded = hkdf::expand(media_key, media_type.info(), 112);
+
+        let mut keys = Self {
+            iv: [0u8; 16],
+            cipher_key: [0u8; 32],
+            mac_key: [0u8; 32],
+            ref_key: [0u8; 32],
+        };
+
+        keys.iv.copy_from_slice(&expanded[..16]);
+        keys.cipher_key.copy_from_slice(&expanded[16..48]);
+        keys.mac_key.copy_from_slice(&expanded[48..80]);
+        keys.ref_key.copy_from_slice(&expanded[80..]);
+        keys
+    }
+}
+
+/// A media file as it's uploaded, with everything the recipient needs to verify it
+pub struct EncryptedMedia {
+    pub data: Vec<u8>,
+    pub info: MediaInfo,
+}
+
+/// Encrypts the file with a new media key, the mac is appended to the ciphertext
+pub fn encrypt(plaintext: &[u8], media_type: MediaType) -> EncryptedMedia {
+    let mut encryptor = MediaEncryptor::new(media_type);
+    let mut data = encryptor.update(plaintext);
+    let (remaining, info) = encryptor.finish();
+    data.extend(remaining);
+
+    EncryptedMedia { data, info }
+}
+
+/// Verifies the downloaded file against the hashes of the message and decrypts it
+pub fn decrypt(
+    data: &[u8],
+    media_key: &[u8],
+    media_type: MediaType,
+    file_sha256: Option<&[u8]>,
+    file_enc_sha256: Option<&[u8]>
+) -> Result<Vec<u8>> {
+    let mut decryptor = MediaDecryptor::new(media_key, media_type);
+    let mut plaintext = decryptor.update(data);
+    plaintext.extend(decryptor.finish(file_sha256, file_enc_sha256)?);
+    Ok(plaintext)
+}
+
+/// Encrypts the request for a re-upload of an expired file, returns the ciphertext and the iv
+pub fn encrypt_retry_request(media_key: &[u8], message_id: &str) -> Result<(Vec<u8>, [u8; 12])> {
+    let receipt = ServerErrorReceipt {
+        stanzaId: message_id.to_owned().into(),
+        ..Default::default()
+    };
+
+    let iv: [u8; 12] = rand::thread_rng().gen();
+    let key = hkdf::expand(media_key, RETRY_INFO, 32);
+    Ok((aes::encrypt_aad(&key, &iv, message_id.as_bytes(), receipt.write_to_bytes()?)?, iv))
+}
+
+/// Decrypts the answer of the phone, which contains the new direct path if the re-upload succeeded
+pub fn decrypt_retry_notification(media_key: &[u8], message_id: &str, ciphertext: &[u8], iv: &[u8]) -> Result<MediaRetryNotification> {
+    let key = hkdf::expand(media_key, RETRY_INFO, 32);
+    let plaintext = aes::decrypt_aad(&key, iv, message_id.as_bytes(), ciphertext)?;
+    Ok(MediaRetryNotification::parse_from_bytes(&plaintext)?)
+}
@@ -0,0 +1,423 @@
+use anyhow::bail;
+use crypto::aessafe::{AesSafe256Decryptor, AesSafe256Encryptor};
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use crypto::symmetriccipher::{BlockDecryptor, BlockEncryptor};
+use crypto::util::fixed_time_eq;
+use rand::Rng;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use crate::security::media::{MediaKeys, MediaType, MAC_LENGTH};
+use crate::security::Error;
+use crate::Result;
+
+const BLOCK_SIZE: usize = 16;
+
+/// The sidecar covers the iv and the ciphertext in chunks of this size, overlapping by one block
+const SIDECAR_CHUNK: usize = 64 * 1024;
+
+/// How much we read at once while streaming a file
+const READ_SIZE: usize = 64 * 1024;
+
+/// Everything a media message needs to reference an encrypted file, apart from where it's stored
+#[derive(Clone, Debug)]
+pub struct MediaInfo {
+    pub media_key: [u8; 32],
+    pub file_sha256: [u8; 32],
+    pub file_enc_sha256: [u8; 32],
+    pub file_length: u64,
+    pub streaming_sidecar: Option<Vec<u8>>,
+}
+
+/// Encrypts a media file chunk by chunk, the mac is emitted by [MediaEncryptor::finish]
+pub struct MediaEncryptor {
+    media_key: [u8; 32],
+    cipher: AesSafe256Encryptor,
+    previous: [u8; BLOCK_SIZE],
+    pending: Vec<u8>,
+    file_hash: Sha256,
+    enc_hash: Sha256,
+    mac: Hmac<Sha256>,
+    sidecar: Option<Sidecar>,
+    file_length: u64,
+}
+
+impl MediaEncryptor {
+    /// Creates an encryptor with a new media key, videos and audios get a sidecar so they can be seeked
+    pub fn new(media_type: MediaType) -> Self {
+        let media_key: [u8; 32] = rand::thread_rng().gen();
+        let keys = MediaKeys::derive(&media_key, media_type);
+
+        let mut mac = Hmac::new(Sha256::new(), &keys.mac_key);
+        mac.input(&keys.iv);
+
+        let sidecar = matches!(media_type, MediaType::Video | MediaType::Audio)
+            .then(|| Sidecar::new(&keys.mac_key, &keys.iv));
+
+        Self {
+            media_key,
+            cipher: AesSafe256Encryptor::new(&keys.cipher_key),
+            previous: keys.iv,
+            pending: Vec::with_capacity(BLOCK_SIZE),
+            file_hash: Sha256::new(),
+            enc_hash: Sha256::new(),
+            mac,
+            sidecar,
+            file_length: 0,
+        }
+    }
+
+    pub fn update(&mut self, input: &[u8]) -> Vec<u8> {
+        self.file_hash.input(input);
+        self.file_length += input.len() as u64;
+        self.pending.extend_from_slice(input);
+
+        let complete = self.pending.len() - self.pending.len() % BLOCK_SIZE;
+        let blocks: Vec<u8> = self.pending.drain(..complete).collect();
+        self.encrypt_blocks(&blocks)
+    }
+
+    /// Pads and encrypts the last block, returns the remaining output including the mac
+    pub fn finish(mut self) -> (Vec<u8>, MediaInfo) {
+        let padding = BLOCK_SIZE - self.pending.len();
+        let mut last = std::mem::take(&mut self.pending);
+        last.extend(std::iter::repeat(padding as u8).take(padding));
+
+        let mut output = self.encrypt_blocks(&last);
+        let mac = self.mac.result();
+        let mac = &mac.code()[..MAC_LENGTH];
+        output.extend_from_slice(mac);
+        self.enc_hash.input(mac);
+
+        let mut file_sha256 = [0u8; 32];
+        let mut file_enc_sha256 = [0u8; 32];
+        self.file_hash.result(&mut file_sha256);
+        self.enc_hash.result(&mut file_enc_sha256);
+
+        let info = MediaInfo {
+            media_key: self.media_key,
+            file_sha256,
+            file_enc_sha256,
+            file_length: self.file_length,
+            streaming_sidecar: self.sidecar.map(Sidecar::finish),
+        };
+
+        (output, info)
+    }
+
+    fn encrypt_blocks(&mut self, input: &[u8]) -> Vec<u8> {
+        let mut output = vec![0u8; input.len()];
+
+        for (block, encrypted) in input.chunks(BLOCK_SIZE).zip(output.chunks_mut(BLOCK_SIZE)) {
+            let mut chained = [0u8; BLOCK_SIZE];
+            for (index, byte) in block.iter().enumerate() {
+                chained[index] = byte ^ self.previous[index];
+            }
+
+            self.cipher.encrypt_block(&chained, encrypted);
+            self.previous.copy_from_slice(encrypted);
+        }
+
+        self.enc_hash.input(&output);
+        self.mac.input(&output);
+        if let Some(sidecar) = &mut self.sidecar {
+            sidecar.update(&output);
+        }
+
+        output
+    }
+}
+
+/// Verifies and decrypts a media file chunk by chunk
+///
+/// Plaintext is returned before the whole file was verified, so it has to be discarded if [MediaDecryptor::finish] fails.
+pub struct MediaDecryptor {
+    cipher: AesSafe256Decryptor,
+    previous: [u8; BLOCK_SIZE],
+    pending: Vec<u8>,
+    file_hash: Sha256,
+    enc_hash: Sha256,
+    mac: Hmac<Sha256>,
+}
+
+impl MediaDecryptor {
+    pub fn new(media_key: &[u8], media_type: MediaType) -> Self {
+        let keys = MediaKeys::derive(media_key, media_type);
+
+        let mut mac = Hmac::new(Sha256::new(), &keys.mac_key);
+        mac.input(&keys.iv);
+
+        Self {
+            cipher: AesSafe256Decryptor::new(&keys.cipher_key),
+            previous: keys.iv,
+            pending: Vec::new(),
+            file_hash: Sha256::new(),
+            enc_hash: Sha256::new(),
+            mac,
+        }
+    }
+
+    pub fn update(&mut self, input: &[u8]) -> Vec<u8> {
+        self.enc_hash.input(input);
+        self.pending.extend_from_slice(input);
+
+        // The mac and the padded last block can only be told apart once the file ended
+        let Some(available) = self.pending.len().checked_sub(MAC_LENGTH + BLOCK_SIZE) else {
+            return Vec::new()
+        };
+
+        let complete = available - available % BLOCK_SIZE;
+        let blocks: Vec<u8> = self.pending.drain(..complete).collect();
+
+        let plaintext = self.decrypt_blocks(&blocks);
+        self.file_hash.input(&plaintext);
+        plaintext
+    }
+
+    /// Checks the mac and the hashes of the message, returns the last plaintext without padding
+    pub fn finish(mut self, file_sha256: Option<&[u8]>, file_enc_sha256: Option<&[u8]>) -> Result<Vec<u8>> {
+        if self.pending.len() < MAC_LENGTH + BLOCK_SIZE || (self.pending.len() - MAC_LENGTH) % BLOCK_SIZE != 0 {
+            bail!(Error::MediaIntegrity("invalid file length"))
+        }
+
+        let mac = self.pending.split_off(self.pending.len() - MAC_LENGTH);
+        let blocks = std::mem::take(&mut self.pending);
+        let mut plaintext = self.decrypt_blocks(&blocks);
+
+        if !fixed_time_eq(&self.mac.result().code()[..MAC_LENGTH], &mac) {
+            bail!(Error::MediaIntegrity("mac mismatch"))
+        }
+
+        let mut enc_sha256 = [0u8; 32];
+        self.enc_hash.result(&mut enc_sha256);
+        if file_enc_sha256.map_or(false, |expected| !fixed_time_eq(&enc_sha256, expected)) {
+            bail!(Error::MediaIntegrity("encrypted hash mismatch"))
+        }
+
+        let padding = plaintext.last().copied().unwrap_or_default() as usize;
+        if padding == 0 || padding > BLOCK_SIZE || padding > plaintext.len() {
+            bail!(Error::MediaIntegrity("invalid padding"))
+        }
+
+        plaintext.truncate(plaintext.len() - padding);
+        self.file_hash.input(&plaintext);
+
+        let mut sha256 = [0u8; 32];
+        self.file_hash.result(&mut sha256);
+        if file_sha256.map_or(false, |expected| !fixed_time_eq(&sha256, expected)) {
+            bail!(Error::MediaIntegrity("hash mismatch"))
+        }
+
+        Ok(plaintext)
+    }
+
+    fn decrypt_blocks(&mut self, input: &[u8]) -> Vec<u8> {
+        self.mac.input(input);
+
+        let mut output = vec![0u8; input.len()];
+        for (block, decrypted) in input.chunks(BLOCK_SIZE).zip(output.chunks_mut(BLOCK_SIZE)) {
+            self.cipher.decrypt_block(block, decrypted);
+            for (byte, previous) in decrypted.iter_mut().zip(self.previous.iter()) {
+                *byte ^= previous;
+            }
+
+            self.previous.copy_from_slice(block);
+        }
+
+        output
+    }
+}
+
+/// Decrypts the file the reader yields as it's read, so even large files are never held in memory
+///
+/// Plaintext is handed out before the whole file was verified: the last read fails if the file doesn't match its mac
+/// or hashes, and [MediaReader::is_verified] only turns true once the whole file did.
+pub struct MediaReader<R> {
+    inner: R,
+    decryptor: Option<MediaDecryptor>,
+    file_sha256: Option<Vec<u8>>,
+    file_enc_sha256: Option<Vec<u8>>,
+    buffer: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+    state: ReaderState,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReaderState {
+    Reading,
+    Verified,
+    Failed,
+}
+
+impl<R> MediaReader<R> {
+    pub fn new(inner: R, media_key: &[u8], media_type: MediaType, file_sha256: Option<&[u8]>, file_enc_sha256: Option<&[u8]>) -> Self {
+        Self {
+            inner,
+            decryptor: MediaDecryptor::new(media_key, media_type).into(),
+            file_sha256: file_sha256.map(<[u8]>::to_vec),
+            file_enc_sha256: file_enc_sha256.map(<[u8]>::to_vec),
+            buffer: vec![0u8; READ_SIZE],
+            output: Vec::new(),
+            position: 0,
+            state: ReaderState::Reading,
+        }
+    }
+
+    /// Whether the whole file has been read and matched its mac and hashes
+    pub fn is_verified(&self) -> bool {
+        self.state == ReaderState::Verified
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for MediaReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if this.state == ReaderState::Failed {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, Error::MediaIntegrity("verification failed"))))
+            }
+
+            if this.position < this.output.len() {
+                let length = (this.output.len() - this.position).min(buf.remaining());
+                buf.put_slice(&this.output[this.position..this.position + length]);
+                this.position += length;
+                return Poll::Ready(Ok(()))
+            }
+
+            let Some(decryptor) = this.decryptor.as_mut() else {
+                return Poll::Ready(Ok(()))
+            };
+
+            let mut read = ReadBuf::new(&mut this.buffer);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut read) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending
+            }
+
+            let read = read.filled().len();
+            this.position = 0;
+            if read > 0 {
+                this.output = decryptor.update(&this.buffer[..read]);
+                continue
+            }
+
+            // The reader ended, so the rest of the file can be verified
+            let Some(decryptor) = this.decryptor.take() else {
+                return Poll::Ready(Ok(()))
+            };
+
+            match decryptor.finish(this.file_sha256.as_deref(), this.file_enc_sha256.as_deref()) {
+                Ok(output) => {
+                    this.output = output;
+                    this.state = ReaderState::Verified;
+                },
+
+                Err(error) => {
+                    this.state = ReaderState::Failed;
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, error)))
+                }
+            }
+        }
+    }
+}
+
+/// Macs of overlapping chunks of the iv and the ciphertext, so players can verify the parts they seek to
+struct Sidecar {
+    mac_key: [u8; 32],
+    window: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Sidecar {
+    fn new(mac_key: &[u8; 32], iv: &[u8; 16]) -> Self {
+        Self {
+            mac_key: *mac_key,
+            window: iv.to_vec(),
+            output: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, ciphertext: &[u8]) {
+        self.window.extend_from_slice(ciphertext);
+
+        while self.window.len() >= SIDECAR_CHUNK + BLOCK_SIZE {
+            self.sign(SIDECAR_CHUNK + BLOCK_SIZE);
+            self.window.drain(..SIDECAR_CHUNK);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if !self.window.is_empty() {
+            self.sign(self.window.len());
+        }
+
+        self.output
+    }
+
+    fn sign(&mut self, length: usize) {
+        let mut mac = Hmac::new(Sha256::new(), &self.mac_key);
+        mac.input(&self.window[..length]);
+        self.output.extend_from_slice(&mac.result().code()[..MAC_LENGTH]);
+    }
+}
+
+/// Encrypts everything the reader yields into the writer, the writer isn't shut down
+pub async fn encrypt<R, W>(reader: &mut R, writer: &mut W, media_type: MediaType) -> Result<MediaInfo>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut encryptor = MediaEncryptor::new(media_type);
+    let mut buffer = vec![0u8; READ_SIZE];
+
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break
+        }
+
+        writer.write_all(&encryptor.update(&buffer[..read])).await?;
+    }
+
+    let (output, info) = encryptor.finish();
+    writer.write_all(&output).await?;
+    writer.flush().await?;
+    Ok(info)
+}
+
+/// Decrypts everything the reader yields into the writer, which has to be discarded if this fails
+pub async fn decrypt<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    media_key: &[u8],
+    media_type: MediaType,
+    file_sha256: Option<&[u8]>,
+    file_enc_sha256: Option<&[u8]>
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut decryptor = MediaDecryptor::new(media_key, media_type);
+    let mut buffer = vec![0u8; READ_SIZE];
+
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break
+        }
+
+        writer.write_all(&decryptor.update(&buffer[..read])).await?;
+    }
+
+    writer.write_all(&decryptor.finish(file_sha256, file_enc_sha256)?).await?;
+    writer.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use rand::Rng;
+
+use crate::model::ContactJid;
+use crate::security::{aes, hash, hkdf};
+use crate::Result;
+
+const POLL_VOTE_INFO: &str = "Poll Vote";
+
+/// Every poll gets its own secret, which is shared with the participants along with the poll
+pub fn generate_secret() -> [u8; 32] {
+    rand::thread_rng().gen()
+}
+
+/// Options are named by their hash in votes
+pub fn option_hash(name: &str) -> [u8; 32] {
+    hash::sha256(name, b"")
+}
+
+/// Every voter encrypts their votes with their own key, derived from the secret of the poll
+fn vote_key(secret: &[u8], poll_id: &str, creator: &ContactJid, voter: &ContactJid) -> Vec<u8> {
+    let info = format!("{}{}{}{}", poll_id, creator.to_user(), voter.to_user(), POLL_VOTE_INFO);
+    hkdf::expand(secret, info, 32)
+}
+
+/// The id of the poll and the voter are authenticated along with the vote
+fn vote_aad(poll_id: &str, voter: &ContactJid) -> Vec<u8> {
+    format!("{}\0{}", poll_id, voter.to_user()).into_bytes()
+}
+
+pub fn decrypt_vote(secret: &[u8], poll_id: &str, creator: &ContactJid, voter: &ContactJid, payload: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    let key = vote_key(secret, poll_id, creator, voter);
+    aes::decrypt_aad(&key, iv, &vote_aad(poll_id, voter), payload)
+}
+
+pub fn encrypt_vote(secret: &[u8], poll_id: &str, creator: &ContactJid, voter: &ContactJid, vote: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    let key = vote_key(secret, poll_id, creator, voter);
+    aes::encrypt_aad(&key, iv, &vote_aad(poll_id, voter), vote)
+}
@@ -0,0 +1,175 @@
+use anyhow::bail;
+use protobuf::{CodedInputStream, CodedOutputStream};
+use rand::Rng;
+
+use crate::security::keypair::{self, Keypair};
+use crate::security::{aes, hash, hkdf, Error};
+use crate::Result;
+
+/// Sender keys are serialized with the version in both nibbles of the first byte
+const VERSION: u8 = 3 << 4 | 3;
+const SIGNATURE_LENGTH: usize = 64;
+
+/// How far we follow the chain to reach an iteration before we assume the message is broken
+const MAX_FORWARD_ITERATIONS: u32 = 2000;
+
+/// The chain of a single sender in a single group, as it's used by WhatsApp (and the original libsignal)
+#[derive(Clone)]
+pub struct SenderKeyState {
+    pub id: u32,
+    pub iteration: u32,
+    pub chain_key: [u8; 32],
+    pub signing_public: [u8; 32],
+    pub signing_private: Option<[u8; 32]>,
+}
+
+struct SenderMessageKey {
+    iv: Vec<u8>,
+    cipher_key: Vec<u8>,
+}
+
+impl SenderKeyState {
+    /// Creates our own chain which we distribute to every participant of the group
+    pub fn generate() -> Self {
+        let signing = Keypair::default();
+
+        Self {
+            id: rand::thread_rng().gen_range(0..i32::MAX as u32),
+            iteration: 0,
+            chain_key: rand::thread_rng().gen(),
+            signing_public: signing.public.to_bytes(),
+            signing_private: signing.secret.to_bytes().into(),
+        }
+    }
+
+    /// Parses the sender key distribution message of another participant
+    pub fn from_distribution(input: &[u8]) -> Result<Self> {
+        let Some((&version, payload)) = input.split_first() else {
+            bail!(Error::InvalidSenderKey("empty distribution message"))
+        };
+
+        if version >> 4 != VERSION >> 4 {
+            bail!(Error::InvalidSenderKey("unsupported version"))
+        }
+
+        let mut state = Self {
+            id: 0,
+            iteration: 0,
+            chain_key: [0u8; 32],
+            signing_public: [0u8; 32],
+            signing_private: None,
+        };
+
+        let mut stream = CodedInputStream::from_bytes(payload);
+        while let Some(tag) = stream.read_raw_tag_or_eof()? {
+            match tag >> 3 {
+                1 => state.id = stream.read_uint32()?,
+                2 => state.iteration = stream.read_uint32()?,
+                3 => state.chain_key = stream.read_bytes()?.as_slice().try_into()
+                    .map_err(|_| Error::InvalidSenderKey("invalid chain key"))?,
+                4 => state.signing_public = strip_key_type(&stream.read_bytes()?)?,
+                _ => bail!(Error::InvalidSenderKey("unknown field"))
+            }
+        }
+
+        Ok(state)
+    }
+
+    pub fn distribution(&self) -> Result<Vec<u8>> {
+        let mut signing_key = vec![5u8];
+        signing_key.extend_from_slice(&self.signing_public);
+
+        let mut output = vec![VERSION];
+        let mut stream = CodedOutputStream::vec(&mut output);
+        stream.write_uint32(1, self.id)?;
+        stream.write_uint32(2, self.iteration)?;
+        stream.write_bytes(3, &self.chain_key)?;
+        stream.write_bytes(4, &signing_key)?;
+        stream.flush()?;
+        drop(stream);
+
+        Ok(output)
+    }
+
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let Some(signing_private) = self.signing_private else {
+            bail!(Error::InvalidSenderKey("missing signing key"))
+        };
+
+        let iteration = self.iteration;
+        let key = self.next_message_key();
+        let ciphertext = aes::encrypt_cbc(&key.cipher_key, &key.iv, plaintext)?;
+
+        let mut output = vec![VERSION];
+        let mut stream = CodedOutputStream::vec(&mut output);
+        stream.write_uint32(1, self.id)?;
+        stream.write_uint32(2, iteration)?;
+        stream.write_bytes(3, &ciphertext)?;
+        stream.flush()?;
+        drop(stream);
+
+        let signature = keypair::sign(&signing_private, &output)?;
+        output.extend_from_slice(&signature);
+        Ok(output)
+    }
+
+    pub fn decrypt(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        if input.len() < 1 + SIGNATURE_LENGTH {
+            bail!(Error::InvalidSenderKey("message too short"))
+        }
+
+        let (message, signature) = input.split_at(input.len() - SIGNATURE_LENGTH);
+        if !keypair::verify_signature(&self.signing_public, message, signature)? {
+            bail!(Error::InvalidSenderKey("invalid signature"))
+        }
+
+        let (mut id, mut iteration, mut ciphertext) = (0, 0, Vec::new());
+        let mut stream = CodedInputStream::from_bytes(&message[1..]);
+        while let Some(tag) = stream.read_raw_tag_or_eof()? {
+            match tag >> 3 {
+                1 => id = stream.read_uint32()?,
+                2 => iteration = stream.read_uint32()?,
+                3 => ciphertext = stream.read_bytes()?,
+                _ => bail!(Error::InvalidSenderKey("unknown field"))
+            }
+        }
+
+        if id != self.id {
+            bail!(Error::InvalidSenderKey("unknown key id"))
+        }
+
+        // Older messages would need the keys we already dropped
+        if iteration < self.iteration || iteration - self.iteration > MAX_FORWARD_ITERATIONS {
+            bail!(Error::InvalidSenderKey("unexpected iteration"))
+        }
+
+        while self.iteration < iteration {
+            self.next_message_key();
+        }
+
+        let key = self.next_message_key();
+        aes::decrypt_cbc(&key.cipher_key, &key.iv, ciphertext)
+    }
+
+    fn next_message_key(&mut self) -> SenderMessageKey {
+        let seed = hash::mac_sha256(self.chain_key, [1u8]);
+        self.chain_key = hash::mac_sha256(self.chain_key, [2u8]);
+        self.iteration += 1;
+
+        let derived = hkdf::expand(seed, b"WhisperGroup", 48);
+        SenderMessageKey {
+            iv: derived[..16].to_vec(),
+            cipher_key: derived[16..].to_vec(),
+        }
+    }
+}
+
+/// Public keys are sometimes prefixed with their type (5 for curve25519)
+pub fn strip_key_type(key: &[u8]) -> Result<[u8; 32]> {
+    let key = match key.len() {
+        33 => &key[1..],
+        _ => key
+    };
+
+    Ok(key.try_into().map_err(|_| Error::InvalidSenderKey("invalid public key"))?)
+}
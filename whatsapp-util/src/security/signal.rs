@@ -0,0 +1,308 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::bail;
+use futures::executor::block_on;
+use libsignal_protocol::{
+    message_decrypt_prekey, message_decrypt_signal, message_encrypt, process_prekey_bundle,
+    CiphertextMessage, IdentityKey, IdentityKeyPair, IdentityKeyStore, InMemSignalProtocolStore,
+    KeyPair, PreKeyRecord, PreKeySignalMessage, PreKeyStore, PrivateKey,
+    ProtocolAddress, PublicKey, SessionStore, SignalMessage, SignedPreKeyRecord, SignedPreKeyStore,
+};
+use rand::Rng;
+use rand_core::OsRng;
+
+pub use libsignal_protocol::PreKeyBundle;
+
+use crate::model::{ContactJid, Credentials};
+use crate::security::sender_key::{self, SenderKeyState};
+use crate::security::Error;
+use crate::Result;
+
+/// A pre key as it's sent to the server or inside of retry receipts
+pub struct PreKey {
+    pub id: u32,
+    pub public: [u8; 32],
+}
+
+/// The signal sessions, pre keys and sender keys of our companion
+///
+/// The in-memory stores never suspend, so we drive their futures synchronously
+/// and the store can be shared with every task that sends messages.
+pub struct SignalStore {
+    store: InMemSignalProtocolStore,
+    sender_keys: HashMap<String, SenderKeyState>,
+
+    /// The devices that received our sender key of each group, they can read everything we send there until it's replaced
+    sender_key_recipients: HashMap<ContactJid, HashSet<ContactJid>>,
+    next_pre_key_id: u32,
+    signed_pre_key_id: u32,
+
+    /// Our signed device identity, which has to be sent alongside every pre key message
+    pub device_identity: Option<Vec<u8>>,
+}
+
+#[derive(Clone)]
+pub struct SharedSignalStore(Arc<Mutex<SignalStore>>);
+
+impl SignalStore {
+    pub fn new(credentials: &Credentials) -> Result<Self> {
+        let private = PrivateKey::deserialize(&credentials.identity_private())?;
+        let identity = IdentityKeyPair::new(IdentityKey::new(private.public_key()?), private);
+        let mut store = InMemSignalProtocolStore::new(identity, credentials.registration_id())?;
+
+        let signed = &credentials.signed_keypair;
+        let record = SignedPreKeyRecord::new(
+            (signed.key_id as u32).into(),
+            0,
+            &signed.key_pair,
+            &signed.signature
+        );
+        block_on(store.save_signed_pre_key((signed.key_id as u32).into(), &record, None))?;
+
+        Ok(Self {
+            store,
+            sender_keys: HashMap::new(),
+            sender_key_recipients: HashMap::new(),
+            next_pre_key_id: rand::thread_rng().gen_range(1..0xFFFF),
+            signed_pre_key_id: signed.key_id as u32,
+            device_identity: None,
+        })
+    }
+
+    pub fn registration_id(&self) -> Result<u32> {
+        Ok(block_on(self.store.get_local_registration_id(None))?)
+    }
+
+    pub fn identity_public(&self) -> Result<[u8; 32]> {
+        let identity = block_on(self.store.get_identity_key_pair(None))?;
+        Ok(identity.public_key().public_key_bytes()?.try_into()?)
+    }
+
+    pub fn signed_pre_key(&self) -> Result<(u32, [u8; 32], Vec<u8>)> {
+        let record = block_on(self.store.get_signed_pre_key(self.signed_pre_key_id.into(), None))?;
+        Ok((
+            self.signed_pre_key_id,
+            record.public_key()?.public_key_bytes()?.try_into()?,
+            record.signature()?.to_vec()
+        ))
+    }
+
+    /// Generates and stores new one-time pre keys, pre key ids only have three bytes
+    pub fn generate_pre_keys(&mut self, count: usize) -> Result<Vec<PreKey>> {
+        let mut pre_keys = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let id = self.next_pre_key_id;
+            self.next_pre_key_id = (self.next_pre_key_id + 1) % 0xFFFFFF;
+
+            let key_pair = KeyPair::generate(&mut OsRng);
+            block_on(self.store.save_pre_key(id.into(), &PreKeyRecord::new(id.into(), &key_pair), None))?;
+
+            pre_keys.push(PreKey {
+                id,
+                public: key_pair.public_key.public_key_bytes()?.try_into()?,
+            });
+        }
+
+        Ok(pre_keys)
+    }
+
+    pub fn has_session(&self, jid: &ContactJid) -> Result<bool> {
+        let session = block_on(self.store.load_session(&Self::address(jid), None))?;
+        Ok(session.map_or(false, |session| session.has_current_session_state()))
+    }
+
+    /// The identity the device had when we last started a session with it
+    pub fn identity(&self, jid: &ContactJid) -> Result<Option<[u8; 32]>> {
+        let Some(identity) = block_on(self.store.get_identity(&Self::address(jid), None))? else {
+            return Ok(None)
+        };
+
+        Ok(Some(identity.public_key().public_key_bytes()?.try_into()?))
+    }
+
+    pub fn process_bundle(&mut self, jid: &ContactJid, bundle: &PreKeyBundle) -> Result<()> {
+        let address = Self::address(jid);
+
+        // The companion might have been reinstalled, WhatsApp trusts the new identity right away
+        block_on(self.store.save_identity(&address, &bundle.identity_key()?, None))?;
+        block_on(process_prekey_bundle(
+            &address,
+            &mut self.store.session_store,
+            &mut self.store.identity_store,
+            bundle,
+            &mut OsRng,
+            None
+        ))?;
+
+        Ok(())
+    }
+
+    /// Encrypts the padded message for a single device, returns the enc type and the ciphertext
+    pub fn encrypt(&mut self, jid: &ContactJid, plaintext: &[u8]) -> Result<(&'static str, Vec<u8>)> {
+        let ciphertext = block_on(message_encrypt(
+            plaintext,
+            &Self::address(jid),
+            &mut self.store.session_store,
+            &mut self.store.identity_store,
+            None
+        ))?;
+
+        Ok(match ciphertext {
+            CiphertextMessage::PreKeySignalMessage(message) => ("pkmsg", message.serialized().to_vec()),
+            CiphertextMessage::SignalMessage(message) => ("msg", message.serialized().to_vec()),
+            _ => bail!(Error::UnsupportedCiphertext("unexpected ciphertext")),
+        })
+    }
+
+    pub fn decrypt(&mut self, jid: &ContactJid, kind: &str, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let address = Self::address(jid);
+
+        Ok(match kind {
+            "pkmsg" => {
+                let message = PreKeySignalMessage::try_from(ciphertext)?;
+                block_on(self.store.save_identity(&address, message.identity_key(), None))?;
+
+                block_on(message_decrypt_prekey(
+                    &message,
+                    &address,
+                    &mut self.store.session_store,
+                    &mut self.store.identity_store,
+                    &mut self.store.pre_key_store,
+                    &mut self.store.signed_pre_key_store,
+                    &mut OsRng,
+                    None
+                ))?
+            },
+
+            "msg" => block_on(message_decrypt_signal(
+                &SignalMessage::try_from(ciphertext)?,
+                &address,
+                &mut self.store.session_store,
+                &mut self.store.identity_store,
+                &mut OsRng,
+                None
+            ))?,
+
+            _ => bail!(Error::UnsupportedCiphertext("unknown enc type"))
+        })
+    }
+
+    /// Remembers the chain another participant uses to encrypt their messages in the group
+    pub fn process_sender_key_distribution(&mut self, group: &ContactJid, sender: &ContactJid, distribution: &[u8]) -> Result<()> {
+        let state = SenderKeyState::from_distribution(distribution)?;
+        self.sender_keys.insert(Self::sender_key_name(group, sender), state);
+        Ok(())
+    }
+
+    pub fn group_decrypt(&mut self, group: &ContactJid, sender: &ContactJid, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.sender_keys.get_mut(&Self::sender_key_name(group, sender))
+            .ok_or(Error::InvalidSenderKey("no sender key for this participant"))?
+            .decrypt(ciphertext)
+    }
+
+    /// Encrypts the message with our own chain of the group, returns the ciphertext and the distribution message
+    pub fn group_encrypt(&mut self, group: &ContactJid, own: &ContactJid, plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let state = self.sender_keys.entry(Self::sender_key_name(group, own))
+            .or_insert_with(SenderKeyState::generate);
+
+        let distribution = state.distribution()?;
+        Ok((state.encrypt(plaintext)?, distribution))
+    }
+
+    /// The devices of the group that don't have our sender key yet
+    ///
+    /// Once a device that has it is no longer part of the group, the key is replaced, so it can't read what follows.
+    pub fn missing_sender_key(&mut self, group: &ContactJid, own: &ContactJid, devices: &[ContactJid]) -> Vec<ContactJid> {
+        let recipients = self.sender_key_recipients.entry(group.clone()).or_default();
+        if recipients.iter().any(|recipient| !devices.contains(recipient)) {
+            recipients.clear();
+            self.sender_keys.remove(&Self::sender_key_name(group, own));
+        }
+
+        devices.iter()
+            .filter(|device| !recipients.contains(device))
+            .cloned()
+            .collect()
+    }
+
+    /// Remembers the devices that received our sender key of the group
+    pub fn sender_key_distributed(&mut self, group: &ContactJid, devices: &[ContactJid]) {
+        self.sender_key_recipients.entry(group.clone())
+            .or_default()
+            .extend(devices.iter().cloned());
+    }
+
+    /// The device lost our sender key (ex. it asked for a retry), so it receives it again with the next message
+    pub fn forget_sender_key_recipient(&mut self, group: &ContactJid, device: &ContactJid) {
+        if let Some(recipients) = self.sender_key_recipients.get_mut(group) {
+            recipients.remove(device);
+        }
+    }
+
+    fn sender_key_name(group: &ContactJid, sender: &ContactJid) -> String {
+        format!("{}::{}::{}", group, sender.user, sender.device)
+    }
+
+    fn address(jid: &ContactJid) -> ProtocolAddress {
+        ProtocolAddress::new(jid.user.clone(), jid.device.into())
+    }
+}
+
+impl SharedSignalStore {
+    pub fn new(store: SignalStore) -> Self {
+        Self(Arc::new(Mutex::new(store)))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, SignalStore> {
+        // Every operation leaves the store consistent, so a panic elsewhere doesn't matter
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Messages are padded with up to 16 bytes, each containing the length of the padding
+pub fn pad(mut plaintext: Vec<u8>) -> Vec<u8> {
+    let padding = rand::thread_rng().gen_range(1..=16u8);
+    plaintext.extend(std::iter::repeat(padding).take(padding as usize));
+    plaintext
+}
+
+pub fn unpad(mut plaintext: Vec<u8>) -> Result<Vec<u8>> {
+    let padding = *plaintext.last().ok_or(Error::UnsupportedCiphertext("empty plaintext"))? as usize;
+    if padding == 0 || padding > plaintext.len() {
+        bail!(Error::UnsupportedCiphertext("invalid padding"))
+    }
+
+    plaintext.truncate(plaintext.len() - padding);
+    Ok(plaintext)
+}
+
+/// Creates the bundle of another device from the raw keys we received from the server
+pub fn bundle(
+    jid: &ContactJid,
+    registration_id: u32,
+    identity: &[u8],
+    signed_pre_key: (u32, &[u8], &[u8]),
+    pre_key: Option<(u32, &[u8])>
+) -> Result<PreKeyBundle> {
+    let (signed_id, signed_public, signature) = signed_pre_key;
+    let pre_key = match pre_key {
+        Some((id, public)) => Some((id.into(), public_key(public)?)),
+        None => None
+    };
+
+    Ok(PreKeyBundle::new(
+        registration_id,
+        jid.device.into(),
+        pre_key,
+        signed_id.into(),
+        public_key(signed_public)?,
+        signature.to_vec(),
+        IdentityKey::new(public_key(identity)?)
+    )?)
+}
+
+pub fn public_key(key: &[u8]) -> Result<PublicKey> {
+    Ok(PublicKey::from_djb_public_key_bytes(&sender_key::strip_key_type(key)?)?)
+}
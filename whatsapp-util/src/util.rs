@@ -1,3 +1,4 @@
 pub mod tag;
 pub mod token;
 pub mod error;
+pub mod id;
@@ -5,6 +5,15 @@ pub enum Error {
     #[error("Failed to cipher: {0:?}")]
     AesCipherFail(aes_gcm::Error),
 
+    #[error("Failed to cipher with aes-cbc")]
+    CbcCipherFail,
+
+    #[error("Invalid sender key: {0}")]
+    InvalidSenderKey(&'static str),
+
+    #[error("Could not handle the ciphertext: {0}")]
+    UnsupportedCiphertext(&'static str),
+
     #[error("Failed to transform: {0:?}")]
     IntoError(&'static str),
     
@@ -30,6 +39,48 @@ pub enum Error {
     #[error("The node is not known by the protocol")]
     UnknownNode,
 
+    #[error("The node <{tag}> is malformed: {reason}")]
+    MalformedNode { tag: String, reason: &'static str },
+
+    #[error("The stream has been closed with error {code}: {text}")]
+    StreamError { code: u32, text: String },
+
+    #[error("The session has been replaced by another connection")]
+    Conflict,
+
+    #[error("The companion has been logged out, please redo the login")]
+    LoggedOut,
+
+    #[error("The connection has been closed before a response arrived")]
+    ConnectionClosed,
+
+    #[error("The server didn't answer the request in time")]
+    Timeout,
+
+    #[error("The server rejected the stanza with error {0}")]
+    AckError(u32),
+
+    #[error("The query failed with error {code}: {text}")]
+    IqError { code: u32, text: String },
+
+    #[error("The request failed with http status {0}")]
+    HttpStatus(u16),
+
+    #[error("The media file failed the integrity check: {0}")]
+    MediaIntegrity(&'static str),
+
+    #[error("The media file is no longer available on the server")]
+    MediaExpired,
+
+    #[error("The app state sync key {0} hasn't been shared with us yet")]
+    MissingAppStateKey(String),
+
+    #[error("The phone hasn't shared any app state sync key yet")]
+    NoAppStateKeys,
+
+    #[error("The app state failed the integrity check: {0}")]
+    AppStateIntegrity(&'static str),
+
     #[error("Failed to connect to the WhatsApp WebSocket")]
     WebSocketConnectError,
     
@@ -0,0 +1,11 @@
+use rand::Rng;
+
+/// Creates a new message id in the same format as WhatsApp Web (ex. 3EB0A1B2C3D4E5F6A7B8)
+pub fn message_id() -> String {
+    let bytes: [u8; 8] = rand::thread_rng().gen();
+
+    bytes.iter().fold("3EB0".to_owned(), |mut id, byte| {
+        id.push_str(&format!("{byte:02X}"));
+        id
+    })
+}
@@ -1,34 +1,147 @@
+pub mod app_state;
 pub mod auth;
+pub mod chat_action;
+pub mod ephemeral;
+pub mod group;
+pub mod handle;
+pub mod history_sync;
+pub mod keep_alive;
+pub mod media;
+pub mod message;
+pub mod poll;
+pub mod pre_key;
+pub mod presence;
+pub mod privacy;
+pub mod profile;
+pub mod receipt;
 
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use anyhow::{bail, Result};
 use futures::{SinkExt, StreamExt};
 use futures::stream::SplitSink;
 use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
 use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
-use whatsapp_rs_util::binary::node::{Node, Value};
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
 use whatsapp_rs_util::binary::state::State;
-use whatsapp_rs_util::model::{Server, Session};
+use whatsapp_rs_util::model::poll::Poll;
+use whatsapp_rs_util::model::{ContactJid, Server, Session};
 use whatsapp_rs_util::security::Error;
+use crate::client::app_state::AppStateSyncs;
+use crate::client::ephemeral::EphemeralTimers;
+use crate::client::group::GroupCache;
+use crate::client::handle::{Handle, Request, SharedState};
+use crate::client::keep_alive::KeepAlive;
+use crate::client::media::MediaSession;
+use crate::client::message::{DeviceCache, RecentMessages};
+use crate::client::poll::PollStore;
+use crate::event::{Event, SharedEvents};
+use crate::stream::digest::retry::RetryCounts;
 use crate::stream::{Stream, Transmission};
 
 pub struct WebSocketClient {
     sink: Option<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>,
     pub session: Session,
     pub state: State,
+    pub keep_alive: KeepAlive,
+
+    pub(crate) last_received: Instant,
+    events: SharedEvents,
+    requests: UnboundedSender<Request>,
+    request_queue: Option<UnboundedReceiver<Request>>,
+    pub(crate) pending: HashMap<String, oneshot::Sender<Result<Node>>>,
+    pub(crate) recent: RecentMessages,
+    pub(crate) device_lists: DeviceCache,
+    pub(crate) retries: RetryCounts,
+    pub(crate) companion: Arc<Mutex<Option<ContactJid>>>,
+    pub(crate) app_state_syncs: AppStateSyncs,
+    pub(crate) groups: GroupCache,
+    pub(crate) push_name: Arc<Mutex<Option<String>>>,
+    pub(crate) polls: PollStore,
+    pub(crate) ephemeral: EphemeralTimers,
+    pub(crate) media: MediaSession,
+    tag_prefix: String,
+    tag_counter: u64,
 }
 
 impl WebSocketClient {
 
     pub fn new(session: Option<Session>) -> Self {
         // This will be important when we want to restore the old key exchange
-        Self { session: session.unwrap_or_default(), sink: None, state: State::default() }
+        let (requests, request_queue) = mpsc::unbounded_channel();
+
+        let session = session.unwrap_or_default();
+        let companion = Arc::new(Mutex::new(session.store.companion.clone()));
+
+        Self {
+            session,
+            sink: None,
+            state: State::default(),
+            keep_alive: KeepAlive::default(),
+            last_received: Instant::now(),
+            events: SharedEvents::default(),
+            requests,
+            request_queue: request_queue.into(),
+            pending: HashMap::new(),
+            recent: RecentMessages::default(),
+            device_lists: DeviceCache::default(),
+            retries: RetryCounts::default(),
+            companion,
+            app_state_syncs: AppStateSyncs::default(),
+            groups: GroupCache::default(),
+            push_name: Arc::default(),
+            polls: PollStore::default(),
+            ephemeral: EphemeralTimers::default(),
+            media: MediaSession::default(),
+            tag_prefix: Self::create_tag_prefix(),
+            tag_counter: 0,
+        }
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Restores the polls exported with [Handle::polls], so votes for them can still be counted
+    pub fn with_polls(self, polls: Vec<Poll>) -> Self {
+        polls.into_iter().for_each(|poll| self.polls.insert(poll));
+        self
+    }
+
+    /// Creates a handle to send requests from other tasks while the client is connected
+    pub fn handle(&self) -> Handle {
+        Handle::new(self.requests.clone(), SharedState {
+            events: self.events.clone(),
+            signal: self.session.signal.clone(),
+            recent: self.recent.clone(),
+            device_lists: self.device_lists.clone(),
+            companion: self.companion.clone(),
+            app_state: self.session.app_state.clone(),
+            app_state_syncs: self.app_state_syncs.clone(),
+            groups: self.groups.clone(),
+            push_name: self.push_name.clone(),
+            polls: self.polls.clone(),
+            ephemeral: self.ephemeral.clone(),
+            media: self.media.clone(),
+        })
     }
 
     pub async fn connect(&mut self) -> Result<()> {
         if !self.state.is_default() && self.state != State::Closed { bail!(Error::StreamAlreadyInitialized) }
 
+        let mut requests = self.request_queue.take().ok_or(Error::StreamAlreadyInitialized)?;
+        let result = self.run(&mut requests).await;
+        self.request_queue = requests.into();
+
+        result
+    }
+
+    async fn run(&mut self, requests: &mut UnboundedReceiver<Request>) -> Result<()> {
         // I think this could be more consistent actually lol
         while self.state == State::Reconnect || self.state.is_default() {
             self.state = State::default();
@@ -38,15 +151,50 @@ impl WebSocketClient {
 
             let (tx, mut rx) = websocket.split();
             self.sink = tx.into();
+            self.last_received = Instant::now();
+
+            let interval = self.keep_alive.interval;
+            let mut keep_alive = tokio::time::interval_at((Instant::now() + interval).into(), interval);
 
             let mut stream = Stream::new(self).await?;
-            while let Some(frame) = rx.next().await {
-                match frame {
-                    Ok(Message::Binary(message)) => {
-                        stream.process(message).await?
+            loop {
+                tokio::select! {
+                    frame = rx.next() => match frame {
+                        Some(Ok(Message::Binary(message))) => {
+                            stream.process(message).await?
+                        },
+
+                        // Errors and close frames mean the socket is gone, so we start over
+                        Some(Ok(Message::Close(_)) | Err(_)) | None => {
+                            stream.disconnect().await;
+                            break
+                        },
+
+                        Some(Ok(_)) => stream.touch()
                     },
 
-                    _ => {}
+                    // Requests are held back until we're logged in, only a broken socket makes us start over
+                    Some(request) = requests.recv(), if stream.is_connected() => {
+                        if stream.request(request).await.is_err() {
+                            stream.disconnect().await;
+                            break
+                        }
+                    },
+
+                    // A ping that can't be written means the socket is gone, so we start over
+                    _ = keep_alive.tick() => {
+                        if stream.keep_alive().await.is_err() {
+                            stream.disconnect().await;
+                            break
+                        }
+                    },
+
+                    // A dead connection has already been closed for reconnect, so we start over
+                    _ = tokio::time::sleep_until(stream.deadline().into()), if stream.is_connected() => {
+                        if !stream.check_alive().await {
+                            break
+                        }
+                    }
                 }
             }
         }
@@ -62,10 +210,44 @@ impl WebSocketClient {
             self.session.store.read_cnt = 0;
             self.session.store.write_cnt = 0;
 
-            sink.send(Message::Close(None)).await.unwrap();
+            // Whoever still waits for a response is notified by the dropped sender
+            self.pending.clear();
+
+            // The socket might already be gone (ex. half-open connections), so there is nothing left to close
+            let _ = sink.send(Message::Close(None)).await;
         }
     }
 
+    /// Creates the channel all further events are sent to, replacing any previous subscriber
+    pub fn events(&mut self) -> UnboundedReceiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Lets background tasks emit events once they finished
+    pub(crate) fn event_sender(&self) -> Option<UnboundedSender<Event>> {
+        self.events.sender()
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        self.events.emit(event);
+    }
+
+    /// Wipes the credentials and the companion, so the next connect starts a new pairing
+    pub fn logout(&mut self) -> Result<()> {
+        *self.companion.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        self.session.reset()
+    }
+
+    pub(crate) fn next_tag(&mut self) -> String {
+        self.tag_counter += 1;
+        format!("{}{}", self.tag_prefix, self.tag_counter)
+    }
+
+    fn create_tag_prefix() -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        format!("{}.{}-", now.as_secs() % 100_000, now.subsec_micros() % 100_000)
+    }
+
     pub(crate) async fn send(&mut self, transmission: Transmission) -> Result<()> {
         let sink = self.sink.as_mut().ok_or(Error::StreamNotInitialized)?;
 
@@ -89,17 +271,68 @@ impl WebSocketClient {
         })
     }
 
+    /// Sends the node of a handle, the requester is handed any error. Fails only if the socket is broken
+    pub(crate) async fn request(&mut self, request: Request) -> Result<()> {
+        let (mut node, response) = match request {
+            Request::Send { node, response } => (node, response),
+            Request::Cancel(id) => {
+                self.pending.remove(&id);
+                return Ok(())
+            }
+        };
+
+        let id = match node.attribute("id").and_then(Value::as_str) {
+            Some(id) => id.to_owned(),
+            None => {
+                let id = self.next_tag();
+                node.set_attribute("id", id.clone());
+                id
+            }
+        };
+
+        if let Err(error) = self.send(Transmission::Node(node)).await {
+            // A node that couldn't be encoded only fails its own request, anything else means the socket is gone
+            let broken = !matches!(error.downcast_ref::<Error>(), Some(Error::EncodeNodeError(_)));
+            if let Some(response) = response {
+                let _ = response.send(Err(error));
+            }
+
+            if broken {
+                bail!(Error::ConnectionClosed)
+            }
+
+            return Ok(())
+        }
+
+        if let Some(response) = response {
+            self.pending.insert(id, response);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn resolve(&mut self, id: &str, response: Result<Node>) -> bool {
+        match self.pending.remove(id) {
+            Some(sender) => {
+                // The requester might not be interested anymore
+                let _ = sender.send(response);
+                true
+            },
+            None => false
+        }
+    }
+
     pub(crate) async fn query(&mut self, method: &str, category: &str, body: Node) -> Result<()> {
         // TODO: Query builder
         let attributes = HashMap::from([
-            ("id".into(), Value::Null),
+            ("id".into(), self.next_tag().into()),
             ("type".into(), method.into()),
             ("to".into(), Server::Whatsapp.address().into()),
             ("xmlns".into(), category.into())
         ]);
 
         self.send(Transmission::Node(
-            Node::new("iq".to_owned(), attributes, Node::serialize(body).unwrap())
+            Node::new("iq".to_owned(), attributes, Node::serialize(body).ok_or(Error::UnknownNode)?)
         )).await
     }
 
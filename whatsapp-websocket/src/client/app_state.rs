@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::{bail, Result};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::app_state::{Collection, Mutation, PendingMutation};
+use whatsapp_rs_util::model::chat_action::ChatAction;
+use whatsapp_rs_util::protobuf::whatsapp::{
+    ExternalBlobReference, MessageParser, SyncdMutations, SyncdPatch, SyncdSnapshot,
+};
+use whatsapp_rs_util::util::error::Error;
+
+use crate::client::handle::Handle;
+use crate::event::Event;
+
+/// Conflicting patches are built again this many times before we give up
+const MAX_PUSH_ATTEMPTS: u32 = 3;
+
+/// Makes sure only one task syncs a collection at a time and remembers the ones waiting for a key
+#[derive(Clone, Default)]
+pub struct AppStateSyncs {
+    locks: Arc<Mutex<HashMap<Collection, Arc<AsyncMutex<()>>>>>,
+    blocked: Arc<Mutex<HashSet<Collection>>>,
+}
+
+impl AppStateSyncs {
+    async fn lock(&self, collection: Collection) -> OwnedMutexGuard<()> {
+        let lock = self.locks.lock().unwrap_or_else(PoisonError::into_inner)
+            .entry(collection)
+            .or_default()
+            .clone();
+
+        lock.lock_owned().await
+    }
+
+    /// The collections whose patches need a key the phone hasn't shared yet, they're forgotten once taken
+    pub(crate) fn take_blocked(&self) -> Vec<Collection> {
+        self.blocked.lock().unwrap_or_else(PoisonError::into_inner).drain().collect()
+    }
+
+    fn block(&self, collection: Collection) {
+        self.blocked.lock().unwrap_or_else(PoisonError::into_inner).insert(collection);
+    }
+}
+
+impl Handle {
+    /// Fetches every patch of the collection we haven't applied yet, the first sync starts from a snapshot
+    ///
+    /// Every sync, including the ones of [Self::push_app_state], is emitted as [Event::AppStateSync] and [Event::ChatAction]s.
+    /// Collections that need a key we don't have yet are synced again as soon as the phone shares it.
+    pub async fn sync_app_state(&self, collection: Collection) -> Result<Vec<Mutation>> {
+        let _guard = self.shared.app_state_syncs.lock(collection).await;
+        self.sync_locked(collection).await
+    }
+
+    /// Pushes the mutations as a new patch, which is retried on top of the latest version if another device was faster
+    ///
+    /// Returns every mutation synced in the meantime, including our own.
+    pub async fn push_app_state(&self, collection: Collection, mutations: &[PendingMutation]) -> Result<Vec<Mutation>> {
+        let _guard = self.shared.app_state_syncs.lock(collection).await;
+        let mut synced = Vec::new();
+
+        // The patch has to be built on top of the whole collection
+        if self.shared.app_state.lock().state(collection).version == 0 {
+            synced.extend(self.sync_locked(collection).await?);
+        }
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+
+            let (version, patch) = {
+                let store = self.shared.app_state.lock();
+                (store.state(collection).version, store.encode_patch(collection, mutations)?)
+            };
+
+            let request = Node::with_children(
+                "collection".to_owned(),
+                HashMap::from([
+                    ("name".to_owned(), Value::String(collection.name().to_owned())),
+                    ("version".to_owned(), version.to_string().into()),
+                    ("return_snapshot".to_owned(), "false".into())
+                ]),
+                vec![Node::with_bytes("patch".to_owned(), patch.write_to_bytes()?)]
+            );
+
+            match self.query_collection(request).await {
+                Ok(_) => {
+                    synced.extend(self.sync_locked(collection).await?);
+                    return Ok(synced)
+                },
+
+                Err(error) if attempt < MAX_PUSH_ATTEMPTS && is_conflict(&error) => {
+                    synced.extend(self.sync_locked(collection).await?);
+                },
+
+                Err(error) => return Err(error)
+            }
+        }
+    }
+
+    /// Only called while holding the lock of the collection
+    async fn sync_locked(&self, collection: Collection) -> Result<Vec<Mutation>> {
+        let result = self.fetch_patches(collection).await;
+        if let Err(error) = &result {
+            if is_missing_key(error) {
+                self.shared.app_state_syncs.block(collection);
+            }
+        }
+
+        result
+    }
+
+    async fn fetch_patches(&self, collection: Collection) -> Result<Vec<Mutation>> {
+        let mut mutations = Vec::new();
+
+        loop {
+            let version = self.shared.app_state.lock().state(collection).version;
+            let response = self.fetch_collection(collection, version).await?;
+
+            if let Some(snapshot) = response.find_node("snapshot").and_then(|snapshot| snapshot.content_bytes()) {
+                let reference = ExternalBlobReference::parse_from_bytes(&snapshot)?;
+                let snapshot = SyncdSnapshot::parse_from_bytes(&self.download_media_data(&reference).await?)?;
+                let decoded = self.shared.app_state.lock().decode_snapshot(collection, &snapshot)?;
+                self.apply_synced(collection, &decoded);
+                mutations.extend(decoded);
+            }
+
+            let patches = response.find_node("patches").map(|patches| patches.nodes()).unwrap_or_default();
+            for patch in patches.iter().filter_map(Node::content_bytes) {
+                let patch = SyncdPatch::parse_from_bytes(&patch)?;
+
+                // The snapshot might already include the first patches
+                if patch.version.version() <= self.shared.app_state.lock().state(collection).version {
+                    continue
+                }
+
+                let patch_mutations = match patch.externalMutations.as_ref() {
+                    Some(reference) => SyncdMutations::parse_from_bytes(&self.download_media_data(reference).await?)?.mutations,
+                    None => patch.mutations.clone()
+                };
+
+                let decoded = self.shared.app_state.lock().decode_patch(collection, &patch, &patch_mutations)?;
+                self.apply_synced(collection, &decoded);
+                mutations.extend(decoded);
+            }
+
+            if response.attribute_str("has_more_patches") != Some("true") {
+                return Ok(mutations)
+            }
+        }
+    }
+
+    /// Every patch is emitted as soon as it's applied, so a later patch failing doesn't hide the earlier ones
+    fn apply_synced(&self, collection: Collection, mutations: &[Mutation]) {
+        if mutations.is_empty() {
+            return
+        }
+
+        let actions: Vec<(ChatAction, Option<i64>)> = mutations.iter()
+            .filter_map(|mutation| ChatAction::from_mutation(mutation).map(|action| (action, mutation.action.timestamp)))
+            .collect();
+
+        // The push name is sent along with our presence
+        let push_name = actions.iter().rev().find_map(|(action, _)| match action {
+            ChatAction::PushName { name } => Some(name.clone()),
+            _ => None
+        });
+
+        if let Some(name) = push_name {
+            *self.shared.push_name.lock().unwrap_or_else(PoisonError::into_inner) = Some(name);
+        }
+
+        self.emit(Event::AppStateSync { collection, mutations: mutations.to_vec() });
+        for (action, timestamp) in actions {
+            self.emit(Event::ChatAction { action, timestamp });
+        }
+    }
+
+    async fn fetch_collection(&self, collection: Collection, version: u64) -> Result<Node> {
+        self.query_collection(Node::from_attributes("collection".to_owned(), HashMap::from([
+            ("name".to_owned(), Value::String(collection.name().to_owned())),
+            ("version".to_owned(), version.to_string().into()),
+            ("return_snapshot".to_owned(), (version == 0).to_string().into())
+        ]))).await
+    }
+
+    async fn query_collection(&self, collection: Node) -> Result<Node> {
+        let request = Node::with_children("sync".to_owned(), HashMap::new(), vec![collection]);
+        let response = self.query("set", "w:sync:app:state", Value::Array(Node::serialize(request).into_iter().collect())).await?;
+        let Some(collection) = response.find_node("sync").and_then(|sync| sync.find_node("collection")) else {
+            bail!(Error::MalformedNode { tag: "sync".to_owned(), reason: "missing collection" })
+        };
+
+        // Every collection of the query fails on its own
+        if let Some(error) = collection.find_node("error") {
+            let code = error.attribute_str("code").and_then(|code| code.parse().ok()).unwrap_or_default();
+            let text = error.attribute_str("text").unwrap_or_default().to_owned();
+            bail!(Error::IqError { code, text })
+        }
+
+        Ok(collection)
+    }
+}
+
+/// The phone shares the key a patch was encrypted with shortly after, the collection is synced again then
+pub(crate) fn is_missing_key(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<Error>(), Some(Error::MissingAppStateKey(_) | Error::NoAppStateKeys))
+}
+
+/// The server rejects patches that don't follow its latest version
+fn is_conflict(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<Error>(), Some(Error::IqError { code: 409, .. }))
+}
@@ -0,0 +1,139 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::handshake::MessageField;
+use whatsapp_rs_util::model::app_state::{Collection, Mutation, PendingMutation};
+use whatsapp_rs_util::model::chat_action::MessageRef;
+use whatsapp_rs_util::model::ContactJid;
+use whatsapp_rs_util::protobuf::whatsapp::syncd_mutation::SyncdMutationSyncdOperation;
+use whatsapp_rs_util::protobuf::whatsapp::{
+    ArchiveChatAction, DeleteMessageForMeAction, LabelAssociationAction, LabelEditAction, MarkChatAsReadAction,
+    MuteAction, PinAction, StarAction, SyncActionValue,
+};
+
+use crate::client::handle::Handle;
+
+/// Changes our devices share through the app state, each returns the mutations synced while pushing it
+impl Handle {
+    /// Archiving a chat also unpins it, like the official clients do
+    pub async fn archive_chat(&self, chat: &ContactJid, archived: bool) -> Result<Vec<Mutation>> {
+        let mut mutations = vec![mutation(vec!["archive".to_owned(), chat.to_string()], 3, SyncActionValue {
+            archiveChatAction: MessageField::some(ArchiveChatAction { archived: archived.into(), ..Default::default() }),
+            ..Default::default()
+        })];
+
+        if archived {
+            mutations.push(pin_mutation(chat, false));
+        }
+
+        self.push_app_state(Collection::RegularLow, &mutations).await
+    }
+
+    pub async fn pin_chat(&self, chat: &ContactJid, pinned: bool) -> Result<Vec<Mutation>> {
+        self.push_app_state(Collection::RegularLow, &[pin_mutation(chat, pinned)]).await
+    }
+
+    /// Mutes the chat until the timestamp in milliseconds, -1 mutes it forever and [None] unmutes it
+    pub async fn mute_chat(&self, chat: &ContactJid, until: Option<i64>) -> Result<Vec<Mutation>> {
+        let value = SyncActionValue {
+            muteAction: MessageField::some(MuteAction {
+                muted: until.is_some().into(),
+                muteEndTimestamp: until,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        self.push_app_state(Collection::RegularHigh, &[mutation(vec!["mute".to_owned(), chat.to_string()], 2, value)]).await
+    }
+
+    pub async fn mark_chat_read(&self, chat: &ContactJid, read: bool) -> Result<Vec<Mutation>> {
+        let value = SyncActionValue {
+            markChatAsReadAction: MessageField::some(MarkChatAsReadAction { read: read.into(), ..Default::default() }),
+            ..Default::default()
+        };
+
+        self.push_app_state(Collection::RegularLow, &[mutation(vec!["markChatAsRead".to_owned(), chat.to_string()], 3, value)]).await
+    }
+
+    pub async fn star_message(&self, message: &MessageRef, starred: bool) -> Result<Vec<Mutation>> {
+        let value = SyncActionValue {
+            starAction: MessageField::some(StarAction { starred: starred.into(), ..Default::default() }),
+            ..Default::default()
+        };
+
+        self.push_app_state(Collection::RegularHigh, &[mutation(message_index("star", message), 2, value)]).await
+    }
+
+    /// Hides the message on our devices, the timestamp is the one of the message in seconds
+    pub async fn delete_message_for_me(
+        &self,
+        message: &MessageRef,
+        delete_media: bool,
+        message_timestamp: Option<i64>
+    ) -> Result<Vec<Mutation>> {
+        let value = SyncActionValue {
+            deleteMessageForMeAction: MessageField::some(DeleteMessageForMeAction {
+                deleteMedia: delete_media.into(),
+                messageTimestamp: message_timestamp,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        self.push_app_state(Collection::RegularHigh, &[mutation(message_index("deleteMessageForMe", message), 3, value)]).await
+    }
+
+    /// Creates or renames the label with the given id, the color is an index into the palette of the clients
+    pub async fn add_label(&self, label: &str, name: &str, color: i32) -> Result<Vec<Mutation>> {
+        let value = SyncActionValue {
+            labelEditAction: MessageField::some(LabelEditAction {
+                name: name.to_owned().into(),
+                color: color.into(),
+                deleted: false.into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        self.push_app_state(Collection::Regular, &[mutation(vec!["label_edit".to_owned(), label.to_owned()], 3, value)]).await
+    }
+
+    pub async fn assign_label(&self, label: &str, chat: &ContactJid, labeled: bool) -> Result<Vec<Mutation>> {
+        let value = SyncActionValue {
+            labelAssociationAction: MessageField::some(LabelAssociationAction { labeled: labeled.into(), ..Default::default() }),
+            ..Default::default()
+        };
+
+        let index = vec!["label_jid".to_owned(), label.to_owned(), chat.to_string()];
+        self.push_app_state(Collection::Regular, &[mutation(index, 3, value)]).await
+    }
+}
+
+fn pin_mutation(chat: &ContactJid, pinned: bool) -> PendingMutation {
+    let value = SyncActionValue {
+        pinAction: MessageField::some(PinAction { pinned: pinned.into(), ..Default::default() }),
+        ..Default::default()
+    };
+
+    mutation(vec!["pin_v1".to_owned(), chat.to_string()], 5, value)
+}
+
+fn message_index(action: &str, message: &MessageRef) -> Vec<String> {
+    let mut index = vec![action.to_owned()];
+    index.extend(message.index());
+    index
+}
+
+/// Every action is stamped with the time it was taken in milliseconds
+pub(crate) fn mutation(index: Vec<String>, version: i32, mut action: SyncActionValue) -> PendingMutation {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    action.timestamp = (now.as_millis() as i64).into();
+
+    PendingMutation {
+        operation: SyncdMutationSyncdOperation::SET,
+        index,
+        action,
+        version,
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::handshake::MessageField;
+use whatsapp_rs_util::binary::node::{Node, Value};
+use whatsapp_rs_util::model::ContactJid;
+use whatsapp_rs_util::model::ephemeral::EphemeralSetting;
+use whatsapp_rs_util::protobuf::whatsapp::{Conversation, Message};
+
+use crate::client::group::{nodes, GROUP_NAMESPACE};
+use crate::client::handle::Handle;
+use crate::client::message::now_ms;
+
+/// The disappearing messages timers of the chats with single contacts, the ones of groups are part of their metadata
+#[derive(Clone, Default)]
+pub struct EphemeralTimers(Arc<Mutex<HashMap<ContactJid, EphemeralSetting>>>);
+
+impl EphemeralTimers {
+    pub fn get(&self, chat: &ContactJid) -> Option<EphemeralSetting> {
+        self.lock().get(&chat.to_user()).copied()
+    }
+
+    pub fn set(&self, chat: &ContactJid, setting: EphemeralSetting) {
+        self.lock().insert(chat.to_user(), setting);
+    }
+
+    /// Remembers a setting we only saw in passing, unless we already know a newer one
+    pub fn learn(&self, chat: &ContactJid, setting: EphemeralSetting) {
+        let mut timers = self.lock();
+        if timers.get(&chat.to_user()).map_or(true, |known| setting.replaces(known)) {
+            timers.insert(chat.to_user(), setting);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<ContactJid, EphemeralSetting>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Handle {
+    /// Turns disappearing messages on for the chat, or off if the duration is 0.
+    /// Any duration is accepted, but the official clients only offer the ones in [whatsapp_rs_util::model::ephemeral]
+    pub async fn set_disappearing_timer(&self, chat: &ContactJid, duration: u32) -> Result<()> {
+        if chat.is_group() {
+            let request = match duration {
+                0 => Node::from_attributes("not_ephemeral".to_owned(), HashMap::new()),
+                duration => Node::from_attributes("ephemeral".to_owned(), HashMap::from([
+                    ("expiration".to_owned(), Value::String(duration.to_string()))
+                ]))
+            };
+
+            // The group cache is updated by the notification that follows
+            self.query_to("set", GROUP_NAMESPACE, chat.to_string(), nodes(vec![request])).await?;
+            return Ok(())
+        }
+
+        let setting = EphemeralSetting { duration, timestamp: Some(now_ms() / 1000) };
+        let message = Message { protocolMessage: MessageField::some(setting.protocol_message()), ..Default::default() };
+        self.send_message(chat, message).await?;
+        self.shared.ephemeral.set(chat, setting);
+        Ok(())
+    }
+
+    /// The timer of the chat as far as we know it, [None] if it's off or we haven't seen it yet
+    pub async fn disappearing_timer(&self, chat: &ContactJid) -> Result<Option<EphemeralSetting>> {
+        let setting = if chat.is_group() {
+            self.cached_group_metadata(chat).await?
+                .ephemeral_duration
+                .map(|duration| EphemeralSetting { duration, timestamp: None })
+        } else {
+            self.shared.ephemeral.get(chat)
+        };
+
+        Ok(setting.filter(EphemeralSetting::is_active))
+    }
+
+    /// Remembers the timers of the chats with single contacts the history names
+    pub(crate) fn learn_disappearing_timers(&self, conversations: &[Conversation]) {
+        for conversation in conversations {
+            let (Ok(chat), Some(setting)) = (conversation.id().parse::<ContactJid>(), EphemeralSetting::of_conversation(conversation)) else {
+                continue
+            };
+
+            if !chat.is_group() {
+                self.shared.ephemeral.learn(&chat, setting);
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+pub mod community;
+pub mod invite;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::group::{GroupChange, GroupMetadata, GroupUpdate, ParticipantChange, ParticipantResult};
+use whatsapp_rs_util::model::{ContactJid, Server};
+use whatsapp_rs_util::util::id;
+
+use crate::client::handle::Handle;
+
+pub(crate) const GROUP_NAMESPACE: &str = "w:g2";
+
+/// The metadata of the groups we queried, kept up to date by their notifications
+#[derive(Clone, Default)]
+pub struct GroupCache(Arc<Mutex<HashMap<ContactJid, GroupMetadata>>>);
+
+impl GroupCache {
+    pub fn get(&self, group: &ContactJid) -> Option<GroupMetadata> {
+        self.lock().get(&group.to_user()).cloned()
+    }
+
+    pub fn insert(&self, metadata: GroupMetadata) {
+        self.lock().insert(metadata.jid.to_user(), metadata);
+    }
+
+    /// Groups we don't know yet are only cached if the update contains their whole metadata
+    pub fn apply(&self, update: &GroupUpdate, own: Option<&ContactJid>) {
+        let mut groups = self.lock();
+        let group = update.group.to_user();
+
+        for change in &update.changes {
+            if let GroupChange::Created(metadata) = change {
+                groups.insert(group.clone(), (**metadata).clone());
+                continue
+            }
+
+            // Groups we left are no longer updated
+            let left = matches!(change, GroupChange::Participants { change: ParticipantChange::Remove, participants }
+                if own.map_or(false, |own| participants.iter().any(|jid| jid.user == own.user)));
+
+            if left {
+                groups.remove(&group);
+            } else if let Some(metadata) = groups.get_mut(&group) {
+                metadata.apply(change);
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<ContactJid, GroupMetadata>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Handle {
+    pub async fn create_group(&self, subject: &str, participants: &[ContactJid]) -> Result<GroupMetadata> {
+        self.create(subject, participants, Vec::new()).await
+    }
+
+    pub async fn group_metadata(&self, group: &ContactJid) -> Result<GroupMetadata> {
+        let query = Node::from_attributes("query".to_owned(), HashMap::from([
+            ("request".to_owned(), Value::String("interactive".to_owned()))
+        ]));
+
+        let response = self.query_to("get", GROUP_NAMESPACE, group.to_string(), nodes(vec![query])).await?;
+        let metadata = GroupMetadata::try_from(response)?;
+        self.shared.groups.insert(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// The metadata we already know, which is only queried if the group isn't cached yet
+    pub async fn cached_group_metadata(&self, group: &ContactJid) -> Result<GroupMetadata> {
+        match self.shared.groups.get(group) {
+            Some(metadata) => Ok(metadata),
+            None => self.group_metadata(group).await
+        }
+    }
+
+    /// Every group we're a participant of, including their participants and descriptions
+    pub async fn joined_groups(&self) -> Result<Vec<GroupMetadata>> {
+        let participating = Node::with_children("participating".to_owned(), HashMap::new(), vec![
+            Node::from_attributes("participants".to_owned(), HashMap::new()),
+            Node::from_attributes("description".to_owned(), HashMap::new())
+        ]);
+
+        let response = self.query_to("get", GROUP_NAMESPACE, Server::Group.address(), nodes(vec![participating])).await?;
+        let groups = response.find_node("groups").map(|groups| groups.nodes()).unwrap_or_default();
+
+        let groups = groups.into_iter()
+            .filter(|group| group.description() == "group")
+            .map(GroupMetadata::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for metadata in &groups {
+            self.shared.groups.insert(metadata.clone());
+        }
+
+        Ok(groups)
+    }
+
+    /// Applies the change to every participant, the result tells for whom it failed
+    pub async fn update_participants(
+        &self,
+        group: &ContactJid,
+        change: ParticipantChange,
+        participants: &[ContactJid]
+    ) -> Result<Vec<ParticipantResult>> {
+        let request = Node::with_children(
+            change.tag().to_owned(),
+            HashMap::new(),
+            participants.iter().map(participant_node).collect()
+        );
+
+        let response = self.query_to("set", GROUP_NAMESPACE, group.to_string(), nodes(vec![request])).await?;
+        let results = response.find_node(change.tag()).map(|results| results.nodes()).unwrap_or_default();
+
+        Ok(results.iter()
+            .filter(|result| result.description() == "participant")
+            .filter_map(ParticipantResult::of)
+            .collect())
+    }
+
+    pub async fn set_group_subject(&self, group: &ContactJid, subject: &str) -> Result<()> {
+        let request = Node::new("subject".to_owned(), HashMap::new(), Value::String(subject.to_owned()));
+        self.query_to("set", GROUP_NAMESPACE, group.to_string(), nodes(vec![request])).await?;
+        Ok(())
+    }
+
+    /// Replaces the description, [None] removes it
+    pub async fn set_group_description(&self, group: &ContactJid, description: Option<&str>) -> Result<()> {
+        // The server only accepts changes of the latest description
+        let previous = self.group_metadata(group).await?.description.map(|description| description.id);
+
+        let mut attributes = HashMap::from([("id".to_owned(), Value::String(id::message_id()))]);
+        if let Some(previous) = previous {
+            attributes.insert("prev".to_owned(), previous.into());
+        }
+
+        let request = match description {
+            Some(description) => Node::with_children("description".to_owned(), attributes, vec![
+                Node::new("body".to_owned(), HashMap::new(), Value::String(description.to_owned()))
+            ]),
+
+            None => {
+                attributes.insert("delete".to_owned(), "true".into());
+                Node::from_attributes("description".to_owned(), attributes)
+            }
+        };
+
+        self.query_to("set", GROUP_NAMESPACE, group.to_string(), nodes(vec![request])).await?;
+        Ok(())
+    }
+
+    /// Only admins can send messages to announcement groups
+    pub async fn set_group_announce(&self, group: &ContactJid, announce: bool) -> Result<()> {
+        let setting = if announce { "announcement" } else { "not_announcement" };
+        self.set_group_setting(group, setting).await
+    }
+
+    /// Only admins can edit the info of locked groups
+    pub async fn set_group_locked(&self, group: &ContactJid, locked: bool) -> Result<()> {
+        let setting = if locked { "locked" } else { "unlocked" };
+        self.set_group_setting(group, setting).await
+    }
+
+    pub async fn leave_group(&self, group: &ContactJid) -> Result<()> {
+        let leave = Node::with_children("leave".to_owned(), HashMap::new(), vec![
+            Node::from_attributes("group".to_owned(), HashMap::from([
+                ("id".to_owned(), Value::String(group.to_string()))
+            ]))
+        ]);
+
+        self.query_to("set", GROUP_NAMESPACE, Server::Group.address(), nodes(vec![leave])).await?;
+        Ok(())
+    }
+
+    /// Creates a group, the settings are added next to the participants
+    pub(crate) async fn create(&self, subject: &str, participants: &[ContactJid], settings: Vec<Node>) -> Result<GroupMetadata> {
+        let mut children: Vec<Node> = participants.iter().map(participant_node).collect();
+        children.extend(settings);
+
+        let create = Node::with_children(
+            "create".to_owned(),
+            HashMap::from([
+                ("subject".to_owned(), Value::String(subject.to_owned())),
+                ("key".to_owned(), id::message_id().into())
+            ]),
+            children
+        );
+
+        let response = self.query_to("set", GROUP_NAMESPACE, Server::Group.address(), nodes(vec![create])).await?;
+        let metadata = GroupMetadata::try_from(response)?;
+        self.shared.groups.insert(metadata.clone());
+        Ok(metadata)
+    }
+
+    async fn set_group_setting(&self, group: &ContactJid, setting: &str) -> Result<()> {
+        let request = Node::from_attributes(setting.to_owned(), HashMap::new());
+        self.query_to("set", GROUP_NAMESPACE, group.to_string(), nodes(vec![request])).await?;
+        Ok(())
+    }
+}
+
+pub(crate) fn participant_node(jid: &ContactJid) -> Node {
+    Node::from_attributes("participant".to_owned(), HashMap::from([
+        ("jid".to_owned(), Value::String(jid.to_user().to_string()))
+    ]))
+}
+
+pub(crate) fn nodes(nodes: Vec<Node>) -> Value {
+    Value::Array(nodes.into_iter().filter_map(Node::serialize).collect())
+}
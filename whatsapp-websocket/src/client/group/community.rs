@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::group::{GroupMetadata, SubGroup};
+use whatsapp_rs_util::model::ContactJid;
+
+use crate::client::group::{nodes, GROUP_NAMESPACE};
+use crate::client::handle::Handle;
+
+impl Handle {
+    /// Creates a community, new members have to be approved by an admin like in the official clients
+    pub async fn create_community(&self, subject: &str) -> Result<GroupMetadata> {
+        let parent = Node::from_attributes("parent".to_owned(), HashMap::from([
+            ("default_membership_approval_mode".to_owned(), Value::String("request_required".to_owned()))
+        ]));
+
+        self.create(subject, &[], vec![parent]).await
+    }
+
+    pub async fn link_group(&self, community: &ContactJid, group: &ContactJid) -> Result<()> {
+        let links = Node::with_children("links".to_owned(), HashMap::new(), vec![
+            Node::with_children(
+                "link".to_owned(),
+                HashMap::from([("link_type".to_owned(), Value::String("sub_group".to_owned()))]),
+                vec![group_node(group)]
+            )
+        ]);
+
+        self.query_to("set", GROUP_NAMESPACE, community.to_string(), nodes(vec![links])).await?;
+        Ok(())
+    }
+
+    pub async fn unlink_group(&self, community: &ContactJid, group: &ContactJid) -> Result<()> {
+        let unlink = Node::with_children(
+            "unlink".to_owned(),
+            HashMap::from([("unlink_type".to_owned(), Value::String("sub_group".to_owned()))]),
+            vec![group_node(group)]
+        );
+
+        self.query_to("set", GROUP_NAMESPACE, community.to_string(), nodes(vec![unlink])).await?;
+        Ok(())
+    }
+
+    /// The groups linked to the community, including its announcement group
+    pub async fn sub_groups(&self, community: &ContactJid) -> Result<Vec<SubGroup>> {
+        let request = Node::from_attributes("sub_groups".to_owned(), HashMap::new());
+        let response = self.query_to("get", GROUP_NAMESPACE, community.to_string(), nodes(vec![request])).await?;
+
+        let groups = response.find_node("sub_groups").map(|groups| groups.nodes()).unwrap_or_default();
+        Ok(groups.iter()
+            .filter(|group| group.description() == "group")
+            .filter_map(SubGroup::of)
+            .collect())
+    }
+
+    /// The community the group belongs to, if any
+    pub async fn parent_community(&self, group: &ContactJid) -> Result<Option<ContactJid>> {
+        Ok(self.cached_group_metadata(group).await?.linked_parent)
+    }
+}
+
+fn group_node(group: &ContactJid) -> Node {
+    Node::from_attributes("group".to_owned(), HashMap::from([
+        ("jid".to_owned(), Value::String(group.to_string()))
+    ]))
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::group::{self, GroupMetadata, JoinRequest, ParticipantResult};
+use whatsapp_rs_util::model::{ContactJid, Server};
+use whatsapp_rs_util::protobuf::whatsapp::GroupInviteMessage;
+use whatsapp_rs_util::util::error::Error;
+
+use crate::client::group::{nodes, participant_node, GROUP_NAMESPACE};
+use crate::client::handle::Handle;
+
+impl Handle {
+    /// The current invite link of the group, resetting it revokes the previous one
+    pub async fn group_invite_link(&self, group: &ContactJid, reset: bool) -> Result<String> {
+        let method = if reset { "set" } else { "get" };
+        let invite = Node::from_attributes("invite".to_owned(), HashMap::new());
+
+        let response = self.query_to(method, GROUP_NAMESPACE, group.to_string(), nodes(vec![invite])).await?;
+        let code = response.find_node("invite")
+            .and_then(|invite| invite.attribute_str("code").map(str::to_owned))
+            .ok_or_else(|| malformed("missing invite code"))?;
+
+        Ok(group::invite_link(&code))
+    }
+
+    /// Previews the group behind an invite link without joining it
+    pub async fn group_info_from_link(&self, link: &str) -> Result<GroupMetadata> {
+        let response = self.query_to("get", GROUP_NAMESPACE, Server::Group.address(), nodes(vec![invite_node(link)])).await?;
+        Ok(GroupMetadata::try_from(response)?)
+    }
+
+    /// Joins the group, which might only create a join request if the group requires approval
+    pub async fn join_group_with_link(&self, link: &str) -> Result<ContactJid> {
+        let response = self.query_to("set", GROUP_NAMESPACE, Server::Group.address(), nodes(vec![invite_node(link)])).await?;
+        joined_group(&response)
+    }
+
+    /// Accepts an invite that was sent to us as message, the inviter is the sender of the message
+    pub async fn accept_group_invite(&self, inviter: &ContactJid, invite: &GroupInviteMessage) -> Result<ContactJid> {
+        let group: ContactJid = invite.groupJid().parse()?;
+        let accept = Node::from_attributes("accept".to_owned(), HashMap::from([
+            ("code".to_owned(), Value::String(invite.inviteCode().to_owned())),
+            ("expiration".to_owned(), invite.inviteExpiration().to_string().into()),
+            ("admin".to_owned(), inviter.to_user().to_string().into())
+        ]));
+
+        let response = self.query_to("set", GROUP_NAMESPACE, group.to_string(), nodes(vec![accept])).await?;
+        // Older servers only acknowledge the accept
+        Ok(joined_group(&response).unwrap_or(group))
+    }
+
+    pub async fn group_join_requests(&self, group: &ContactJid) -> Result<Vec<JoinRequest>> {
+        let request = Node::from_attributes("membership_approval_requests".to_owned(), HashMap::new());
+        let response = self.query_to("get", GROUP_NAMESPACE, group.to_string(), nodes(vec![request])).await?;
+
+        let requests = response.find_node("membership_approval_requests").map(|requests| requests.nodes()).unwrap_or_default();
+        Ok(requests.iter()
+            .filter(|request| request.description() == "membership_approval_request")
+            .filter_map(JoinRequest::of)
+            .collect())
+    }
+
+    /// Approves or rejects the join requests, the result tells for whom it failed
+    pub async fn update_join_requests(
+        &self,
+        group: &ContactJid,
+        approve: bool,
+        participants: &[ContactJid]
+    ) -> Result<Vec<ParticipantResult>> {
+        let action = if approve { "approve" } else { "reject" };
+        let request = Node::with_children("membership_requests_action".to_owned(), HashMap::new(), vec![
+            Node::with_children(action.to_owned(), HashMap::new(), participants.iter().map(participant_node).collect())
+        ]);
+
+        let response = self.query_to("set", GROUP_NAMESPACE, group.to_string(), nodes(vec![request])).await?;
+        let results = response.find_node("membership_requests_action")
+            .and_then(|results| results.find_node(action))
+            .map(|results| results.nodes())
+            .unwrap_or_default();
+
+        Ok(results.iter()
+            .filter(|result| result.description() == "participant")
+            .filter_map(ParticipantResult::of)
+            .collect())
+    }
+}
+
+fn invite_node(link: &str) -> Node {
+    Node::from_attributes("invite".to_owned(), HashMap::from([
+        ("code".to_owned(), Value::String(group::invite_code(link).to_owned()))
+    ]))
+}
+
+fn joined_group(response: &Node) -> Result<ContactJid> {
+    let group = response.find_node("group").ok_or_else(|| malformed("missing group"))?;
+    let jid = group.attribute_jid("jid")
+        .or_else(|| group.attribute_str("id").map(group::group_jid))
+        .ok_or_else(|| malformed("missing group jid"))?;
+
+    Ok(jid)
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::MalformedNode { tag: "invite".to_owned(), reason }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::app_state::SharedAppStateStore;
+use whatsapp_rs_util::model::{ContactJid, Server};
+use whatsapp_rs_util::security::signal::SharedSignalStore;
+use whatsapp_rs_util::util::error::Error;
+use whatsapp_rs_util::util::id;
+
+use crate::client::app_state::AppStateSyncs;
+use crate::client::ephemeral::EphemeralTimers;
+use crate::client::group::GroupCache;
+use crate::client::media::MediaSession;
+use crate::client::message::{DeviceCache, RecentMessages};
+use crate::client::poll::PollStore;
+use crate::event::{Event, SharedEvents};
+
+pub(crate) enum Request {
+    /// Sends the node, the response is handed to the sender if there is one
+    Send {
+        node: Node,
+        response: Option<oneshot::Sender<Result<Node>>>,
+    },
+
+    /// Forgets the pending response of a request nobody waits for anymore
+    Cancel(String),
+}
+
+/// How long we wait for the response of a request, same as WhatsApp Web
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(75);
+
+/// The acknowledgement of the server for one of our stanzas
+#[derive(Clone, Debug)]
+pub struct ServerAck {
+    pub id: String,
+    pub timestamp: Option<u64>,
+}
+
+/// The stores the client shares with its handles, cloning it only clones the references
+#[derive(Clone)]
+pub(crate) struct SharedState {
+    pub(crate) events: SharedEvents,
+    pub(crate) signal: SharedSignalStore,
+    pub(crate) recent: RecentMessages,
+    pub(crate) device_lists: DeviceCache,
+    pub(crate) companion: Arc<Mutex<Option<ContactJid>>>,
+    pub(crate) app_state: SharedAppStateStore,
+    pub(crate) app_state_syncs: AppStateSyncs,
+    pub(crate) groups: GroupCache,
+    pub(crate) push_name: Arc<Mutex<Option<String>>>,
+    pub(crate) polls: PollStore,
+    pub(crate) ephemeral: EphemeralTimers,
+    pub(crate) media: MediaSession,
+}
+
+/// A cloneable handle to talk to the connected client from other tasks
+#[derive(Clone)]
+pub struct Handle {
+    requests: UnboundedSender<Request>,
+    pub(crate) shared: SharedState,
+}
+
+impl Handle {
+    pub(crate) fn new(requests: UnboundedSender<Request>, shared: SharedState) -> Self {
+        Self { requests, shared }
+    }
+
+    /// The jid of our companion device, known as soon as we logged in
+    pub fn own_jid(&self) -> Option<ContactJid> {
+        self.shared.companion.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Emits the event to whoever subscribed to the client, for work that finishes outside of the stream
+    pub(crate) fn emit(&self, event: Event) {
+        self.shared.events.emit(event);
+    }
+
+    /// Sends the node without waiting for any response
+    pub fn send(&self, node: Node) -> Result<()> {
+        self.requests.send(Request::Send { node, response: None })
+            .map_err(|_| Error::ConnectionClosed)?;
+
+        Ok(())
+    }
+
+    /// Sends the node and waits for the response with the same id (an iq result or a server ack)
+    /// Fails with [Error::Timeout] if the server doesn't answer in time
+    pub async fn request(&self, mut node: Node) -> Result<Node> {
+        // We need the id to forget the request once it timed out
+        let id = match node.attribute("id").and_then(Value::as_str) {
+            Some(id) => id.to_owned(),
+            None => {
+                let id = id::message_id();
+                node.set_attribute("id", id.clone());
+                id
+            }
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.requests.send(Request::Send { node, response: tx.into() })
+            .map_err(|_| Error::ConnectionClosed)?;
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(response) => response.map_err(|_| Error::ConnectionClosed)?,
+            Err(_) => {
+                // The connection might be gone already, then there's nothing left to forget
+                let _ = self.requests.send(Request::Cancel(id));
+                Err(Error::Timeout.into())
+            }
+        }
+    }
+
+    pub async fn query(&self, method: &str, category: &str, body: Value) -> Result<Node> {
+        self.query_to(method, category, Server::Whatsapp.address(), body).await
+    }
+
+    pub async fn query_to<T: Into<Value>>(&self, method: &str, category: &str, to: T, body: Value) -> Result<Node> {
+        let attributes = HashMap::from([
+            ("type".into(), method.into()),
+            ("to".into(), to.into()),
+            ("xmlns".into(), category.into())
+        ]);
+
+        self.request(Node::new("iq".to_owned(), attributes, body)).await
+    }
+
+    /// Sends a message stanza and resolves as soon as the server acknowledged it
+    pub async fn send_stanza(&self, mut message: Node) -> Result<ServerAck> {
+        if message.attribute("id").is_none() {
+            message.set_attribute("id", id::message_id());
+        }
+
+        let ack = self.request(message).await?;
+        let id = ack.attribute("id").and_then(Value::as_str).unwrap_or_default().to_owned();
+        let timestamp = ack.attribute("t")
+            .and_then(Value::as_str)
+            .and_then(|timestamp| timestamp.parse().ok());
+
+        Ok(ServerAck { id, timestamp })
+    }
+}
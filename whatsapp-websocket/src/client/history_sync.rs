@@ -0,0 +1,18 @@
+use std::io::Read;
+
+use anyhow::Result;
+use flate2::read::ZlibDecoder;
+use whatsapp_rs_util::protobuf::whatsapp::{HistorySync, HistorySyncNotification, MessageParser};
+
+use crate::client::handle::Handle;
+
+impl Handle {
+    /// Downloads, inflates and decodes a chunk of the history the phone announced
+    pub async fn download_history_sync(&self, notification: &HistorySyncNotification) -> Result<HistorySync> {
+        let compressed = self.download_media_data(notification).await?;
+
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut inflated)?;
+        Ok(HistorySync::parse_from_bytes(&inflated)?)
+    }
+}
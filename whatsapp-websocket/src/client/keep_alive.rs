@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::node::Node;
+
+use crate::client::WebSocketClient;
+
+#[derive(Copy, Clone, Debug)]
+pub struct KeepAlive {
+    /// How often a `w:p` ping is sent while the stream is connected
+    pub interval: Duration,
+
+    /// How long we wait past the interval for any frame before the connection is considered dead
+    pub timeout: Duration,
+}
+
+impl KeepAlive {
+    pub fn new(interval: Duration, timeout: Duration) -> Self {
+        Self { interval, timeout }
+    }
+
+    /// The server answers our pings, so it's quiet for at most the interval unless the connection is dead
+    pub fn deadline(&self, last_received: Instant) -> Instant {
+        last_received + self.interval + self.timeout
+    }
+
+    pub fn is_expired(&self, last_received: Instant) -> bool {
+        Instant::now() >= self.deadline(last_received)
+    }
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        // Same values as WhatsApp Web
+        Self::new(Duration::from_secs(30), Duration::from_secs(5))
+    }
+}
+
+impl WebSocketClient {
+    pub(crate) async fn ping(&mut self) -> Result<()> {
+        self.query(
+            "get",
+            "w:p",
+            Node::from_attributes("ping".to_owned(), HashMap::new())
+        ).await
+    }
+}
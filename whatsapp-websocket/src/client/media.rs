@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::Result;
+use tokio::io::AsyncReadExt;
+use whatsapp_rs_http::client::download::BodyReader;
+use whatsapp_rs_http::client::Client;
+use whatsapp_rs_util::binary::node::{Node, Value};
+use whatsapp_rs_util::model::media_connection::MediaConnection;
+use whatsapp_rs_util::model::message_info::MessageInfo;
+use whatsapp_rs_util::protobuf::media_message::DownloadableMessage;
+use whatsapp_rs_util::security::media;
+use whatsapp_rs_util::security::media::stream::MediaReader;
+use whatsapp_rs_util::util::error::Error;
+
+use crate::client::handle::Handle;
+
+/// The http client and the media connection every handle shares, the connection is reused until it expires
+#[derive(Clone, Default)]
+pub struct MediaSession {
+    http: Client,
+    connection: Arc<Mutex<Option<MediaConnection>>>,
+}
+
+impl Handle {
+    /// The hosts and the auth token for media transfers, only requested again once they expired
+    pub async fn media_connection(&self) -> Result<MediaConnection> {
+        if let Some(connection) = self.cached_media_connection() {
+            return Ok(connection)
+        }
+
+        let media_conn = Node::from_attributes("media_conn".to_owned(), HashMap::new());
+        let response = self.query("set", "w:m", Value::Array(Node::serialize(media_conn).into_iter().collect())).await?;
+
+        let connection = MediaConnection::try_from(response)?;
+        *self.shared.media.connection.lock().unwrap_or_else(PoisonError::into_inner) = connection.clone().into();
+        Ok(connection)
+    }
+
+    /// Drops the media connection, for when the server rejected its auth token before it expired
+    pub fn forget_media_connection(&self) {
+        self.shared.media.connection.lock().unwrap_or_else(PoisonError::into_inner).take();
+    }
+
+    /// The http client media is transferred with
+    pub fn http(&self) -> &Client {
+        &self.shared.media.http
+    }
+
+    /// Downloads the media file of the message, which is decrypted and verified as it's read.
+    /// The last read fails if the file doesn't match the message, see [MediaReader]
+    pub async fn download_media<M: DownloadableMessage>(&self, message: &M) -> Result<MediaReader<BodyReader>> {
+        let media_key = message.media_key().ok_or(Error::MediaIntegrity("missing media key"))?;
+        let reader = self.open_media(message).await?;
+
+        Ok(MediaReader::new(reader, media_key, M::MEDIA_TYPE, message.file_sha256(), message.file_enc_sha256()))
+    }
+
+    /// Downloads and verifies the whole file, for the files the client processes itself like history blobs
+    pub(crate) async fn download_media_data<M: DownloadableMessage>(&self, message: &M) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.download_media(message).await?.read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    /// Tries every media host before falling back to the url of the message
+    async fn open_media<M: DownloadableMessage>(&self, message: &M) -> Result<BodyReader> {
+        let mut urls = Vec::new();
+        if let Some(direct_path) = message.direct_path() {
+            let connection = self.media_connection().await?;
+            urls.extend(connection.hosts.iter().map(|host| format!("https://{}{}", host, direct_path)));
+        }
+
+        urls.extend(message.url().map(str::to_owned));
+        self.shared.media.http.download_any(&urls).await
+    }
+
+    fn cached_media_connection(&self) -> Option<MediaConnection> {
+        self.shared.media.connection.lock().unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .filter(|connection| !connection.is_expired())
+            .cloned()
+    }
+
+    /// Asks the phone to upload an expired media file again, the answer arrives as [crate::event::Event::MediaRetry]
+    pub fn request_media_retry(&self, info: &MessageInfo, media_key: &[u8]) -> Result<()> {
+        let own = self.own_jid().ok_or(Error::StreamNotInitialized)?;
+        let (ciphertext, iv) = media::encrypt_retry_request(media_key, &info.id)?;
+
+        let mut rmr = HashMap::from([
+            ("jid".to_owned(), Value::String(info.chat.to_string())),
+            ("from_me".to_owned(), info.from_me.to_string().into())
+        ]);
+
+        if info.chat != info.sender {
+            rmr.insert("participant".to_owned(), info.sender.to_string().into());
+        }
+
+        let receipt = Node::with_children(
+            "receipt".to_owned(),
+            HashMap::from([
+                ("id".to_owned(), Value::String(info.id.clone())),
+                ("to".to_owned(), own.to_user().to_string().into()),
+                ("type".to_owned(), "server-error".into())
+            ]),
+            vec![
+                Node::with_children("encrypt".to_owned(), HashMap::new(), vec![
+                    Node::with_bytes("enc_p".to_owned(), ciphertext),
+                    Node::with_bytes("enc_iv".to_owned(), iv),
+                ]),
+                Node::from_attributes("rmr".to_owned(), rmr)
+            ]
+        );
+
+        self.send(receipt)
+    }
+}
@@ -0,0 +1,298 @@
+pub mod edit;
+pub mod group;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::{ContactJid, Server};
+use whatsapp_rs_util::protobuf::message_wrapper::MessageWrapperExt;
+use whatsapp_rs_util::protobuf::poll_message::PollMessageExt;
+use whatsapp_rs_util::protobuf::whatsapp::{Message, MessageParser};
+use whatsapp_rs_util::security::signal::{self, SignalStore};
+use whatsapp_rs_util::util::error::Error;
+use whatsapp_rs_util::util::id;
+
+use crate::client::handle::{Handle, ServerAck};
+
+/// How many sent messages we keep to answer retry receipts
+const RECENT_MESSAGES: usize = 256;
+
+/// How long we trust the device list of a user before we query it again
+const DEVICE_LIST_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone)]
+pub struct RecentMessage {
+    pub id: String,
+    pub to: ContactJid,
+    pub message: Message,
+}
+
+/// The messages we sent lately, shared between the handles and the stream
+#[derive(Clone, Default)]
+pub struct RecentMessages(Arc<Mutex<VecDeque<RecentMessage>>>);
+
+impl RecentMessages {
+    pub fn push(&self, message: RecentMessage) {
+        let mut recent = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if recent.len() >= RECENT_MESSAGES {
+            recent.pop_front();
+        }
+
+        recent.push_back(message);
+    }
+
+    pub fn find(&self, id: &str) -> Option<RecentMessage> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find(|message| message.id == id)
+            .cloned()
+    }
+}
+
+/// The devices of the users we sent messages to, so not every message needs a usync
+#[derive(Clone, Default)]
+pub struct DeviceCache(Arc<Mutex<HashMap<ContactJid, (Instant, Vec<ContactJid>)>>>);
+
+impl DeviceCache {
+    pub fn get(&self, user: &ContactJid) -> Option<Vec<ContactJid>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+            .get(&user.to_user())
+            .filter(|(fetched, _)| fetched.elapsed() < DEVICE_LIST_TTL)
+            .map(|(_, devices)| devices.clone())
+    }
+
+    pub fn insert(&self, user: &ContactJid, devices: Vec<ContactJid>) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).insert(user.to_user(), (Instant::now(), devices));
+    }
+
+    /// The user linked or removed a device, so the list is queried again
+    pub fn invalidate(&self, user: &ContactJid) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).remove(&user.to_user());
+    }
+}
+
+impl Handle {
+    pub async fn send_message(&self, to: &ContactJid, message: Message) -> Result<ServerAck> {
+        self.send_message_with_id(to, message, id::message_id()).await
+    }
+
+    pub async fn send_message_with_id(&self, to: &ContactJid, message: Message, id: String) -> Result<ServerAck> {
+        self.send_message_with_edit(to, message, id, None).await
+    }
+
+    /// Sends the message with the edit attribute, which tells the server which earlier message it changes.
+    /// The content names the disappearing messages timer of the chat, if it has one
+    pub(crate) async fn send_message_with_edit(&self, to: &ContactJid, mut message: Message, id: String, edit: Option<&str>) -> Result<ServerAck> {
+        if let Some(setting) = self.disappearing_timer(to).await? {
+            setting.apply(&mut message);
+        }
+
+        let (mut stanza, distributed) = match to.server {
+            Server::Group => self.group_stanza(to, &message, &id).await?,
+            _ => (self.direct_stanza(to, &message, &id).await?, Vec::new())
+        };
+
+        if let Some(edit) = edit {
+            stanza.set_attribute("edit", edit);
+        }
+
+        self.shared.recent.push(RecentMessage { id, to: to.clone(), message });
+        let ack = self.send_stanza(stanza).await?;
+        if !distributed.is_empty() {
+            self.shared.signal.lock().sender_key_distributed(to, &distributed);
+        }
+
+        Ok(ack)
+    }
+
+    /// Lists every device of the user, including the phone itself (device 0)
+    pub async fn devices(&self, jid: &ContactJid) -> Result<Vec<ContactJid>> {
+        self.devices_of(&[jid.clone()]).await
+    }
+
+    /// Lists every device of all the users at once, only the users we don't know the devices of are queried
+    pub async fn devices_of(&self, jids: &[ContactJid]) -> Result<Vec<ContactJid>> {
+        let mut devices = Vec::new();
+        let mut missing = Vec::new();
+        for jid in jids {
+            match self.shared.device_lists.get(jid) {
+                Some(cached) => devices.extend(cached),
+                None => missing.push(jid.to_user())
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(devices)
+        }
+
+        let query = Node::from_attributes("devices".to_owned(), HashMap::from([("version".to_owned(), "2".into())]));
+        let users = self.usync("message", query, &missing).await?;
+
+        // Users the server doesn't answer for have no devices we could send to
+        let mut fetched: HashMap<ContactJid, Vec<ContactJid>> = missing.into_iter().map(|user| (user, Vec::new())).collect();
+        for user in users {
+            let Some(jid) = user.attribute_jid("jid") else {
+                continue
+            };
+
+            let device_list = user.find_node("devices")
+                .and_then(|devices| devices.find_node("device-list"))
+                .map(|list| list.nodes())
+                .unwrap_or_default();
+
+            fetched.entry(jid.to_user()).or_default().extend(device_list.iter()
+                .filter_map(|device| device.attribute_str("id")?.parse().ok())
+                .map(|device| ContactJid { device, ..jid.to_user() }));
+        }
+
+        for (user, user_devices) in fetched {
+            devices.extend(user_devices.iter().cloned());
+            self.shared.device_lists.insert(&user, user_devices);
+        }
+
+        Ok(devices)
+    }
+
+    async fn direct_stanza(&self, to: &ContactJid, message: &Message, id: &str) -> Result<Node> {
+        let devices = self.devices(to).await?;
+        self.ensure_sessions(&devices).await?;
+
+        let plaintext = signal::pad(message.write_to_bytes()?);
+        let mut signal = self.shared.signal.lock();
+        let participants = participant_nodes(&mut signal, &devices, &plaintext)?;
+        message_node(&signal, to, id, stanza_type(message), participants, None)
+    }
+
+    /// Queries the same information of every user, returns their <user> nodes
+    pub(crate) async fn usync(&self, context: &str, query: Node, jids: &[ContactJid]) -> Result<Vec<Node>> {
+        let users = jids.iter()
+            .map(|jid| Node::from_attributes("user".to_owned(), HashMap::from([("jid".to_owned(), jid.to_user().to_string().into())])))
+            .collect();
+
+        let usync = Node::with_children(
+            "usync".to_owned(),
+            HashMap::from([
+                ("sid".to_owned(), id::message_id().into()),
+                ("mode".to_owned(), "query".into()),
+                ("last".to_owned(), "true".into()),
+                ("index".to_owned(), "0".into()),
+                ("context".to_owned(), context.into())
+            ]),
+            vec![
+                Node::with_children("query".to_owned(), HashMap::new(), vec![query]),
+                Node::with_children("list".to_owned(), HashMap::new(), users)
+            ]
+        );
+
+        let response = self.query("get", "usync", Value::Array(Node::serialize(usync).into_iter().collect())).await?;
+        let users = response.find_node("usync")
+            .and_then(|usync| usync.find_node("list"))
+            .ok_or(Error::MalformedNode { tag: "usync".to_owned(), reason: "missing list" })?;
+
+        Ok(users.nodes())
+    }
+}
+
+/// Encrypts the message for every device we have a session with, the others won't receive it
+pub(crate) fn participant_nodes(signal: &mut SignalStore, devices: &[ContactJid], plaintext: &[u8]) -> Result<Vec<Node>> {
+    let mut participants = Vec::with_capacity(devices.len());
+    for device in devices {
+        if signal.has_session(device)? {
+            participants.push(participant_node(signal, device, plaintext, None)?);
+        }
+    }
+
+    Ok(participants)
+}
+
+/// Encrypts the message for a single device, as <to jid><enc/></to>
+pub(crate) fn participant_node(signal: &mut SignalStore, device: &ContactJid, plaintext: &[u8], retry: Option<u32>) -> Result<Node> {
+    let (kind, ciphertext) = signal.encrypt(device, plaintext)?;
+
+    Ok(Node::with_children(
+        "to".to_owned(),
+        HashMap::from([("jid".to_owned(), device.to_string().into())]),
+        vec![enc_node(kind, ciphertext, retry)]
+    ))
+}
+
+pub(crate) fn enc_node(kind: &str, ciphertext: Vec<u8>, retry: Option<u32>) -> Node {
+    let mut attributes = HashMap::from([
+        ("v".to_owned(), Value::String("2".to_owned())),
+        ("type".to_owned(), kind.into())
+    ]);
+
+    if let Some(count) = retry {
+        attributes.insert("count".to_owned(), count.to_string().into());
+    }
+
+    Node::new("enc".to_owned(), attributes, Value::from(ciphertext))
+}
+
+/// Wraps the encrypted participants into the message stanza, group messages carry their content next to them
+pub(crate) fn message_node(
+    signal: &SignalStore,
+    to: &ContactJid,
+    id: &str,
+    kind: &str,
+    participants: Vec<Node>,
+    content: Option<Node>
+) -> Result<Node> {
+    let includes_pre_key = participants.iter()
+        .flat_map(|participant| participant.nodes())
+        .any(|enc| enc.attribute_str("type") == Some("pkmsg"));
+
+    // Group messages to devices that all have our sender key already only carry the content
+    let mut children = Vec::new();
+    if !participants.is_empty() {
+        children.push(Node::with_children("participants".to_owned(), HashMap::new(), participants));
+    }
+
+    children.extend(content);
+
+    // Pre key messages are only accepted alongside our signed device identity
+    if includes_pre_key {
+        let device_identity = signal.device_identity.as_ref()
+            .ok_or(Error::UnsupportedCiphertext("missing device identity"))?;
+
+        children.push(Node::with_bytes("device-identity".to_owned(), device_identity));
+    }
+
+    Ok(Node::with_children(
+        "message".to_owned(),
+        HashMap::from([
+            ("id".to_owned(), id.into()),
+            ("to".to_owned(), to.to_string().into()),
+            ("type".to_owned(), kind.into())
+        ]),
+        children
+    ))
+}
+
+pub(crate) fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+/// The type names the content, whichever wrappers it's sent in
+pub(crate) fn stanza_type(message: &Message) -> &'static str {
+    let message = message.clone().unwrap_content().message;
+    if message.reactionMessage.is_some() {
+        return "reaction"
+    }
+
+    // Polls and their votes are newer than our schema, so they're only found in the unknown fields
+    if message.poll_creation().is_some() || message.poll_update().is_some() {
+        return "poll"
+    }
+
+    let is_media = message.imageMessage.is_some()
+        || message.videoMessage.is_some()
+        || message.audioMessage.is_some()
+        || message.documentMessage.is_some()
+        || message.stickerMessage.is_some();
+
+    if is_media { "media" } else { "text" }
+}
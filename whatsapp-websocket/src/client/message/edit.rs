@@ -0,0 +1,62 @@
+use anyhow::{bail, Result};
+use whatsapp_rs_util::binary::handshake::MessageField;
+use whatsapp_rs_util::model::ContactJid;
+use whatsapp_rs_util::protobuf::protocol_message::ProtocolMessageExt;
+use whatsapp_rs_util::protobuf::whatsapp::{Message, MessageKey, ProtocolMessage, ReactionMessage};
+use whatsapp_rs_util::util::id;
+
+use crate::client::handle::{Handle, ServerAck};
+use crate::client::message::now_ms;
+
+/// Messages that change an earlier one, which is named by its key as [whatsapp_rs_util::model::message_info::MessageInfo::key] builds it
+impl Handle {
+    pub async fn react(&self, key: &MessageKey, emoji: &str) -> Result<ServerAck> {
+        let message = Message {
+            reactionMessage: MessageField::some(ReactionMessage {
+                key: MessageField::some(key.clone()),
+                text: emoji.to_owned().into(),
+                senderTimestampMs: now_ms().into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        // Removals are sent like a revoke of our reaction
+        let edit = emoji.is_empty().then_some("7");
+        self.send_message_with_edit(&key.remoteJid().parse()?, message, id::message_id(), edit).await
+    }
+
+    pub async fn remove_reaction(&self, key: &MessageKey) -> Result<ServerAck> {
+        self.react(key, "").await
+    }
+
+    /// Replaces the content of one of our messages, the official clients only accept edits within 15 minutes
+    pub async fn edit_message(&self, key: &MessageKey, content: &Message) -> Result<ServerAck> {
+        if !key.fromMe() {
+            bail!("Only our own messages can be edited")
+        }
+
+        let message = Message {
+            protocolMessage: MessageField::some(ProtocolMessage::edit(key.clone(), content, now_ms())?),
+            ..Default::default()
+        };
+
+        self.send_message_with_edit(&key.remoteJid().parse()?, message, id::message_id(), Some("1")).await
+    }
+
+    /// Deletes the message for everyone, admins can also delete the messages of the other participants of their groups
+    pub async fn revoke(&self, key: &MessageKey) -> Result<ServerAck> {
+        let chat: ContactJid = key.remoteJid().parse()?;
+        if !key.fromMe() && key.participant.is_none() {
+            bail!("Messages of other participants are named by their participant")
+        }
+
+        let message = Message {
+            protocolMessage: MessageField::some(ProtocolMessage::revoke(key.clone())),
+            ..Default::default()
+        };
+
+        let edit = if key.fromMe() { "7" } else { "8" };
+        self.send_message_with_edit(&chat, message, id::message_id(), Some(edit)).await
+    }
+}
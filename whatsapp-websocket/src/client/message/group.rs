@@ -0,0 +1,61 @@
+use anyhow::Result;
+use whatsapp_rs_util::binary::handshake::MessageField;
+use whatsapp_rs_util::binary::node::Node;
+use whatsapp_rs_util::model::ContactJid;
+use whatsapp_rs_util::protobuf::whatsapp::{Message, MessageParser, SenderKeyDistributionMessage};
+use whatsapp_rs_util::security::signal;
+use whatsapp_rs_util::util::error::Error;
+
+use crate::client::handle::Handle;
+use crate::client::message::{enc_node, message_node, participant_nodes, stanza_type};
+
+impl Handle {
+    /// Group messages are encrypted once with our sender key, which only the devices that don't have it yet receive along with them
+    ///
+    /// Returns the devices the sender key is distributed to, they're remembered once the server accepted the message.
+    pub(crate) async fn group_stanza(&self, group: &ContactJid, message: &Message, id: &str) -> Result<(Node, Vec<ContactJid>)> {
+        let own = self.own_jid().ok_or(Error::StreamNotInitialized)?;
+        let participants: Vec<ContactJid> = self.cached_group_metadata(group).await?
+            .participants
+            .into_iter()
+            .map(|participant| participant.jid)
+            .collect();
+
+        // Our other devices need the sender key as well, but this one already has it
+        let devices: Vec<ContactJid> = self.devices_of(&participants).await?
+            .into_iter()
+            .filter(|device| device.user != own.user || device.device != own.device)
+            .collect();
+
+        let missing = self.shared.signal.lock().missing_sender_key(group, &own, &devices);
+        self.ensure_sessions(&missing).await?;
+
+        let mut signal = self.shared.signal.lock();
+        let (ciphertext, distribution) = signal.group_encrypt(group, &own, &signal::pad(message.write_to_bytes()?))?;
+        let content = enc_node("skmsg", ciphertext, None);
+
+        // Devices we couldn't start a session with get the key once we can
+        let mut recipients = Vec::with_capacity(missing.len());
+        for device in missing {
+            if signal.has_session(&device)? {
+                recipients.push(device);
+            }
+        }
+
+        if recipients.is_empty() {
+            return Ok((message_node(&signal, group, id, stanza_type(message), Vec::new(), Some(content))?, recipients))
+        }
+
+        let distribution = Message {
+            senderKeyDistributionMessage: MessageField::some(SenderKeyDistributionMessage {
+                groupId: group.to_string().into(),
+                axolotlSenderKeyDistributionMessage: distribution.into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let participants = participant_nodes(&mut signal, &recipients, &signal::pad(distribution.write_to_bytes()?))?;
+        Ok((message_node(&signal, group, id, stanza_type(message), participants, Some(content))?, recipients))
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::{bail, Result};
+use whatsapp_rs_util::binary::handshake::MessageField;
+use whatsapp_rs_util::model::message_info::MessageInfo;
+use whatsapp_rs_util::model::poll::Poll;
+use whatsapp_rs_util::model::ContactJid;
+use whatsapp_rs_util::protobuf::poll_message::{PollCreationMessage, PollMessageExt};
+use whatsapp_rs_util::protobuf::whatsapp::{Message, MessageKey};
+use whatsapp_rs_util::security::poll;
+use whatsapp_rs_util::util::error::Error;
+use whatsapp_rs_util::util::id;
+
+use crate::client::handle::{Handle, ServerAck};
+use crate::client::message::now_ms;
+
+/// How many polls we keep, the oldest one is forgotten first
+const POLLS: usize = 512;
+
+/// The polls whose secret we know, which are the ones we sent, received or restored
+#[derive(Clone, Default)]
+pub struct PollStore(Arc<Mutex<VecDeque<Poll>>>);
+
+impl PollStore {
+    pub fn get(&self, id: &str) -> Option<Poll> {
+        self.lock().iter().find(|poll| poll.id == id).cloned()
+    }
+
+    /// Remembers the poll of the message, if it is one that carries its secret
+    pub fn register(&self, info: &MessageInfo, message: &Message) {
+        let (Some(creation), Some(secret)) = (message.poll_creation(), message.message_secret()) else {
+            return
+        };
+
+        self.insert(Poll::new(info.id.clone(), info.chat.clone(), &info.sender, creation, secret));
+    }
+
+    /// Remembers the poll, replacing the one with the same id
+    pub fn insert(&self, poll: Poll) {
+        let mut polls = self.lock();
+        polls.retain(|known| known.id != poll.id);
+        if polls.len() >= POLLS {
+            polls.pop_front();
+        }
+
+        polls.push_back(poll);
+    }
+
+    /// Every poll we know, oldest first, to be restored with [PollStore::insert] after a restart
+    pub fn export(&self) -> Vec<Poll> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// Counts the vote of the message for the poll it names, returns the poll with every vote counted so far
+    pub fn apply_vote(&self, voter: &ContactJid, message: &Message) -> Option<(Poll, Vec<String>)> {
+        let update = message.poll_update()?;
+        let mut polls = self.lock();
+        let poll = polls.iter_mut().find(|poll| poll.id == update.poll_key.id())?;
+
+        // Votes we can't decrypt are for polls with another secret
+        let selected = poll.apply_vote(voter, &update).ok()?;
+        Some((poll.clone(), selected))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Poll>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Handle {
+    /// Sends a poll, whose votes are counted in the [crate::event::Event::PollVote]s
+    pub async fn send_poll(&self, chat: &ContactJid, name: &str, options: &[&str], selectable_count: u32) -> Result<ServerAck> {
+        let own = self.own_jid().ok_or(Error::StreamNotInitialized)?;
+        let creation = PollCreationMessage {
+            name: name.to_owned(),
+            options: options.iter().map(|option| option.to_string()).collect(),
+            selectable_count,
+        };
+
+        let mut message = Message::default();
+        message.set_poll_creation(&creation)?;
+        message.set_message_secret(poll::generate_secret().to_vec());
+
+        let id = id::message_id();
+        let info = MessageInfo {
+            id: id.clone(),
+            chat: chat.clone(),
+            sender: own.to_user(),
+            from_me: true,
+            timestamp: 0,
+            push_name: None,
+        };
+
+        self.shared.polls.register(&info, &message);
+        self.send_message_with_id(chat, message, id).await
+    }
+
+    /// Votes for the options with the given names, which replaces our previous vote
+    pub async fn vote_poll(&self, poll_key: &MessageKey, options: &[&str]) -> Result<ServerAck> {
+        let own = self.own_jid().ok_or(Error::StreamNotInitialized)?;
+        let Some(poll) = self.shared.polls.get(poll_key.id()) else {
+            bail!("The secret of the poll isn't known")
+        };
+
+        let mut update = poll.encrypt_vote(&own.to_user(), options)?;
+        update.poll_key = poll_key.clone();
+        update.sender_timestamp_ms = Some(now_ms());
+
+        let mut message = Message::default();
+        message.set_poll_update(&update)?;
+
+        // Our own votes aren't sent back to us
+        self.shared.polls.apply_vote(&own, &message);
+        self.send_message(&poll.chat, message).await
+    }
+
+    /// A poll we know the secret of, with every vote counted so far
+    pub fn poll(&self, id: &str) -> Option<Poll> {
+        self.shared.polls.get(id)
+    }
+
+    /// Every poll we know the secret of, to be handed to [crate::client::WebSocketClient::with_polls] after a restart
+    pub fn polls(&self) -> Vec<Poll> {
+        self.shared.polls.export()
+    }
+}
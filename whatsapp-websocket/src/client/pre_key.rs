@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::ContactJid;
+use whatsapp_rs_util::security::signal::{self, PreKey, PreKeyBundle, SignalStore};
+use whatsapp_rs_util::util::error::Error;
+
+use crate::client::handle::Handle;
+
+/// The server asks for new pre keys as soon as it has less than this
+const MIN_PRE_KEYS: u32 = 5;
+const UPLOADED_PRE_KEYS: usize = 30;
+
+impl Handle {
+    /// Uploads new one-time pre keys whenever the server is about to run out of them
+    pub async fn upload_pre_keys(&self) -> Result<()> {
+        let response = self.query(
+            "get",
+            "encrypt",
+            Value::Array(Node::serialize(Node::from_attributes("count".to_owned(), HashMap::new())).into_iter().collect())
+        ).await?;
+
+        let count = response.find_node("count")
+            .and_then(|count| count.attribute_str("value").and_then(|value| value.parse().ok()))
+            .unwrap_or_default();
+
+        if count >= MIN_PRE_KEYS {
+            return Ok(())
+        }
+
+        let children = {
+            let mut signal = self.shared.signal.lock();
+            let pre_keys = signal.generate_pre_keys(UPLOADED_PRE_KEYS)?;
+            let mut children = identity_nodes(&signal)?;
+
+            children.push(Node::with_children(
+                "list".to_owned(),
+                HashMap::new(),
+                pre_keys.iter().map(pre_key_node).collect()
+            ));
+            children.push(signed_pre_key_node(&signal)?);
+            children
+        };
+
+        self.query(
+            "set",
+            "encrypt",
+            Value::Array(children.into_iter().filter_map(Node::serialize).collect())
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Fetches the bundles of the devices we have no session with and starts a session with each of them
+    pub async fn ensure_sessions(&self, devices: &[ContactJid]) -> Result<()> {
+        let missing: Vec<&ContactJid> = {
+            let signal = self.shared.signal.lock();
+            devices.iter()
+                .filter(|device| !signal.has_session(device).unwrap_or(false))
+                .collect()
+        };
+
+        if missing.is_empty() {
+            return Ok(())
+        }
+
+        let users = missing.iter()
+            .map(|device| Node::from_attributes(
+                "user".to_owned(),
+                HashMap::from([("jid".to_owned(), device.to_string().into())])
+            ))
+            .collect();
+
+        let key = Node::with_children("key".to_owned(), HashMap::new(), users);
+        let response = self.query(
+            "get",
+            "encrypt",
+            Value::Array(Node::serialize(key).into_iter().collect())
+        ).await?;
+
+        let list = response.find_node("list")
+            .ok_or(Error::MalformedNode { tag: "iq".to_owned(), reason: "missing list" })?;
+
+        let mut signal = self.shared.signal.lock();
+        for user in list.nodes() {
+            let Some(jid) = user.attribute_jid("jid") else {
+                continue
+            };
+
+            // Devices without keys are skipped, they won't receive the message
+            if user.find_node("error").is_some() {
+                continue
+            }
+
+            signal.process_bundle(&jid, &parse_bundle(&jid, &user)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Registration id, key type and identity, as they introduce every key upload
+pub(crate) fn identity_nodes(signal: &SignalStore) -> Result<Vec<Node>> {
+    Ok(vec![
+        Node::with_bytes("registration".to_owned(), signal.registration_id()?.to_be_bytes()),
+        Node::with_bytes("type".to_owned(), [5u8]),
+        Node::with_bytes("identity".to_owned(), signal.identity_public()?),
+    ])
+}
+
+pub(crate) fn pre_key_node(pre_key: &PreKey) -> Node {
+    Node::with_children("key".to_owned(), HashMap::new(), vec![
+        Node::with_bytes("id".to_owned(), &pre_key.id.to_be_bytes()[1..]),
+        Node::with_bytes("value".to_owned(), pre_key.public),
+    ])
+}
+
+pub(crate) fn signed_pre_key_node(signal: &SignalStore) -> Result<Node> {
+    let (id, public, signature) = signal.signed_pre_key()?;
+
+    Ok(Node::with_children("skey".to_owned(), HashMap::new(), vec![
+        Node::with_bytes("id".to_owned(), &id.to_be_bytes()[1..]),
+        Node::with_bytes("value".to_owned(), public),
+        Node::with_bytes("signature".to_owned(), signature),
+    ]))
+}
+
+/// Parses the keys of a device, either from a key query or from a retry receipt
+pub(crate) fn parse_bundle(jid: &ContactJid, keys: &Node) -> Result<PreKeyBundle> {
+    let registration = bytes(keys, "registration")?;
+    let identity = bytes(keys, "identity")?;
+
+    let Some(signed) = keys.find_node("skey") else {
+        bail!(malformed("missing skey"))
+    };
+
+    let pre_key = match keys.find_node("key") {
+        Some(key) => Some((to_u32(&bytes(&key, "id")?), bytes(&key, "value")?)),
+        None => None
+    };
+
+    signal::bundle(
+        jid,
+        to_u32(&registration),
+        &identity,
+        (to_u32(&bytes(&signed, "id")?), &bytes(&signed, "value")?, &bytes(&signed, "signature")?),
+        pre_key.as_ref().map(|(id, value)| (*id, value.as_slice()))
+    )
+}
+
+fn bytes(node: &Node, description: &str) -> Result<Vec<u8>> {
+    Ok(node.find_node(description)
+        .and_then(|child| child.content_bytes())
+        .ok_or_else(|| malformed("missing key material"))?)
+}
+
+/// Ids are sent as big endian integers of three or four bytes
+fn to_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |result, byte| result << 8 | *byte as u32)
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::MalformedNode { tag: "keys".to_owned(), reason }
+}
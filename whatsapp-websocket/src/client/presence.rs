@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+use std::sync::PoisonError;
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::node::{Node, Value};
+use whatsapp_rs_util::model::presence::{ChatState, Presence};
+use whatsapp_rs_util::model::ContactJid;
+
+use crate::client::handle::Handle;
+
+impl Handle {
+    /// Our contacts only see us online with our push name, which is known once the login succeeded
+    pub fn send_presence(&self, presence: Presence) -> Result<()> {
+        let mut attributes = HashMap::from([("type".to_owned(), Value::String(presence.tag().to_owned()))]);
+        if let Some(name) = self.shared.push_name.lock().unwrap_or_else(PoisonError::into_inner).clone() {
+            attributes.insert("name".to_owned(), name.into());
+        }
+
+        self.send(Node::from_attributes("presence".to_owned(), attributes))
+    }
+
+    /// The presence of the contact is sent as [crate::event::Event::Presence] until we disconnect
+    pub fn subscribe_presence(&self, jid: &ContactJid) -> Result<()> {
+        self.send(Node::from_attributes("presence".to_owned(), HashMap::from([
+            ("type".to_owned(), Value::String("subscribe".to_owned())),
+            ("to".to_owned(), jid.to_user().to_string().into())
+        ])))
+    }
+
+    pub fn send_chat_state(&self, chat: &ContactJid, state: ChatState) -> Result<()> {
+        self.send(Node::with_children(
+            "chatstate".to_owned(),
+            HashMap::from([("to".to_owned(), Value::String(chat.to_user().to_string()))]),
+            vec![state.node()]
+        ))
+    }
+}
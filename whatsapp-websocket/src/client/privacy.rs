@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::privacy::{self, BlockAction, BlocklistChange, PrivacySetting, PrivacyValue};
+use whatsapp_rs_util::model::ContactJid;
+
+use crate::client::group::nodes;
+use crate::client::handle::Handle;
+
+impl Handle {
+    pub async fn privacy_settings(&self) -> Result<HashMap<PrivacySetting, PrivacyValue>> {
+        let request = Node::from_attributes("privacy".to_owned(), HashMap::new());
+        let response = self.query("get", "privacy", nodes(vec![request])).await?;
+        Ok(response.find_node("privacy").as_ref().map(privacy::privacy_settings).unwrap_or_default())
+    }
+
+    pub async fn set_privacy_setting(&self, setting: PrivacySetting, value: PrivacyValue) -> Result<()> {
+        let request = Node::with_children("privacy".to_owned(), HashMap::new(), vec![
+            Node::from_attributes("category".to_owned(), HashMap::from([
+                ("name".to_owned(), Value::String(setting.name().to_owned())),
+                ("value".to_owned(), value.name().into())
+            ]))
+        ]);
+
+        self.query("set", "privacy", nodes(vec![request])).await?;
+        Ok(())
+    }
+
+    /// Changes every setting that differs from the expected one, returns the ones that were changed
+    pub async fn enforce_privacy_settings(&self, expected: &HashMap<PrivacySetting, PrivacyValue>) -> Result<Vec<PrivacySetting>> {
+        let current = self.privacy_settings().await?;
+
+        let mut changed = Vec::new();
+        for (setting, value) in expected {
+            if current.get(setting) == Some(value) {
+                continue
+            }
+
+            self.set_privacy_setting(*setting, *value).await?;
+            changed.push(*setting);
+        }
+
+        Ok(changed)
+    }
+
+    /// Every contact we blocked
+    pub async fn blocklist(&self) -> Result<Vec<ContactJid>> {
+        let response = self.query("get", "blocklist", Value::Null).await?;
+        let items = response.find_node("list").map(|list| list.nodes()).unwrap_or_default();
+
+        Ok(items.iter()
+            .filter_map(BlocklistChange::of)
+            .map(|change| change.jid)
+            .collect())
+    }
+
+    pub async fn block(&self, jid: &ContactJid) -> Result<()> {
+        self.update_blocklist(jid, BlockAction::Block).await
+    }
+
+    pub async fn unblock(&self, jid: &ContactJid) -> Result<()> {
+        self.update_blocklist(jid, BlockAction::Unblock).await
+    }
+
+    /// The timer new chats start with in seconds, 0 turns disappearing messages off
+    pub async fn set_default_disappearing_timer(&self, duration: u32) -> Result<()> {
+        let request = Node::from_attributes("disappearing_mode".to_owned(), HashMap::from([
+            ("duration".to_owned(), Value::String(duration.to_string()))
+        ]));
+
+        self.query("set", "disappearing_mode", nodes(vec![request])).await?;
+        Ok(())
+    }
+
+    async fn update_blocklist(&self, jid: &ContactJid, action: BlockAction) -> Result<()> {
+        let item = Node::from_attributes("item".to_owned(), HashMap::from([
+            ("action".to_owned(), Value::String(action.tag().to_owned())),
+            ("jid".to_owned(), jid.to_user().to_string().into())
+        ]));
+
+        self.query("set", "blocklist", nodes(vec![item])).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::sync::PoisonError;
+
+use anyhow::Result;
+use whatsapp_rs_util::binary::handshake::MessageField;
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::app_state::{Collection, Mutation};
+use whatsapp_rs_util::model::profile::{BusinessProfile, PictureKind, ProfilePicture, Status, VerifiedName};
+use whatsapp_rs_util::model::{ContactJid, Server};
+use whatsapp_rs_util::protobuf::whatsapp::{PushNameSetting, SyncActionValue};
+use whatsapp_rs_util::util::error::Error;
+
+use crate::client::chat_action::mutation;
+use crate::client::group::nodes;
+use crate::client::handle::Handle;
+
+const PICTURE_NAMESPACE: &str = "w:profile:picture";
+
+impl Handle {
+    /// The picture of a contact or group, [None] if there's none or its privacy settings hide it from us
+    pub async fn profile_picture_url(&self, jid: &ContactJid, kind: PictureKind) -> Result<Option<ProfilePicture>> {
+        let picture = Node::from_attributes("picture".to_owned(), HashMap::from([
+            ("type".to_owned(), Value::String(kind.tag().to_owned())),
+            ("query".to_owned(), "url".into())
+        ]));
+
+        match self.query_picture("get", Some(jid), nodes(vec![picture])).await {
+            Ok(response) => Ok(response.find_node("picture").as_ref().and_then(ProfilePicture::of)),
+            Err(error) if matches!(error.downcast_ref::<Error>(), Some(Error::IqError { code: 401 | 404, .. })) => Ok(None),
+            Err(error) => Err(error)
+        }
+    }
+
+    /// Replaces our picture, which has to be a square jpeg (usually 640x640). Returns the id of the new picture
+    pub async fn set_profile_picture(&self, jpeg: Vec<u8>) -> Result<Option<String>> {
+        let picture = Node::new(
+            "picture".to_owned(),
+            HashMap::from([("type".to_owned(), Value::String("image".to_owned()))]),
+            Value::from(jpeg)
+        );
+
+        let response = self.query_picture("set", None, nodes(vec![picture])).await?;
+        Ok(response.find_node("picture").and_then(|picture| picture.attribute_str("id").map(str::to_owned)))
+    }
+
+    pub async fn remove_profile_picture(&self) -> Result<()> {
+        self.query_picture("set", None, Value::Null).await?;
+        Ok(())
+    }
+
+    /// The about text of the contact, [None] if it's hidden from us
+    pub async fn status(&self, jid: &ContactJid) -> Result<Option<Status>> {
+        let query = Node::from_attributes("status".to_owned(), HashMap::new());
+        let users = self.usync("interactive", query, &[jid.clone()]).await?;
+
+        Ok(users.iter()
+            .filter_map(|user| user.find_node("status"))
+            .find_map(|status| Status::of(&status)))
+    }
+
+    pub async fn set_status(&self, text: &str) -> Result<()> {
+        let status = Node::new("status".to_owned(), HashMap::new(), Value::String(text.to_owned()));
+        self.query("set", "status", nodes(vec![status])).await?;
+        Ok(())
+    }
+
+    /// Our name as our contacts see it, which is synced to our other devices through the app state
+    pub async fn set_push_name(&self, name: &str) -> Result<Vec<Mutation>> {
+        let value = SyncActionValue {
+            pushNameSetting: MessageField::some(PushNameSetting { name: name.to_owned().into(), ..Default::default() }),
+            ..Default::default()
+        };
+
+        let mutations = self.push_app_state(Collection::CriticalBlock, &[mutation(vec!["setting_pushName".to_owned()], 1, value)]).await?;
+        *self.shared.push_name.lock().unwrap_or_else(PoisonError::into_inner) = Some(name.to_owned());
+        Ok(mutations)
+    }
+
+    /// The profile of a business account, [None] for regular accounts
+    pub async fn business_profile(&self, jid: &ContactJid) -> Result<Option<BusinessProfile>> {
+        let request = Node::with_children(
+            "business_profile".to_owned(),
+            HashMap::from([("v".to_owned(), Value::String("244".to_owned()))]),
+            vec![Node::from_attributes("profile".to_owned(), HashMap::from([
+                ("jid".to_owned(), Value::String(jid.to_user().to_string()))
+            ]))]
+        );
+
+        let response = self.query("get", "w:biz", nodes(vec![request])).await?;
+        Ok(response.find_node("business_profile")
+            .and_then(|profile| profile.find_node("profile"))
+            .as_ref()
+            .and_then(BusinessProfile::of))
+    }
+
+    /// The verified name of a business account, checked against the identity of its phone if we have a session with it
+    pub async fn verified_name(&self, jid: &ContactJid) -> Result<Option<VerifiedName>> {
+        let query = Node::with_children("business".to_owned(), HashMap::new(), vec![
+            Node::from_attributes("verified_name".to_owned(), HashMap::new())
+        ]);
+
+        let users = self.usync("interactive", query, &[jid.clone()]).await?;
+        let Some(certificate) = users.iter()
+            .filter_map(|user| user.find_node("business"))
+            .find_map(|business| business.find_node("verified_name")) else {
+            return Ok(None)
+        };
+
+        // Accounts without a verified name answer with an empty node
+        if !certificate.has_content() {
+            return Ok(None)
+        }
+
+        let identity = self.shared.signal.lock().identity(&jid.to_user())?;
+        Ok(Some(VerifiedName::of(&certificate, identity.as_ref())?))
+    }
+
+    /// Pictures of users are addressed through the target, the ones of groups directly
+    async fn query_picture(&self, method: &str, jid: Option<&ContactJid>, body: Value) -> Result<Node> {
+        let mut attributes = HashMap::from([
+            ("type".to_owned(), Value::String(method.to_owned())),
+            ("xmlns".to_owned(), PICTURE_NAMESPACE.into()),
+            ("to".to_owned(), Server::Whatsapp.address().into())
+        ]);
+
+        match jid {
+            Some(jid) if jid.is_group() => {
+                attributes.insert("to".to_owned(), jid.to_string().into());
+            },
+
+            Some(jid) => {
+                attributes.insert("target".to_owned(), jid.to_user().to_string().into());
+            },
+
+            None => {}
+        }
+
+        self.request(Node::new("iq".to_owned(), attributes, body)).await
+    }
+}
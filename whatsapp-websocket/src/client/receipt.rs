@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use whatsapp_rs_util::binary::node::{Node, Value};
+use whatsapp_rs_util::model::{ContactJid, Server};
+use whatsapp_rs_util::model::receipt::ReceiptKind;
+
+use crate::client::handle::Handle;
+
+impl Handle {
+    /// Marks the messages of the sender as read, the sender is only needed in groups
+    pub fn mark_read(&self, chat: &ContactJid, sender: Option<&ContactJid>, ids: &[String]) -> Result<()> {
+        self.send_receipt(chat, sender, ids, ReceiptKind::Read)
+    }
+
+    /// Marks voice notes and videos of the sender as played
+    pub fn mark_played(&self, chat: &ContactJid, sender: Option<&ContactJid>, ids: &[String]) -> Result<()> {
+        self.send_receipt(chat, sender, ids, ReceiptKind::Played)
+    }
+
+    pub fn send_receipt(&self, chat: &ContactJid, participant: Option<&ContactJid>, ids: &[String], kind: ReceiptKind) -> Result<()> {
+        self.send(receipt_node(chat, participant, ids, kind)?)
+    }
+}
+
+/// Builds a receipt for one or more messages of the same chat and sender
+pub(crate) fn receipt_node(chat: &ContactJid, participant: Option<&ContactJid>, ids: &[String], kind: ReceiptKind) -> Result<Node> {
+    let Some((first, remaining)) = ids.split_first() else {
+        bail!("A receipt needs at least one message id")
+    };
+
+    let mut attributes = HashMap::from([("id".to_owned(), Value::String(first.clone()))]);
+
+    if matches!(kind, ReceiptKind::Read | ReceiptKind::ReadSelf) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        attributes.insert("t".to_owned(), timestamp.to_string().into());
+    }
+
+    match participant {
+        // Sender receipts of direct chats are addressed the other way around
+        Some(participant) if kind == ReceiptKind::Sender && chat.server != Server::Group => {
+            attributes.insert("recipient".to_owned(), chat.to_string().into());
+            attributes.insert("to".to_owned(), participant.to_string().into());
+        },
+
+        participant => {
+            attributes.insert("to".to_owned(), chat.to_string().into());
+            if let Some(participant) = participant {
+                attributes.insert("participant".to_owned(), participant.to_string().into());
+            }
+        }
+    }
+
+    if let Some(kind) = kind.as_str() {
+        attributes.insert("type".to_owned(), kind.into());
+    }
+
+    let content = match remaining {
+        [] => Value::Null,
+        remaining => {
+            let items = remaining.iter()
+                .map(|id| Node::from_attributes("item".to_owned(), HashMap::from([("id".to_owned(), id.clone().into())])))
+                .filter_map(Node::serialize)
+                .collect();
+
+            let list = Node::new("list".to_owned(), HashMap::new(), Value::Array(items));
+            Value::Array(Node::serialize(list).into_iter().collect())
+        }
+    };
+
+    Ok(Node::new("receipt".to_owned(), attributes, content))
+}
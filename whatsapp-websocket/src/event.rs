@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use whatsapp_rs_util::model::ContactJid;
+use whatsapp_rs_util::model::app_state::{Collection, Mutation};
+use whatsapp_rs_util::model::chat_action::ChatAction;
+use whatsapp_rs_util::model::ephemeral::EphemeralSetting;
+use whatsapp_rs_util::model::group::GroupUpdate;
+use whatsapp_rs_util::model::poll::Poll;
+use whatsapp_rs_util::model::presence::ChatState;
+use whatsapp_rs_util::model::privacy::{BlocklistChange, PrivacyToken};
+use whatsapp_rs_util::model::message_info::MessageInfo;
+use whatsapp_rs_util::model::receipt::ReceiptKind;
+use whatsapp_rs_util::protobuf::whatsapp::history_sync::HistorySyncHistorySyncType;
+use whatsapp_rs_util::protobuf::whatsapp::{Conversation, Message, MessageKey};
+pub use crate::stream::digest::error::StreamError;
+
+/// Everything that happens on the stream and might be of interest for the application
+#[derive(Clone, Debug)]
+pub enum Event {
+	StreamError(StreamError),
+
+	/// The content of the message, without the wrappers it arrived in
+	Message {
+		info: MessageInfo,
+		message: Box<Message>,
+
+		/// The media can only be opened once
+		view_once: bool,
+
+		/// Sent to a chat with disappearing messages by an older client
+		ephemeral: bool,
+
+		/// The content arrived in the wrapper of an edit, without a protocol message naming what it edits
+		edit: bool,
+
+		/// A document whose caption arrived next to it
+		document_with_caption: bool,
+
+		/// Another device of ours sent the message, to the chat named by the destination
+		sent_by_self_device: bool,
+		destination: Option<ContactJid>,
+	},
+
+	/// A message we couldn't decrypt even after the sender encrypted it again, its content is lost
+	UndecryptableMessage {
+		info: MessageInfo,
+	},
+
+	/// A reaction to an earlier message, the emoji is [None] if the sender removed their reaction
+	Reaction {
+		info: MessageInfo,
+		key: MessageKey,
+		emoji: Option<String>,
+		timestamp_ms: Option<i64>,
+	},
+
+	/// The new content of an earlier message
+	MessageEdit {
+		info: MessageInfo,
+		key: MessageKey,
+		message: Box<Message>,
+		timestamp_ms: Option<i64>,
+	},
+
+	/// An earlier message was deleted for everyone, either by its sender or by an admin of the group
+	MessageRevoke {
+		info: MessageInfo,
+		key: MessageKey,
+	},
+
+	/// A vote for a poll we know the secret of, the poll includes every vote counted so far
+	PollVote {
+		info: MessageInfo,
+		poll: Poll,
+
+		/// The options the voter selected, which replace their previous vote
+		selected: Vec<String>,
+	},
+
+	Receipt {
+		ids: Vec<String>,
+		from: ContactJid,
+		participant: Option<ContactJid>,
+		kind: ReceiptKind,
+		timestamp: Option<u64>,
+	},
+
+	/// Only sent for contacts we subscribed to
+	Presence {
+		jid: ContactJid,
+		available: bool,
+
+		/// Seconds since the epoch, unless the contact hides it
+		last_seen: Option<u64>,
+	},
+
+	ChatState {
+		chat: ContactJid,
+
+		/// Who is typing in a group
+		participant: Option<ContactJid>,
+		state: ChatState,
+	},
+
+	PrivacyToken(PrivacyToken),
+
+	/// Contacts another device of ours blocked or unblocked
+	Blocklist(Vec<BlocklistChange>),
+
+	/// A chunk of the chats the phone had before we were paired
+	HistorySync {
+		kind: HistorySyncHistorySyncType,
+		progress: Option<u32>,
+		conversations: Vec<Conversation>,
+	},
+
+	/// A chunk of the history we couldn't download, the phone doesn't offer it again
+	HistorySyncFailed {
+		id: String,
+		reason: String,
+	},
+
+	/// The decrypted mutations of a collection, starting with the whole snapshot on the first sync
+	AppStateSync {
+		collection: Collection,
+		mutations: Vec<Mutation>,
+	},
+
+	/// A collection failed to sync for another reason than a missing key, it's synced again with the next change
+	AppStateSyncFailed {
+		collection: Collection,
+		reason: String,
+	},
+
+	/// A mutation of the app state we understood, emitted after the [Event::AppStateSync] it's part of
+	ChatAction {
+		action: ChatAction,
+
+		/// Milliseconds since the epoch, when the action was taken
+		timestamp: Option<i64>,
+	},
+
+	GroupUpdate(GroupUpdate),
+
+	/// The disappearing messages timer of a chat changed, for groups this follows the [Event::GroupUpdate] that changed it
+	EphemeralSetting {
+		chat: ContactJid,
+
+		/// Who changed the timer, the server if it's missing
+		actor: Option<ContactJid>,
+		setting: EphemeralSetting,
+	},
+
+	MediaRetry {
+		id: String,
+		chat: Option<ContactJid>,
+		participant: Option<ContactJid>,
+		from_me: bool,
+
+		/// The encrypted notification and its iv, or the error code if the phone couldn't upload the file again
+		result: std::result::Result<(Vec<u8>, Vec<u8>), u32>,
+	},
+}
+
+/// The subscriber of the client, shared with every handle so background tasks reach whoever listens right now
+#[derive(Clone, Default)]
+pub(crate) struct SharedEvents(Arc<Mutex<Option<UnboundedSender<Event>>>>);
+
+impl SharedEvents {
+	pub(crate) fn subscribe(&self) -> UnboundedReceiver<Event> {
+		let (tx, rx) = mpsc::unbounded_channel();
+		*self.0.lock().unwrap_or_else(PoisonError::into_inner) = tx.into();
+		rx
+	}
+
+	pub(crate) fn sender(&self) -> Option<UnboundedSender<Event>> {
+		self.0.lock().unwrap_or_else(PoisonError::into_inner).clone()
+	}
+
+	pub(crate) fn emit(&self, event: Event) {
+		if let Some(events) = self.sender() {
+			// Nobody is listening anymore, that's fine
+			let _ = events.send(event);
+		}
+	}
+}
@@ -3,6 +3,7 @@
 extern crate core;
 
 pub mod client;
+pub mod event;
 pub mod stream;
 
 use tokio_tungstenite::tungstenite::http::header::{CONNECTION, HOST, ORIGIN, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE};
@@ -12,7 +13,14 @@ pub use crate::Result;
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+    use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+    use whatsapp_rs_util::model::ContactJid;
+    use whatsapp_rs_util::model::receipt::{receipt_ids, ReceiptKind};
+    use crate::client::receipt::receipt_node;
     use crate::client::WebSocketClient;
+    use crate::event::StreamError;
+    use crate::stream::digest::ack::Ack;
 
     #[tokio::test]
     pub async fn test() {
@@ -20,6 +28,117 @@ mod tests {
         let mut client = WebSocketClient::new(None);
         client.connect().await.unwrap();
     }
+
+    fn node(description: &str, attributes: &[(&str, &str)], children: Vec<Node>) -> Node {
+        let attributes: HashMap<String, Value> = attributes.iter()
+            .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+            .collect();
+
+        if children.is_empty() {
+            Node::from_attributes(description.to_owned(), attributes)
+        } else {
+            Node::with_children(description.to_owned(), attributes, children)
+        }
+    }
+
+    #[test]
+    pub fn acks_name_the_stanza() {
+        let receipt = node("receipt", &[
+            ("id", "ABCD"),
+            ("from", "1234@g.us"),
+            ("participant", "5678@s.whatsapp.net"),
+            ("type", "read")
+        ], vec![]);
+
+        let ack = Ack::of(&receipt).unwrap();
+        assert_eq!(ack.description(), "ack");
+        assert_eq!(ack.attribute_str("id"), Some("ABCD"));
+        assert_eq!(ack.attribute_str("to"), Some("1234@g.us"));
+        assert_eq!(ack.attribute_str("class"), Some("receipt"));
+        assert_eq!(ack.attribute_str("participant"), Some("5678@s.whatsapp.net"));
+        assert_eq!(ack.attribute_str("type"), Some("read"));
+
+        let message = node("message", &[("id", "EFGH"), ("from", "1234@s.whatsapp.net"), ("type", "text")], vec![]);
+        assert_eq!(Ack::of(&message).unwrap().attribute_str("type"), None);
+
+        assert!(Ack::of(&node("iq", &[("id", "1"), ("from", "s.whatsapp.net")], vec![])).is_none());
+        assert!(Ack::of(&node("message", &[("id", "1")], vec![])).is_none());
+    }
+
+    #[test]
+    pub fn stream_errors_are_classified() {
+        let removed = node("stream:error", &[("code", "401")], vec![node("conflict", &[("type", "device_removed")], vec![])]);
+        assert_eq!(StreamError::from(&removed), StreamError::DeviceRemoved);
+
+        let replaced = node("stream:error", &[], vec![node("conflict", &[("type", "replaced")], vec![])]);
+        assert_eq!(StreamError::from(&replaced), StreamError::Conflict);
+
+        let ack = node("stream:error", &[], vec![node("ack", &[("id", "ABCD")], vec![])]);
+        assert_eq!(StreamError::from(&ack), StreamError::Ack { id: Some("ABCD".to_owned()) });
+
+        assert_eq!(StreamError::from(&node("stream:error", &[("code", "515")], vec![])), StreamError::ForceReconnect);
+        assert_eq!(
+            StreamError::from(&node("stream:error", &[("code", "418")], vec![node("teapot", &[], vec![])])),
+            StreamError::Unknown { code: Some(418), text: "teapot".to_owned() }
+        );
+    }
+
+    #[test]
+    pub fn view_once_media_follows_the_timer() {
+        use whatsapp_rs_util::binary::handshake::MessageField;
+        use whatsapp_rs_util::model::ephemeral::{self, EphemeralSetting, TIMER_24_HOURS};
+        use whatsapp_rs_util::protobuf::message_wrapper::MessageWrapperExt;
+        use whatsapp_rs_util::protobuf::whatsapp::{ImageMessage, Message};
+        use crate::client::message::stanza_type;
+
+        let image = Message { imageMessage: MessageField::some(ImageMessage::default()), ..Default::default() };
+        let mut message = image.view_once().unwrap();
+        let setting = EphemeralSetting { duration: TIMER_24_HOURS, timestamp: Some(1_000) };
+        setting.apply(&mut message);
+
+        let context_info = message.viewOnceMessage.message.imageMessage.contextInfo.as_ref().unwrap();
+        assert_eq!(context_info.expiration, Some(TIMER_24_HOURS));
+        assert_eq!(context_info.ephemeralSettingTimestamp, Some(1_000));
+        assert_eq!(ephemeral::context_info(&message), Some(context_info));
+        assert_eq!(stanza_type(&message), "media");
+    }
+
+    #[test]
+    pub fn polls_are_sent_as_polls() {
+        use whatsapp_rs_util::protobuf::poll_message::{PollCreationMessage, PollMessageExt, PollUpdateMessage};
+        use whatsapp_rs_util::protobuf::whatsapp::Message;
+        use crate::client::message::stanza_type;
+
+        let mut creation = Message::default();
+        creation.set_poll_creation(&PollCreationMessage { name: "Meeting".to_owned(), options: vec!["Monday".to_owned()], selectable_count: 1 }).unwrap();
+        assert_eq!(stanza_type(&creation), "poll");
+
+        let mut vote = Message::default();
+        vote.set_poll_update(&PollUpdateMessage { enc_iv: vec![0; 12], ..Default::default() }).unwrap();
+        assert_eq!(stanza_type(&vote), "poll");
+        assert_eq!(stanza_type(&Message::default()), "text");
+    }
+
+    #[test]
+    pub fn receipts_list_every_id() {
+        let chat: ContactJid = "1234@g.us".parse().unwrap();
+        let participant: ContactJid = "5678@s.whatsapp.net".parse().unwrap();
+        let ids = vec!["A".to_owned(), "B".to_owned(), "C".to_owned()];
+
+        let receipt = receipt_node(&chat, Some(&participant), &ids, ReceiptKind::Read).unwrap();
+        assert_eq!(receipt.attribute_str("to"), Some("1234@g.us"));
+        assert_eq!(receipt.attribute_str("participant"), Some("5678@s.whatsapp.net"));
+        assert_eq!(receipt.attribute_str("type"), Some("read"));
+        assert!(receipt.attribute_str("t").is_some());
+        assert_eq!(receipt_ids(&receipt), ids);
+
+        let delivery = node("receipt", &[("id", "A"), ("from", "1234@s.whatsapp.net")], vec![]);
+        assert_eq!(receipt_ids(&delivery), vec!["A".to_owned()]);
+        assert_eq!(ReceiptKind::of(delivery.attribute_str("type")), ReceiptKind::Delivery);
+        assert_eq!(ReceiptKind::of(Some("hist_sync")), ReceiptKind::HistorySync);
+
+        assert!(receipt_node(&chat, None, &[], ReceiptKind::Read).is_err());
+    }
 }
 
 pub fn form_ws_request() -> Result<Request<()>> {
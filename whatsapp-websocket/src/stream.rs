@@ -1,6 +1,7 @@
 
 pub mod processor;
 pub mod digest;
+pub mod keep_alive;
 
 use anyhow::bail;
 
@@ -10,6 +11,7 @@ use whatsapp_rs_util::binary::node::Node;
 use whatsapp_rs_util::protobuf::whatsapp::{ClientHello, HandshakeMessage};
 
 use crate::client::auth::AuthHandler;
+use crate::client::handle::Request;
 use crate::client::WebSocketClient;
 pub use crate::Result;
 pub use crate::util::error::Error;
@@ -70,4 +72,12 @@ impl<'a> Stream<'a> {
 		result
 	}
 
+	pub fn is_connected(&self) -> bool {
+		self.client.state == State::Connected
+	}
+
+	pub(crate) async fn request(&mut self, request: Request) -> Result<()> {
+		self.client.request(request).await
+	}
+
 }
\ No newline at end of file
@@ -1,8 +1,19 @@
+pub(crate) mod ack;
+mod app_state;
 mod iq;
-mod error;
+pub mod error;
+mod group;
+mod history_sync;
+mod message;
+mod notification;
+mod presence;
+mod privacy;
+mod receipt;
+pub(crate) mod retry;
 mod success;
 
 use crate::Result;
+use ack::*;
 use iq::*;
 use whatsapp_rs_util::binary::node::{DataExt, Node};
 use whatsapp_rs_util::model::Session;
@@ -20,27 +31,47 @@ pub trait Digest {
 impl Stream<'_> {
 
 	pub async fn digest(&mut self, node: Node) -> Result<()> {
-		if node.id().is_none() {
+		let Some(node) = self.resolve(node) else {
 			return Ok(());
-		}
+		};
+
+		// Acknowledgements are sent after the stanza has been processed
+		let ack = Ack::of(&node);
 
 		let data = DigestData {
 			session: self.client.session.clone(),
 			node
 		};
 
-		if let Some(node) = match data.node.id().unwrap() {
+		let Some(description) = data.node.id() else {
+			return Ok(());
+		};
+
+		if let Some(node) = match description {
 			"iq" => <Iq as Digest>::digest(data)?,
-			"success" => self.handle_success().await,
-			"stream:error" => self.handle_error(data.node).await,
+			"success" => self.handle_success(data.node).await?,
+			"stream:error" => self.handle_error(data.node).await?,
+			"message" => self.handle_message(data.node).await?,
+			"receipt" => self.handle_receipt(data.node).await?,
+			"notification" => self.handle_notification(data.node).await?,
+			"presence" => self.handle_presence(data.node).await?,
+			"chatstate" => self.handle_chat_state(data.node).await?,
 			"xmlstreamend" => None,
 
-			_ => unimplemented!()
+			// Acks of stanzas nobody waits for
+			"ack" => None,
+
+			// Nodes we don't handle yet are dropped instead of aborting the stream
+			_ => None
 		} {
 			let DigestData { session, node} = node;
 			self.client.session = session;
 
-			return self.client.send(Transmission::Node(node)).await
+			self.client.send(Transmission::Node(node)).await?
+		}
+
+		if let Some(ack) = ack {
+			self.client.send(Transmission::Node(ack)).await?
 		}
 
 		Ok(())
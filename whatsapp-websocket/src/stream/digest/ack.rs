@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use crate::stream::{Error, Stream};
+use crate::Result;
+
+/// Stanzas the server redelivers until we acknowledged them
+const ACKNOWLEDGED_CLASSES: [&str; 4] = ["message", "receipt", "notification", "call"];
+
+pub struct Ack;
+
+impl Ack {
+	pub fn of(node: &Node) -> Option<Node> {
+		let class = node.description();
+		if !ACKNOWLEDGED_CLASSES.contains(&class) {
+			return None
+		}
+
+		let mut attributes = HashMap::from([
+			("id".to_owned(), node.attribute("id")?.clone()),
+			("to".to_owned(), node.attribute("from")?.clone()),
+			("class".to_owned(), Value::String(class.to_owned()))
+		]);
+
+		for key in ["participant", "recipient"] {
+			if let Some(value) = node.attribute(key) {
+				attributes.insert(key.to_owned(), value.clone());
+			}
+		}
+
+		// The type of messages is only repeated when something went wrong
+		if class != "message" {
+			if let Some(kind) = node.attribute("type") {
+				attributes.insert("type".to_owned(), kind.clone());
+			}
+		}
+
+		Some(Node::from_attributes("ack".to_owned(), attributes))
+	}
+
+	fn is_response(node: &Node) -> bool {
+		match node.description() {
+			"ack" => true,
+			"iq" => matches!(node.attribute("type").and_then(Value::as_str), Some("result" | "error")),
+			_ => false
+		}
+	}
+
+	fn into_response(node: Node) -> Result<Node> {
+		if let Some(code) = node.attribute("error").and_then(Value::as_str) {
+			return Err(Error::AckError(code.parse().unwrap_or_default()).into())
+		}
+
+		if node.attribute("type").and_then(Value::as_str) == Some("error") {
+			let error = node.find_description("error");
+			let code = error.and_then(|error| error.attribute("code"))
+				.and_then(Value::as_str)
+				.and_then(|code| code.parse().ok())
+				.unwrap_or_default();
+
+			let text = error.and_then(|error| error.attribute("text"))
+				.and_then(Value::as_str)
+				.unwrap_or_default()
+				.to_owned();
+
+			return Err(Error::IqError { code, text }.into())
+		}
+
+		Ok(node)
+	}
+}
+
+impl Stream<'_> {
+	/// Hands responses to whoever is waiting for them, every other node is given back
+	pub fn resolve(&mut self, node: Node) -> Option<Node> {
+		if !Ack::is_response(&node) {
+			return Some(node)
+		}
+
+		let Some(id) = node.attribute("id").and_then(Value::as_str).map(str::to_owned) else {
+			return Some(node)
+		};
+
+		if !self.client.pending.contains_key(&id) {
+			return Some(node)
+		}
+
+		self.client.resolve(&id, Ack::into_response(node));
+		None
+	}
+}
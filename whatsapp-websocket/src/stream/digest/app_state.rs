@@ -0,0 +1,56 @@
+use whatsapp_rs_util::binary::node::{DataExt, Node};
+use whatsapp_rs_util::model::app_state::Collection;
+use whatsapp_rs_util::protobuf::whatsapp::AppStateSyncKeyShare;
+use crate::client::app_state::is_missing_key;
+use crate::event::Event;
+use crate::stream::Stream;
+
+impl Stream<'_> {
+	/// The first keys the phone shares unlock the initial sync of every collection, later ones the collections waiting for them
+	pub(crate) fn add_app_state_keys(&mut self, share: &AppStateSyncKeyShare) {
+		let initial = {
+			let mut store = self.client.session.app_state.lock();
+			let initial = !store.has_keys();
+			store.add_keys(share);
+			initial
+		};
+
+		let blocked = self.client.app_state_syncs.take_blocked();
+		if initial {
+			self.sync_app_state(Collection::ALL.to_vec());
+		} else if !blocked.is_empty() {
+			self.sync_app_state(blocked);
+		}
+	}
+
+	/// Another device changed the app state, so the named collections have new patches
+	pub(crate) fn handle_server_sync(&mut self, node: &Node) {
+		let collections = node.nodes().into_iter()
+			.filter(|child| child.description() == "collection")
+			.filter_map(|child| child.attribute_str("name").and_then(Collection::from_name))
+			.collect();
+
+		self.sync_app_state(collections);
+	}
+
+	fn sync_app_state(&mut self, collections: Vec<Collection>) {
+		let handle = self.client.handle();
+
+		tokio::spawn(async move {
+			for collection in collections {
+				// The collection is synced again with the next server_sync or key share, starting from the last valid patch
+				match handle.sync_app_state(collection).await {
+					Ok(_) => {},
+					Err(error) if is_missing_key(&error) => {
+						log::debug!("Syncing {} once its key has been shared: {}", collection.name(), error);
+					},
+
+					Err(error) => {
+						log::warn!("Failed to sync {}: {}", collection.name(), error);
+						handle.emit(Event::AppStateSyncFailed { collection, reason: error.to_string() });
+					}
+				}
+			}
+		});
+	}
+}
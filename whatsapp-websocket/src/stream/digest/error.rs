@@ -1,30 +1,142 @@
-use whatsapp_rs_util::binary::node::{DataExt, Node};
+use anyhow::bail;
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use crate::event::Event;
 use crate::stream::digest::DigestData;
-use crate::stream::Stream;
+use crate::stream::{Error, Stream};
+use crate::Result;
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum StreamError {
+	/// 515: The server wants us to reconnect, usually right after the pairing
 	ForceReconnect,
-	Unauthorized
+
+	/// 401: The session is no longer valid
+	Unauthorized,
+
+	/// 401 with a `device_removed` conflict: The companion has been removed on the phone
+	DeviceRemoved,
+
+	/// 403: The account has been banned
+	Banned,
+
+	/// 406: The server did not accept our client
+	NotAcceptable,
+
+	/// 500: Something went wrong on the server side
+	InternalServerError,
+
+	/// 503: The server is currently not available
+	ServiceUnavailable,
+
+	/// The session has been replaced by another connection (ex. WhatsApp Desktop)
+	Conflict,
+
+	/// The server did not accept one of our acks
+	Ack { id: Option<String> },
+
+	/// The server could not parse one of our nodes
+	XmlNotWellFormed,
+
+	Unknown { code: Option<u32>, text: String }
+}
+
+pub enum StreamErrorBehavior {
+	Reconnect,
+	Logout,
+	Surface
+}
+
+impl StreamError {
+	pub fn behavior(&self) -> StreamErrorBehavior {
+		match self {
+			Self::ForceReconnect
+			| Self::InternalServerError
+			| Self::ServiceUnavailable
+			| Self::Ack { .. }
+			| Self::XmlNotWellFormed => StreamErrorBehavior::Reconnect,
+
+			Self::Unauthorized | Self::DeviceRemoved => StreamErrorBehavior::Logout,
+
+			Self::Banned
+			| Self::NotAcceptable
+			| Self::Conflict
+			| Self::Unknown { .. } => StreamErrorBehavior::Surface
+		}
+	}
+
+	pub fn code(&self) -> Option<u32> {
+		match self {
+			Self::ForceReconnect => Some(515),
+			Self::Unauthorized | Self::DeviceRemoved => Some(401),
+			Self::Banned => Some(403),
+			Self::NotAcceptable => Some(406),
+			Self::InternalServerError => Some(500),
+			Self::ServiceUnavailable => Some(503),
+			Self::Unknown { code, .. } => *code,
+			_ => None
+		}
+	}
+
+	pub fn into_error(self) -> Error {
+		match self {
+			Self::Conflict => Error::Conflict,
+			Self::Unauthorized | Self::DeviceRemoved => Error::LoggedOut,
+			Self::Unknown { code, text } => Error::StreamError { code: code.unwrap_or_default(), text },
+			error => Error::StreamError {
+				code: error.code().unwrap_or_default(),
+				text: format!("{error:?}")
+			}
+		}
+	}
 }
 
-impl From<u32> for StreamError {
-	fn from(input: u32) -> Self {
-		match input {
-			515 => Self::ForceReconnect,
-			401 => Self::Unauthorized,
-			_ => unimplemented!()
+impl From<&Node> for StreamError {
+	fn from(node: &Node) -> Self {
+		// The reason is described by the first child (ex. <conflict type="replaced"/>)
+		let child = node.content_as_value().as_array()
+			.and_then(|children| children.first());
+
+		let text = child.map(|child| child.description()).unwrap_or_default();
+		let kind = child.and_then(|child| child.attribute("type")).and_then(Value::as_str);
+
+		match (node.error_code(), text, kind) {
+			(Some(515), _, _) => Self::ForceReconnect,
+			(Some(401), "conflict", Some("device_removed")) => Self::DeviceRemoved,
+			(Some(401), _, _) => Self::Unauthorized,
+			(Some(403), _, _) => Self::Banned,
+			(Some(406), _, _) => Self::NotAcceptable,
+			(Some(500), _, _) => Self::InternalServerError,
+			(Some(503), _, _) => Self::ServiceUnavailable,
+			(_, "conflict", _) => Self::Conflict,
+			(_, "ack", _) => Self::Ack {
+				id: child.and_then(|child| child.attribute("id"))
+					.and_then(Value::as_str)
+					.map(str::to_owned)
+			},
+			(_, "xml-not-well-formed", _) => Self::XmlNotWellFormed,
+			(code, text, _) => Self::Unknown { code, text: text.to_owned() }
 		}
 	}
 }
 
 impl Stream<'_> {
-	pub async fn handle_error(&mut self, node: Node) -> Option<DigestData> {
-		let error: StreamError = node.error_code().expect("Expected error code").into();
-		match error {
-			StreamError::ForceReconnect => self.client.close(true).await,
-			StreamError::Unauthorized => todo!(),
+	pub async fn handle_error(&mut self, node: Node) -> Result<Option<DigestData>> {
+		let error = StreamError::from(&node);
+		self.client.emit(Event::StreamError(error.clone()));
+
+		match error.behavior() {
+			StreamErrorBehavior::Reconnect => self.client.close(true).await,
+			StreamErrorBehavior::Logout => {
+				self.client.close(false).await;
+				self.client.logout()?;
+				bail!(error.into_error())
+			},
+			StreamErrorBehavior::Surface => {
+				self.client.close(false).await;
+				bail!(error.into_error())
+			}
 		}
 
-		None
+		Ok(None)
 	}
-}
\ No newline at end of file
+}
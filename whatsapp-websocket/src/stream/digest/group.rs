@@ -0,0 +1,31 @@
+use whatsapp_rs_util::binary::node::Node;
+use whatsapp_rs_util::model::ephemeral::EphemeralSetting;
+use whatsapp_rs_util::model::group::{GroupChange, GroupUpdate};
+use crate::event::Event;
+use crate::stream::Stream;
+
+impl Stream<'_> {
+	pub(crate) fn handle_group_notification(&mut self, node: &Node) {
+		// Changes we don't know are only acknowledged
+		let Some(update) = GroupUpdate::of(node) else {
+			return
+		};
+
+		self.client.groups.apply(&update, self.client.session.store.companion.as_ref());
+		let settings: Vec<EphemeralSetting> = update.changes.iter()
+			.filter_map(|change| match change {
+				GroupChange::Ephemeral(duration) => Some(EphemeralSetting {
+					duration: duration.unwrap_or_default(),
+					timestamp: update.timestamp.map(|timestamp| timestamp as i64),
+				}),
+				_ => None
+			})
+			.collect();
+
+		let (group, actor) = (update.group.clone(), update.actor.clone());
+		self.client.emit(Event::GroupUpdate(update));
+		for setting in settings {
+			self.client.emit(Event::EphemeralSetting { chat: group.clone(), actor: actor.clone(), setting });
+		}
+	}
+}
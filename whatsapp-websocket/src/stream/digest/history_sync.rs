@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use whatsapp_rs_util::model::message_info::MessageInfo;
+use whatsapp_rs_util::model::receipt::ReceiptKind;
+use whatsapp_rs_util::protobuf::whatsapp::HistorySyncNotification;
+use whatsapp_rs_util::util::error::Error;
+use crate::event::Event;
+use crate::stream::Stream;
+
+/// How often we try to download a chunk before giving up on it
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+
+impl Stream<'_> {
+	/// The blob has to be downloaded first, so the history is emitted once that finished
+	pub(crate) fn sync_history(&mut self, info: &MessageInfo, notification: HistorySyncNotification) {
+		let handle = self.client.handle();
+		let events = self.client.event_sender();
+		let (chat, id) = (info.chat.clone(), info.id.clone());
+
+		tokio::spawn(async move {
+			let mut attempt = 1;
+			let event = loop {
+				match handle.download_history_sync(&notification).await {
+					Ok(history) => {
+						handle.learn_disappearing_timers(&history.conversations);
+						break Event::HistorySync {
+							kind: history.syncType(),
+							progress: history.progress,
+							conversations: history.conversations,
+						}
+					},
+
+					// An expired blob won't come back, no matter how often we ask
+					Err(error) if attempt >= DOWNLOAD_ATTEMPTS || matches!(error.downcast_ref::<Error>(), Some(Error::MediaExpired)) => {
+						log::warn!("Giving up on history sync chunk {}: {}", id, error);
+						break Event::HistorySyncFailed { id: id.clone(), reason: error.to_string() }
+					},
+
+					Err(error) => {
+						log::debug!("Failed to download history sync chunk {} (attempt {}): {}", id, attempt, error);
+						tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+						attempt += 1;
+					}
+				}
+			};
+
+			if let Some(events) = events {
+				let _ = events.send(event);
+			}
+
+			// The phone only offers the next chunk once this one was receipted, even if we couldn't use it
+			let _ = handle.send_receipt(&chat, None, &[id], ReceiptKind::HistorySync);
+		});
+	}
+}
@@ -12,52 +12,60 @@ use crate::Result;
 pub struct Iq;
 
 impl Iq {
-	pub fn print(container: Node, session: &Session) {
+	pub fn print(container: Node, session: &Session) -> Result<()> {
 		let reference = container.find_description("ref")
-			.expect("Missing ref").content::<&str>().unwrap();
+			.and_then(|reference| reference.content::<&str>())
+			.ok_or_else(|| malformed("missing ref"))?;
 
 		let formatted_code = format!(
 			"{},{noise_public},{identity_public},{companion}",
 			reference,
 			noise_public = security::base64::encode(session.credentials.noise_keypair.public.as_bytes()),
 			identity_public = security::base64::encode(session.credentials.identity_keypair.public.as_bytes()),
-			companion = security::base64::encode(session.credentials.signed_keypair.key_pair.public_key.public_key_bytes().unwrap())
+			companion = security::base64::encode(session.credentials.signed_keypair.key_pair.public_key.public_key_bytes()?)
 		);
 
-		qr2term::print_qr(formatted_code).unwrap();
+		qr2term::print_qr(formatted_code)?;
+		Ok(())
 	}
 
-	pub fn send_confirm(node: Node, content: Value) -> Node {
+	pub fn send_confirm(node: Node, content: Value) -> Result<Node> {
+		let id = node.attribute("id")
+			.and_then(Value::as_str)
+			.ok_or_else(|| malformed("missing id"))?;
+
 		let request = Node::new(
 			"iq".to_owned(),
 			HashMap::from([
-				("id".to_owned(), Value::String(node.attributes_clone()["id"].as_str().unwrap().to_string())),
+				("id".to_owned(), Value::String(id.to_owned())),
 				("type".to_owned(), Value::String("result".to_owned())),
 				("to".to_owned(), Value::String(contact_jid::Server::Whatsapp.address().to_owned()))
 			]),
 			content
 		);
 
-		request
+		Ok(request)
 	}
 
 	pub fn identify(session: &mut Session, node: Node, container: Node) -> Result<Node> {
 		Self::save_companion(&container, &mut session.store)?;
 
 		let device_identity = container.find_description("device-identity")
-			.expect("Missing device-identity").content_array_nums().unwrap();
+			.and_then(|identity| identity.content_array_nums())
+			.ok_or_else(|| malformed("missing device-identity"))?;
 
 		let adv_identity = ADVSignedDeviceIdentityHMAC::parse_from_bytes(device_identity.as_slice())?;
+		let details = adv_identity.details.as_ref().ok_or_else(|| malformed("missing identity details"))?;
 		let adv_sign = security::hash::mac_sha256(
-			session.credentials.signed_keypair.key_pair.public_key.public_key_bytes().unwrap(),
-			adv_identity.details.as_ref().unwrap()
+			session.credentials.signed_keypair.key_pair.public_key.public_key_bytes()?,
+			details
 		);
 
-		if adv_sign.ne(adv_identity.hmac.unwrap().as_slice()) {
+		if adv_sign.ne(adv_identity.hmac()) {
 			bail!(Error::IqInvalidSignature)
 		}
 
-		let mut account = ADVSignedDeviceIdentity::parse_from_bytes(&adv_identity.details.unwrap())?;
+		let mut account = ADVSignedDeviceIdentity::parse_from_bytes(details)?;
 		let message = account.form_message(&session.credentials);
 
 		if !security::keypair::verify_signature(account.accountSignatureKey(), &message, account.accountSignature())? {
@@ -66,7 +74,7 @@ impl Iq {
 
 		account.sign(&session.credentials)?;
 
-		let key_index = ADVDeviceIdentity::parse_from_bytes(account.details.as_ref().unwrap())?.keyIndex();
+		let key_index = ADVDeviceIdentity::parse_from_bytes(account.details())?.keyIndex();
 
 		let attributes: HashMap<String, Value> = HashMap::from([("key-index".to_owned(), key_index.into())]);
 		let account_without_key_node: Value = account.without_key().try_into()?;
@@ -75,23 +83,25 @@ impl Iq {
 		let pair_device = Node::new(
 			"pair-device-sign".to_owned(),
 			HashMap::new(),
-			Node::serialize(identity_node).unwrap()
+			Node::serialize(identity_node).ok_or_else(|| malformed("unserializable device-identity"))?
 		);
 
+		// Pre key messages we send later on have to carry our identity
+		session.signal.lock().device_identity = account.without_key().write_to_bytes()?.into();
 		session.store.companion_identity = account.into();
 
-		let serialized = Node::serialize(pair_device).unwrap();
-		Ok(Self::send_confirm(node, serialized))
+		let serialized = Node::serialize(pair_device).ok_or_else(|| malformed("unserializable pair-device-sign"))?;
+		Self::send_confirm(node, serialized)
 	}
 
 	pub fn save_companion(container: &Node, store: &mut SessionStore) -> Result<()> {
 		let device_node = Node::deserialize(
 			container.find_description("device").ok_or(Error::IqMissingDevice)?.clone()
-		).unwrap();
+		).ok_or(Error::IqMissingDevice)?;
 
-		let attributes = device_node.attributes_clone();
-		let companion_node = attributes.get("jid").unwrap();
-		let jid = Node::parse_jid(companion_node);
+		let jid = device_node.attribute("jid")
+			.and_then(Node::parse_jid)
+			.ok_or_else(|| malformed("missing device jid"))?;
 		store.companion = jid.into();
 		Ok(())
 	}
@@ -102,20 +112,33 @@ impl Digest for Iq {
 	{
 		let DigestData { mut session, node} = data;
 
-		// TODO: error handling
+		// The server pings us as well and expects an empty result
+		if node.attribute("xmlns").and_then(Value::as_str) == Some("urn:xmpp:ping") {
+			return Ok(DigestData {
+				node: Iq::send_confirm(node, Value::Null)?,
+				session
+			}.into())
+		}
+
+		// Results of our own queries (ex. keep-alive pings) don't need a response
+		if node.attribute("type").and_then(Value::as_str) == Some("result") {
+			return Ok(None)
+		}
+
 		let container = node.children().first()
 			.and_then(|child| child.as_array())
-			.and_then(|nodes| nodes.first()).unwrap();
+			.and_then(|nodes| nodes.first())
+			.ok_or_else(|| malformed("missing child"))?;
 
 		let container: Node = container.try_into()?;
 
 		Ok(match container.description() {
 			"pair-device" => {
 				// Print qr code and send confirmation
-				Iq::print(container, &session);
+				Iq::print(container, &session)?;
 				DigestData {
 					session,
-					node: Iq::send_confirm(node, Value::Null)
+					node: Iq::send_confirm(node, Value::Null)?
 				}.into()
 			},
 
@@ -128,7 +151,15 @@ impl Digest for Iq {
 				}.into()
 			}
 
-			_ => unreachable!()
+			// Requests we don't model don't concern the stream, so they're left unanswered
+			other => {
+				log::debug!("Ignoring unknown iq child <{}>", other);
+				None
+			}
 		})
 	}
+}
+
+fn malformed(reason: &'static str) -> Error {
+	Error::MalformedNode { tag: "iq".to_owned(), reason }
 }
\ No newline at end of file
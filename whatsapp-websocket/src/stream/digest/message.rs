@@ -0,0 +1,220 @@
+use whatsapp_rs_util::binary::node::{DataExt, Node};
+use whatsapp_rs_util::model::ContactJid;
+use whatsapp_rs_util::model::ephemeral::{self, EphemeralSetting};
+use whatsapp_rs_util::model::message_info::MessageInfo;
+use whatsapp_rs_util::model::receipt::ReceiptKind;
+use whatsapp_rs_util::protobuf::message_wrapper::{MessageWrapperExt, UnwrappedMessage};
+use whatsapp_rs_util::protobuf::protocol_message::ProtocolMessageExt;
+use whatsapp_rs_util::protobuf::whatsapp::{Message, MessageParser};
+use whatsapp_rs_util::security::signal;
+use crate::client::receipt::receipt_node;
+use crate::event::Event;
+use crate::stream::digest::DigestData;
+use crate::stream::{Error, Stream, Transmission};
+use crate::Result;
+
+impl Stream<'_> {
+	pub async fn handle_message(&mut self, node: Node) -> Result<Option<DigestData>> {
+		// A malformed message is dropped, it doesn't concern the rest of the stream
+		let info = match self.message_info(&node) {
+			Ok(info) => info,
+			Err(error) => {
+				log::warn!("Dropping malformed message: {}", error);
+				return Ok(None)
+			}
+		};
+
+		// Every part that did decrypt is handled, only the ones that didn't are asked for again
+		let mut messages = Vec::new();
+		let mut failed = false;
+		for enc in node.nodes().into_iter().filter(|child| child.description() == "enc") {
+			match self.decrypt(&info, &enc) {
+				Ok(message) => messages.push(message),
+				Err(error) => {
+					log::debug!("Couldn't decrypt {:?} of message {}: {}", enc.attribute_str("type"), info.id, error);
+					failed = true;
+				}
+			}
+		}
+
+		// The sender re-encrypts the message once it got our keys
+		if failed {
+			self.send_retry(&node, &info).await?;
+		} else {
+			self.client.retries.remove(&info.id);
+		}
+
+		for message in messages {
+			if is_distribution_only(&message) {
+				continue
+			}
+
+			let mut unwrapped = message.unwrap_content();
+
+			// Only our own devices may claim to have sent a message for us, to the chat they name
+			let info = match unwrapped.destination.as_deref().and_then(|destination| destination.parse().ok()) {
+				Some(chat) if info.from_me => MessageInfo { chat, ..info.clone() },
+				_ if info.from_me => info.clone(),
+				_ => {
+					unwrapped.sent_by_self_device = false;
+					unwrapped.destination = None;
+					info.clone()
+				}
+			};
+
+			let message = &unwrapped.message;
+
+			// Only our own phone is allowed to hand us the history and the app state keys
+			if let Some(protocol) = message.protocolMessage.as_ref().filter(|_| info.from_me) {
+				if let Some(notification) = protocol.historySyncNotification.as_ref() {
+					self.sync_history(&info, notification.clone());
+				}
+
+				if let Some(share) = protocol.appStateSyncKeyShare.as_ref() {
+					self.add_app_state_keys(share);
+				}
+			}
+
+			// The timers of groups are part of their metadata, which their notifications keep up to date
+			if !info.chat.is_group() {
+				if let Some(setting) = message.protocolMessage.as_ref().and_then(EphemeralSetting::of) {
+					self.client.ephemeral.set(&info.chat, setting);
+				} else if let Some(setting) = ephemeral::context_info(message).and_then(EphemeralSetting::of_context_info) {
+					self.client.ephemeral.learn(&info.chat, setting);
+				}
+			}
+
+			self.client.polls.register(&info, message);
+
+			// Votes for polls we don't know the secret of are handed over as they are
+			let event = match self.client.polls.apply_vote(&info.sender, message) {
+				Some((poll, selected)) => Event::PollVote { info: info.clone(), poll, selected },
+				None => message_event(&info, unwrapped, self.client.session.store.companion.as_ref())
+			};
+
+			self.client.emit(event);
+		}
+
+		// The retry receipt takes the place of the delivery receipt
+		if failed {
+			return Ok(None)
+		}
+
+		let (chat, participant) = receipt_target(&node, &info);
+		let receipt = receipt_node(&chat, participant.as_ref(), &[info.id.clone()], ReceiptKind::Delivery)?;
+		self.client.send(Transmission::Node(receipt)).await?;
+
+		Ok(None)
+	}
+
+	fn message_info(&self, node: &Node) -> Result<MessageInfo> {
+		let from = node.attribute_jid("from").ok_or_else(|| malformed("missing from"))?;
+		let id = node.attribute_str("id").ok_or_else(|| malformed("missing id"))?.to_owned();
+		let sender = node.attribute_jid("participant").unwrap_or_else(|| from.clone());
+
+		let from_me = self.client.session.store.companion.as_ref()
+			.map_or(false, |companion| companion.user == sender.user);
+
+		// Messages we sent from another device name the chat as recipient
+		let chat = match node.attribute_jid("recipient") {
+			Some(recipient) if from_me => recipient,
+			_ => from
+		};
+
+		Ok(MessageInfo {
+			id,
+			chat,
+			sender,
+			from_me,
+			timestamp: node.attribute_str("t").and_then(|timestamp| timestamp.parse().ok()).unwrap_or_default(),
+			push_name: node.attribute_str("notify").map(str::to_owned),
+		})
+	}
+
+	fn decrypt(&mut self, info: &MessageInfo, enc: &Node) -> Result<Message> {
+		let ciphertext = enc.content_bytes().ok_or_else(|| malformed("missing ciphertext"))?;
+		let kind = enc.attribute_str("type").ok_or_else(|| malformed("missing enc type"))?;
+
+		let mut signal = self.client.session.signal.lock();
+		let plaintext = match kind {
+			"skmsg" => signal.group_decrypt(&info.chat, &info.sender, &ciphertext)?,
+			kind => signal.decrypt(&info.sender, kind, &ciphertext)?
+		};
+
+		let message = Message::parse_from_bytes(&signal::unpad(plaintext)?)?;
+
+		// Following group messages of this participant are encrypted with the distributed sender key
+		if let Some(distribution) = message.senderKeyDistributionMessage.as_ref() {
+			signal.process_sender_key_distribution(
+				&info.chat,
+				&info.sender,
+				distribution.axolotlSenderKeyDistributionMessage()
+			)?;
+		}
+
+		Ok(message)
+	}
+}
+
+/// Reactions, edits and revokes are attached to the message they change instead of being messages of their own.
+/// Timer changes of groups are emitted for their notification instead
+fn message_event(info: &MessageInfo, unwrapped: UnwrappedMessage, own: Option<&ContactJid>) -> Event {
+	let message = &unwrapped.message;
+	if let Some(reaction) = message.reactionMessage.as_ref() {
+		return Event::Reaction {
+			info: info.clone(),
+			key: info.referenced_key(&reaction.key, own),
+			emoji: reaction.text.clone().filter(|emoji| !emoji.is_empty()),
+			timestamp_ms: reaction.senderTimestampMs,
+		}
+	}
+
+	if let Some(protocol) = message.protocolMessage.as_ref() {
+		if protocol.is_revoke() {
+			return Event::MessageRevoke { info: info.clone(), key: info.referenced_key(&protocol.key, own) }
+		}
+
+		if let Some(setting) = EphemeralSetting::of(protocol).filter(|_| !info.chat.is_group()) {
+			return Event::EphemeralSetting { chat: info.chat.clone(), actor: Some(info.sender.to_user()), setting }
+		}
+
+		if let Some(edited) = protocol.edited_message().filter(|_| protocol.is_edit()) {
+			return Event::MessageEdit {
+				info: info.clone(),
+				key: info.referenced_key(&protocol.key, own),
+				message: Box::new(edited.unwrap_content().message),
+				timestamp_ms: protocol.timestamp_ms(),
+			}
+		}
+	}
+
+	Event::Message {
+		info: info.clone(),
+		message: Box::new(unwrapped.message),
+		view_once: unwrapped.is_view_once,
+		ephemeral: unwrapped.is_ephemeral,
+		edit: unwrapped.is_edit,
+		document_with_caption: unwrapped.is_document_with_caption,
+		sent_by_self_device: unwrapped.sent_by_self_device,
+		destination: unwrapped.destination.and_then(|destination| destination.parse().ok()),
+	}
+}
+
+/// Pre key messages to groups only carry the sender key, the content follows in the skmsg
+fn is_distribution_only(message: &Message) -> bool {
+	let mut content = message.clone();
+	content.senderKeyDistributionMessage.clear();
+	content.messageContextInfo.clear();
+
+	message.senderKeyDistributionMessage.is_some() && content == Message::default()
+}
+
+/// Receipts are addressed like the message they confirm
+fn receipt_target(node: &Node, info: &MessageInfo) -> (ContactJid, Option<ContactJid>) {
+	let from = node.attribute_jid("from").unwrap_or_else(|| info.chat.clone());
+	(from, node.attribute_jid("participant"))
+}
+
+fn malformed(reason: &'static str) -> Error {
+	Error::MalformedNode { tag: "message".to_owned(), reason }
+}
@@ -0,0 +1,57 @@
+use whatsapp_rs_util::binary::node::{DataExt, Node};
+use crate::event::Event;
+use crate::stream::digest::DigestData;
+use crate::stream::Stream;
+use crate::Result;
+
+impl Stream<'_> {
+	pub async fn handle_notification(&mut self, node: Node) -> Result<Option<DigestData>> {
+		// Notifications we don't handle yet are only acknowledged
+		match node.attribute_str("type") {
+			Some("mediaretry") => self.handle_media_retry(&node),
+			Some("server_sync") => self.handle_server_sync(&node),
+			Some("w:gp2") => self.handle_group_notification(&node),
+			Some("privacy_token") => self.handle_privacy_tokens(&node),
+			Some("blocklist") => self.handle_blocklist(&node),
+			Some("devices") => self.handle_device_notification(&node),
+			_ => {}
+		}
+
+		Ok(None)
+	}
+
+	/// A contact linked or removed a device, the next message to them queries their devices again
+	fn handle_device_notification(&mut self, node: &Node) {
+		if let Some(user) = node.attribute_jid("from") {
+			self.client.device_lists.invalidate(&user);
+		}
+	}
+
+	fn handle_media_retry(&mut self, node: &Node) {
+		let Some(id) = node.attribute_str("id") else {
+			return
+		};
+
+		let rmr = node.find_node("rmr");
+		let encrypted = node.find_node("encrypt").and_then(|encrypt| {
+			let ciphertext = encrypt.find_node("enc_p")?.content_bytes()?;
+			let iv = encrypt.find_node("enc_iv")?.content_bytes()?;
+			Some((ciphertext, iv))
+		});
+
+		let result = match encrypted {
+			Some(encrypted) => Ok(encrypted),
+			None => Err(node.find_node("error")
+				.and_then(|error| error.attribute_str("code").and_then(|code| code.parse().ok()))
+				.unwrap_or_default())
+		};
+
+		self.client.emit(Event::MediaRetry {
+			id: id.to_owned(),
+			chat: rmr.as_ref().and_then(|rmr| rmr.attribute_jid("jid")),
+			participant: rmr.as_ref().and_then(|rmr| rmr.attribute_jid("participant")),
+			from_me: rmr.as_ref().and_then(|rmr| rmr.attribute_str("from_me")) == Some("true"),
+			result,
+		});
+	}
+}
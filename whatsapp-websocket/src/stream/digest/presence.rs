@@ -0,0 +1,29 @@
+use whatsapp_rs_util::binary::node::Node;
+use whatsapp_rs_util::model::presence::{ChatStateUpdate, PresenceUpdate};
+use crate::event::Event;
+use crate::stream::digest::DigestData;
+use crate::stream::Stream;
+use crate::Result;
+
+impl Stream<'_> {
+	pub async fn handle_presence(&mut self, node: Node) -> Result<Option<DigestData>> {
+		let Some(PresenceUpdate { jid, available, last_seen }) = PresenceUpdate::of(&node) else {
+			log::debug!("Dropping presence without a sender: {:?}", node);
+			return Ok(None)
+		};
+
+		self.client.emit(Event::Presence { jid, available, last_seen });
+		Ok(None)
+	}
+
+	pub async fn handle_chat_state(&mut self, node: Node) -> Result<Option<DigestData>> {
+		// States we don't know yet are dropped as well
+		let Some(ChatStateUpdate { chat, participant, state }) = ChatStateUpdate::of(&node) else {
+			log::debug!("Dropping chatstate without a sender or a known state: {:?}", node);
+			return Ok(None)
+		};
+
+		self.client.emit(Event::ChatState { chat, participant, state });
+		Ok(None)
+	}
+}
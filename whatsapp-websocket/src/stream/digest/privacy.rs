@@ -0,0 +1,35 @@
+use whatsapp_rs_util::binary::node::{DataExt, Node};
+use whatsapp_rs_util::model::privacy::{BlocklistChange, PrivacyToken};
+use crate::event::Event;
+use crate::stream::Stream;
+
+impl Stream<'_> {
+	/// A contact trusts us, the tokens are only valid for them
+	pub(crate) fn handle_privacy_tokens(&mut self, node: &Node) {
+		let Some(from) = node.attribute_jid("from") else {
+			return
+		};
+
+		let tokens = node.find_node("tokens").map(|tokens| tokens.nodes()).unwrap_or_default();
+		for token in tokens.iter().filter_map(|token| PrivacyToken::of(&from, token)) {
+			self.client.emit(Event::PrivacyToken(token));
+		}
+	}
+
+	/// Another device of ours changed the blocklist, the items are either listed directly or as a whole list
+	pub(crate) fn handle_blocklist(&mut self, node: &Node) {
+		let mut items = node.nodes();
+		if let Some(list) = node.find_node("list") {
+			items.extend(list.nodes());
+		}
+
+		let changes: Vec<BlocklistChange> = items.iter()
+			.filter(|item| item.description() == "item")
+			.filter_map(BlocklistChange::of)
+			.collect();
+
+		if !changes.is_empty() {
+			self.client.emit(Event::Blocklist(changes));
+		}
+	}
+}
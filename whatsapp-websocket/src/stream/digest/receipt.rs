@@ -0,0 +1,33 @@
+use whatsapp_rs_util::binary::node::Node;
+use whatsapp_rs_util::model::receipt::{receipt_ids, ReceiptKind};
+use crate::event::Event;
+use crate::stream::digest::DigestData;
+use crate::stream::Stream;
+use crate::Result;
+
+impl Stream<'_> {
+	pub async fn handle_receipt(&mut self, node: Node) -> Result<Option<DigestData>> {
+		// A malformed receipt is dropped, it doesn't concern the rest of the stream
+		let Some(from) = node.attribute_jid("from") else {
+			log::warn!("Dropping receipt without sender: {:?}", node);
+			return Ok(None)
+		};
+
+		let ids = receipt_ids(&node);
+
+		let kind = ReceiptKind::of(node.attribute_str("type"));
+		if kind == ReceiptKind::Retry {
+			self.handle_retry(&node).await?;
+		}
+
+		self.client.emit(Event::Receipt {
+			ids,
+			from,
+			participant: node.attribute_jid("participant"),
+			kind,
+			timestamp: node.attribute_str("t").and_then(|timestamp| timestamp.parse().ok()),
+		});
+
+		Ok(None)
+	}
+}
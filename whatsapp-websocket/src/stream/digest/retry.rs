@@ -0,0 +1,166 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use whatsapp_rs_util::binary::node::{DataExt, Node, Value};
+use whatsapp_rs_util::model::ContactJid;
+use whatsapp_rs_util::model::message_info::MessageInfo;
+use whatsapp_rs_util::protobuf::whatsapp::MessageParser;
+use whatsapp_rs_util::security::signal;
+use crate::client::message::{message_node, participant_node, stanza_type, RecentMessage};
+use crate::event::Event;
+use crate::client::pre_key::{identity_nodes, parse_bundle, pre_key_node, signed_pre_key_node};
+use crate::stream::{Stream, Transmission};
+use crate::Result;
+
+/// The official clients give up on a message after this many retries
+const MAX_RETRIES: u32 = 5;
+
+/// How many messages we count retries for, senders that never answer would keep theirs forever otherwise
+const RETRIED_MESSAGES: usize = 256;
+
+/// The retries we asked for so far, by the id of the message, the oldest one is forgotten first
+#[derive(Default)]
+pub(crate) struct RetryCounts(VecDeque<(String, u32)>);
+
+impl RetryCounts {
+	/// Counts another retry of the message, returns how many we asked for including this one
+	pub fn increment(&mut self, id: &str) -> u32 {
+		if let Some((_, count)) = self.0.iter_mut().find(|(retried, _)| retried == id) {
+			*count += 1;
+			return *count
+		}
+
+		if self.0.len() >= RETRIED_MESSAGES {
+			self.0.pop_front();
+		}
+
+		self.0.push_back((id.to_owned(), 1));
+		1
+	}
+
+	pub fn remove(&mut self, id: &str) {
+		self.0.retain(|(retried, _)| retried != id);
+	}
+}
+
+impl Stream<'_> {
+	/// Asks the sender to encrypt the message again, starting from the second attempt with fresh keys
+	pub(crate) async fn send_retry(&mut self, message: &Node, info: &MessageInfo) -> Result<()> {
+		let count = self.client.retries.increment(&info.id);
+		if count > MAX_RETRIES {
+			self.client.retries.remove(&info.id);
+			self.client.emit(Event::UndecryptableMessage { info: info.clone() });
+			return Ok(())
+		}
+
+		let mut attributes = HashMap::from([
+			("id".to_owned(), Value::String(info.id.clone())),
+			("type".to_owned(), "retry".into())
+		]);
+
+		for (from, to) in [("from", "to"), ("participant", "participant"), ("recipient", "recipient")] {
+			if let Some(value) = message.attribute(from) {
+				attributes.insert(to.to_owned(), value.clone());
+			}
+		}
+
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		let mut children = vec![Node::from_attributes("retry".to_owned(), HashMap::from([
+			("count".to_owned(), Value::String(count.to_string())),
+			("id".to_owned(), info.id.clone().into()),
+			("t".to_owned(), timestamp.to_string().into()),
+			("v".to_owned(), "1".into())
+		]))];
+
+		{
+			let mut signal = self.client.session.signal.lock();
+			children.push(Node::with_bytes("registration".to_owned(), signal.registration_id()?.to_be_bytes()));
+
+			// The first retry assumes the session can be repaired, afterwards the sender starts a new one
+			if count > 1 {
+				let pre_key = signal.generate_pre_keys(1)?;
+				let mut keys: Vec<Node> = identity_nodes(&signal)?.into_iter()
+					.filter(|key| key.description() != "registration")
+					.collect();
+
+				keys.extend(pre_key.iter().map(pre_key_node));
+				keys.push(signed_pre_key_node(&signal)?);
+
+				if let Some(device_identity) = &signal.device_identity {
+					keys.push(Node::with_bytes("device-identity".to_owned(), device_identity));
+				}
+
+				children.push(Node::with_children("keys".to_owned(), HashMap::new(), keys));
+			}
+		}
+
+		self.client.send(Transmission::Node(Node::with_children("receipt".to_owned(), attributes, children))).await
+	}
+
+	/// Encrypts a message we sent once more for the device that couldn't decrypt it.
+	/// Receipts we can't answer are dropped, they don't concern the rest of the stream
+	pub(crate) async fn handle_retry(&mut self, receipt: &Node) -> Result<()> {
+		let Some(id) = receipt.attribute_str("id") else {
+			return Ok(())
+		};
+
+		// Messages we no longer remember can't be sent again
+		let Some(recent) = self.client.recent.find(id) else {
+			return Ok(())
+		};
+
+		let Some(device) = receipt.attribute_jid("participant").or_else(|| receipt.attribute_jid("from")) else {
+			return Ok(())
+		};
+
+		// Only the devices the message was meant for get its content
+		if !self.is_recipient(&recent, &device) {
+			log::warn!("Dropping retry receipt of {} for message {}, which wasn't sent to it", device, recent.id);
+			return Ok(())
+		}
+
+		match self.retry_stanza(receipt, &recent, &device) {
+			Ok(stanza) => self.client.send(Transmission::Node(stanza)).await,
+			Err(error) => {
+				log::warn!("Dropping retry receipt of {} for message {}: {}", device, recent.id, error);
+				Ok(())
+			}
+		}
+	}
+
+	/// Direct messages are meant for every device of the recipient, group messages for the ones of every participant
+	fn is_recipient(&self, recent: &RecentMessage, device: &ContactJid) -> bool {
+		if !recent.to.is_group() {
+			return recent.to.user == device.user
+		}
+
+		self.client.groups.get(&recent.to).map_or(false, |metadata| {
+			metadata.participants.iter().any(|participant| participant.jid.user == device.user)
+		})
+	}
+
+	fn retry_stanza(&mut self, receipt: &Node, recent: &RecentMessage, device: &ContactJid) -> Result<Node> {
+		let count = receipt.find_node("retry")
+			.and_then(|retry| retry.attribute_str("count").and_then(|count| count.parse().ok()))
+			.unwrap_or(1);
+
+		let plaintext = signal::pad(recent.message.write_to_bytes()?);
+		let mut signal = self.client.session.signal.lock();
+
+		// The registration id is sent next to the keys instead of inside of them
+		if let Some(keys) = receipt.find_node("keys") {
+			let mut material = keys.nodes();
+			material.extend(receipt.find_node("registration"));
+
+			let bundle = parse_bundle(device, &Node::with_children("keys".to_owned(), HashMap::new(), material))?;
+			signal.process_bundle(device, &bundle)?;
+		}
+
+		// The device might have lost our sender key as well, so it gets it with our next message to the group
+		if recent.to.is_group() {
+			signal.forget_sender_key_recipient(&recent.to, device);
+		}
+
+		let participant = participant_node(&mut signal, device, &plaintext, Some(count))?;
+		message_node(&signal, &recent.to, &recent.id, stanza_type(&recent.message), vec![participant], None)
+	}
+}
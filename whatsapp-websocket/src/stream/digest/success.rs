@@ -1,16 +1,40 @@
+use std::sync::PoisonError;
 use whatsapp_rs_util::binary::node::Node;
+use whatsapp_rs_util::protobuf::adv_message::AccountMessageFormer;
+use whatsapp_rs_util::protobuf::whatsapp::MessageParser;
 use crate::stream::digest::DigestData;
 use crate::stream::Stream;
+use crate::Result;
 
 impl Stream<'_> {
-	pub async fn handle_success(&mut self) -> Option<DigestData> {
+	pub async fn handle_success(&mut self, node: Node) -> Result<Option<DigestData>> {
 		self.client.query(
 			"set",
 			"passive",
 			Node::from_attributes("active".to_owned(), [].into())
-		).await.unwrap();
+		).await?;
 
-		// TODO: send pre keys when available
-		todo!()
+		*self.client.companion.lock().unwrap_or_else(PoisonError::into_inner) = self.client.session.store.companion.clone();
+
+		if let Some(push_name) = node.attribute_str("pushname") {
+			*self.client.push_name.lock().unwrap_or_else(PoisonError::into_inner) = push_name.to_owned().into();
+		}
+
+		// Restored sessions only know the identity from the pairing
+		if let Some(identity) = &self.client.session.store.companion_identity {
+			let mut signal = self.client.session.signal.lock();
+			if signal.device_identity.is_none() {
+				signal.device_identity = identity.without_key().write_to_bytes()?.into();
+			}
+		}
+
+		// The response only arrives once the stream processes it, so the upload can't happen inline
+		let handle = self.client.handle();
+		tokio::spawn(async move {
+			// The server tells us again on the next login if it's still out of keys
+			let _ = handle.upload_pre_keys().await;
+		});
+
+		Ok(None)
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,40 @@
+use std::time::Instant;
+
+use crate::stream::{State, Stream};
+use crate::Result;
+
+impl Stream<'_> {
+	pub fn touch(&mut self) {
+		self.client.last_received = Instant::now();
+	}
+
+	/// When the connection is considered dead, unless another frame arrives until then
+	pub fn deadline(&self) -> Instant {
+		self.client.keep_alive.deadline(self.client.last_received)
+	}
+
+	/// Sends the next ping, pings are only known by the server after the login
+	pub async fn keep_alive(&mut self) -> Result<()> {
+		if self.client.state != State::Connected {
+			return Ok(())
+		}
+
+		self.client.ping().await
+	}
+
+	/// Closes the connection for reconnect when the server stopped answering.
+	/// Returns false when the connection has been dropped.
+	pub async fn check_alive(&mut self) -> bool {
+		if !self.client.keep_alive.is_expired(self.client.last_received) {
+			return true
+		}
+
+		self.disconnect().await;
+		false
+	}
+
+	/// Closes the broken connection for reconnect, unless we closed it on purpose already
+	pub async fn disconnect(&mut self) {
+		self.client.close(true).await;
+	}
+}
@@ -44,6 +44,8 @@ impl Stream<'_> {
 		where
 			T: AsRef<[u8]>
 	{
+		self.touch();
+
 		match self.client.state {
 			// This state should never be reached:
 			// As soon as the connection is established, the websocket won't send anything until our hello is sent